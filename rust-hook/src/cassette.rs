@@ -0,0 +1,110 @@
+use anyhow::{bail, Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use crate::{state, HookEvent, NotchHook};
+
+// cassette 是一行一条记录的 JSONL：收到的事件和由它产生的通知按时间顺序交替出现
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    /// 记录时间（毫秒）
+    pub at: u64,
+    #[serde(flatten)]
+    pub record: Record,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "data", rename_all = "lowercase")]
+pub enum Record {
+    Event(Value),
+    Notification(Value),
+}
+
+pub fn append(path: &Path, record: Record) -> Result<()> {
+    let line = serde_json::to_string(&Entry { at: state::now_millis(), record })?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open cassette {}", path.display()))?;
+    writeln!(file, "{}", line)?;
+    Ok(())
+}
+
+pub fn load(path: &Path) -> Result<Vec<Entry>> {
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read cassette {}", path.display()))?;
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| serde_json::from_str(line).with_context(|| format!("Invalid cassette entry at line {}", index + 1)))
+        .collect()
+}
+
+/// 比较时只看通知本身的内容；metadata 含耗时、重要性等每次运行都会变的值
+fn comparable(notification: &Value) -> Value {
+    serde_json::json!({
+        "title": notification.get("title"),
+        "message": notification.get("message"),
+        "type": notification.get("type"),
+        "priority": notification.get("priority"),
+    })
+}
+
+impl NotchHook {
+    /// 按顺序把 cassette 中的事件重新交给 handler；check 时逐个事件比对产生的通知与录制时是否一致
+    pub(crate) fn replay_cassette(mut self, path: &Path, check: bool) -> Result<()> {
+        let entries = load(path)?;
+
+        // 每个事件和紧随其后录下的通知分为一组
+        let mut groups: Vec<(Value, Vec<Value>)> = Vec::new();
+        for entry in entries {
+            match entry.record {
+                Record::Event(event) => groups.push((event, Vec::new())),
+                Record::Notification(notification) => match groups.last_mut() {
+                    Some((_, notifications)) => notifications.push(notification),
+                    None => warn!("Ignoring notification recorded before any event"),
+                },
+            }
+        }
+
+        let mut mismatches = 0;
+        for (index, (event, expected)) in groups.iter().enumerate() {
+            let hook_event: HookEvent = serde_json::from_value(event.clone())
+                .with_context(|| format!("Invalid event #{} in cassette", index + 1))?;
+            eprintln!("[REPLAY] {}/{} {}", index + 1, groups.len(), hook_event.hook_event_name);
+
+            self.captured.borrow_mut().clear();
+            self.handle_event(&hook_event)?;
+
+            if !check {
+                continue;
+            }
+            let actual: Vec<Value> = self.captured.borrow().iter().map(comparable).collect();
+            let expected: Vec<Value> = expected.iter().map(comparable).collect();
+            if actual != expected {
+                mismatches += 1;
+                eprintln!("[REPLAY] Event #{} ({}) produced different notifications", index + 1, hook_event.hook_event_name);
+                eprintln!("[REPLAY]   expected: {}", Value::Array(expected));
+                eprintln!("[REPLAY]   actual:   {}", Value::Array(actual));
+            }
+        }
+
+        if check {
+            if mismatches > 0 {
+                bail!("{} of {} event(s) did not match the cassette", mismatches, groups.len());
+            }
+            eprintln!("[REPLAY] All {} event(s) match the cassette", groups.len());
+        }
+
+        if self.validate_only {
+            self.report_validation()?;
+        }
+        Ok(())
+    }
+}
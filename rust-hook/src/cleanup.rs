@@ -0,0 +1,66 @@
+use anyhow::{Context, Result};
+use log::warn;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use crate::{format_size, NotchHook};
+
+/// 可以清理的文件：diff 预览/结果（含截断前的完整版和 gzip）、diff 统计，以及会话状态
+fn is_prunable(name: &str) -> bool {
+    name.ends_with(".diff")
+        || name.ends_with(".diff.gz")
+        || name.ends_with(".stats.json")
+        || (name.starts_with("session-") && (name.ends_with(".json") || name.ends_with(".lock")))
+}
+
+impl NotchHook {
+    /// 遍历所有项目的 diff 目录，删除修改时间早于 older_than_days 天的文件
+    pub(crate) fn handle_cleanup_command(&self, older_than_days: u64, dry_run: bool) -> Result<()> {
+        let root = self.diff_dir.parent().context("Invalid diff directory")?;
+        let cutoff = SystemTime::now() - Duration::from_secs(older_than_days * 86400);
+
+        let mut stale: Vec<(PathBuf, u64)> = Vec::new();
+        for project in fs::read_dir(root)?.filter_map(|entry| entry.ok()) {
+            let Ok(files) = fs::read_dir(project.path()) else {
+                continue;
+            };
+            for file in files.filter_map(|entry| entry.ok()) {
+                if !is_prunable(&file.file_name().to_string_lossy()) {
+                    continue;
+                }
+                let Ok(metadata) = file.metadata() else {
+                    continue;
+                };
+                if metadata.is_file() && metadata.modified().is_ok_and(|modified| modified < cutoff) {
+                    stale.push((file.path(), metadata.len()));
+                }
+            }
+        }
+
+        let mut freed = 0;
+        let mut removed = 0;
+        for (path, size) in &stale {
+            if dry_run {
+                println!("{} ({})", path.display(), format_size(*size as usize));
+                freed += size;
+                removed += 1;
+                continue;
+            }
+            match fs::remove_file(path) {
+                Ok(()) => {
+                    freed += size;
+                    removed += 1;
+                }
+                Err(e) => warn!("Failed to remove {}: {}", path.display(), e),
+            }
+        }
+
+        if dry_run {
+            println!("Would delete {} file(s), freeing {}", removed, format_size(freed as usize));
+        } else {
+            println!("Deleted {} file(s), freed {}", removed, format_size(freed as usize));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::{i18n, state};
+
+// Read/Grep/Glob/LS 这类“噪音”工具的合并：窗口内连续的调用共用一条滚动更新的通知（相同 notification_id），
+// 窗口过期或中间出现其他工具时重新开始计数。每次 hook 调用都是独立进程，计数保存在 diff 目录的 noise.json
+
+pub const NOISE_TOOLS: &[&str] = &["Read", "Grep", "Glob", "LS"];
+
+pub fn is_noise_tool(tool_name: &str) -> bool {
+    NOISE_TOOLS.contains(&tool_name)
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NoiseGroup {
+    /// 这一组开始的时间（毫秒），同时用作滚动通知的 id
+    pub started_at: u64,
+    /// 最近一次噪音工具调用的时间
+    pub last_at: u64,
+    /// 按首次出现顺序记录的 工具名 -> 调用次数
+    pub counts: Vec<(String, usize)>,
+}
+
+impl NoiseGroup {
+    pub fn total(&self) -> usize {
+        self.counts.iter().map(|(_, count)| count).sum()
+    }
+
+    /// 如 "读取 5 个文件 · 搜索 2 次"
+    pub fn summary(&self) -> String {
+        self.counts
+            .iter()
+            .map(|(tool, count)| match tool.as_str() {
+                "Read" => i18n::tf("noise_read", &[count]),
+                "Grep" => i18n::tf("noise_grep", &[count]),
+                "Glob" => i18n::tf("noise_glob", &[count]),
+                _ => i18n::tf("noise_ls", &[count]),
+            })
+            .collect::<Vec<_>>()
+            .join(" · ")
+    }
+}
+
+/// 记录一次噪音工具调用，返回所在的组；距上次调用超过 window_ms 时开始新的一组
+pub fn record(path: &Path, tool_name: &str, window_ms: u64) -> Option<NoiseGroup> {
+    let now = state::now_millis();
+    state::update(path, |group: &mut NoiseGroup| {
+        if group.counts.is_empty() || now.saturating_sub(group.last_at) >= window_ms {
+            *group = NoiseGroup { started_at: now, ..Default::default() };
+        }
+        group.last_at = now;
+        match group.counts.iter_mut().find(|(tool, _)| tool == tool_name) {
+            Some((_, count)) => *count += 1,
+            None => group.counts.push((tool_name.to_string(), 1)),
+        }
+        group.clone()
+    })
+    .map_err(|e| warn!("Failed to update noise state: {}", e))
+    .ok()
+}
+
+/// 出现其他工具时结束当前这一组
+pub fn reset(path: &Path) {
+    if !path.exists() {
+        return;
+    }
+    if let Err(e) = state::update(path, |group: &mut NoiseGroup| *group = NoiseGroup::default()) {
+        warn!("Failed to reset noise state: {}", e);
+    }
+}
@@ -0,0 +1,438 @@
+use log::{debug, warn};
+use serde::Deserialize;
+
+use crate::confirm::Decision;
+use crate::prediff::PrediffFormat;
+use crate::privacy::UrlPrivacy;
+use crate::transport::{AckStyle, Transport};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 双阶段工具（Edit/Write/Bash 等）在哪个阶段发送通知
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyPhase {
+    Pre,
+    Post,
+    #[default]
+    Both,
+}
+
+impl NotifyPhase {
+    pub fn allows_pre(self) -> bool {
+        matches!(self, NotifyPhase::Pre | NotifyPhase::Both)
+    }
+
+    pub fn allows_post(self) -> bool {
+        matches!(self, NotifyPhase::Post | NotifyPhase::Both)
+    }
+}
+
+/// diff 文件格式：普通 unified diff，或可 `git apply` 的补丁
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffStyle {
+    #[default]
+    Unified,
+    Git,
+}
+
+/// unified diff 中修改行的粒度：line 为整行增删，word 把成对修改的行合并为一行并内联标注 [-旧-]{+新+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffGranularity {
+    #[default]
+    Line,
+    Word,
+}
+
+/// 项目级配置文件名，从项目根向上查找（不超出所在 git 仓库），与全局配置按字段合并
+const PROJECT_CONFIG_FILE: &str = ".notchhook.toml";
+
+/// 项目级配置允许设置的键，只限展示和降噪相关。投递通道（error_transport、socket_ack、离线队列）
+/// 和安全相关的键（enforce_dangerous、confirm_dangerous、危险命令列表）只认全局配置，
+/// 避免仓库里提交的配置把命令和路径转发到外部，或关掉危险操作的拦截
+const PROJECT_CONFIG_KEYS: &[&str] = &[
+    "notify_phase",
+    "fuzzy_dedup",
+    "passthrough_fields",
+    "show_token_estimate",
+    "summarize_created_files",
+    "watch_paths",
+    "diff_style",
+    "diff_granularity",
+    "max_diff_lines",
+    "url_privacy",
+    "url_in_metadata",
+    "command_history_size",
+    "always_confirm_completion",
+    "prediff_format",
+    "test_patterns",
+    "suppress_while_debugging",
+    "debug_min_priority",
+    "debug_timeout_secs",
+    "compress_diffs",
+    "suppress_env_setup",
+    "preview_diffs",
+    "stats_style",
+    "large_write_bytes",
+    "path_prefix",
+    "path_prefix_project_name",
+    "idle_notify_secs",
+    "defer_low_priority",
+    "defer_flush_idle_secs",
+    "diff_extensions",
+    "migration_commands",
+    "coalesce_noise_tools",
+    "coalesce_window_secs",
+    "tools",
+    "priority_overrides",
+    "bash_ignore_prefixes",
+];
+
+/// 内置的不通知命令前缀
+const BUILTIN_BASH_IGNORE_PREFIXES: &[&str] = &["echo", "ls", "pwd", "date", "curl localhost:9876"];
+/// 内置的危险命令前缀，提高通知优先级
+const BUILTIN_BASH_DANGER_PREFIXES: &[&str] = &["rm ", "mv "];
+
+/// 通知中增删行数的展示方式：short 为 "+3 -1"，verbose 为 "新增3行 删除1行"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsStyle {
+    #[default]
+    Short,
+    Verbose,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub notify_phase: NotifyPhase,
+    /// 去重前忽略消息中的数字和空白（可能过于激进，默认关闭）
+    pub fuzzy_dedup: bool,
+    /// 工具名 -> 需要原样复制到 metadata 的 tool_input 字段
+    pub passthrough_fields: HashMap<String, Vec<String>>,
+    /// 在通知中附带读取/修改的 token 估算
+    pub show_token_estimate: bool,
+    /// Stop 时列出本次会话新建的文件（默认关闭）
+    pub summarize_created_files: bool,
+    /// 重点关注的路径前缀（相对项目根或绝对路径），命中时提高通知重要性
+    pub watch_paths: Vec<String>,
+    pub diff_style: DiffStyle,
+    /// 只影响 unified 格式；git 补丁始终按行，保证可以 git apply
+    pub diff_granularity: DiffGranularity,
+    /// diff 文件超过该行数时截断并另存完整版（.full.diff），0 表示不限制
+    pub max_diff_lines: usize,
+    /// WebFetch/curl 等 URL 的展示方式: full | domain | host_path
+    pub url_privacy: UrlPrivacy,
+    /// 即使展示被裁剪，也把完整 URL 放进 metadata
+    pub url_in_metadata: bool,
+    /// 会话中保留的最近 Bash 命令条数，0 表示不记录
+    pub command_history_size: usize,
+    /// PostToolUse 没有输出可展示时（如 Bash 无输出）仍发送完成通知
+    pub always_confirm_completion: bool,
+    /// 扩展名 -> 生成 diff 前的内容规范化方式，如 { json = "json" }
+    pub prediff_format: HashMap<String, PrediffFormat>,
+    /// 测试文件识别规则：以 / 结尾匹配目录名，其余按文件名通配（支持 *）
+    pub test_patterns: Vec<String>,
+    /// JetBrains 调试期间只显示重要通知（默认关闭）
+    pub suppress_while_debugging: bool,
+    /// 调试期间的最低通知优先级
+    pub debug_min_priority: u8,
+    /// 调试状态的超时时间，超时后自动恢复（防止漏掉停止事件）
+    pub debug_timeout_secs: u64,
+    /// 以 gzip 保存 diff 文件（.diff.gz），通知 metadata 中带 compressed=true
+    pub compress_diffs: bool,
+    /// 完全不通知 source/export/nvm use 等环境配置命令（默认只降为最低优先级）
+    pub suppress_env_setup: bool,
+    /// 错误通知（type = error 或 priority >= 3）额外投递的通道
+    pub error_transport: Option<Transport>,
+    /// NotchNoti socket 的回执方式: ignore | expect-json | expect-close
+    pub socket_ack: AckStyle,
+    /// error_transport 为 socket 时的回执方式（webhook 以 HTTP 状态码为准）
+    pub error_transport_ack: AckStyle,
+    /// Pre 阶段生成 diff 文件；关闭时 Edit 只按 old_string/new_string 估算行数，不读取原文件
+    pub preview_diffs: bool,
+    pub stats_style: StatsStyle,
+    /// Write 内容超过该字节数（或为二进制）时只提示文件大小，不生成 diff
+    pub large_write_bytes: usize,
+    /// NotchNoti 未运行时把通知排队，下次连接成功时补发（默认关闭）
+    pub queue_when_offline: bool,
+    /// 自动补发时丢弃超过该时长（秒）的排队通知
+    pub queue_max_age_secs: u64,
+    /// monorepo 中关注的子项目目录（相对项目根），显示路径时去掉该前缀；环境变量 NOTCH_PATH_PREFIX 优先
+    pub path_prefix: Option<String>,
+    /// 设置 path_prefix 时用子项目目录名作为通知中的项目名
+    pub path_prefix_project_name: bool,
+    /// 危险操作在 PreToolUse 时等待用户在刘海中允许/拒绝
+    pub confirm_dangerous: bool,
+    /// 等待确认回复（危险操作确认和确认通知的选择）的最长时间（秒）
+    pub confirm_timeout_secs: u64,
+    /// 超时或 App 未运行时的默认决定
+    pub confirm_default: Decision,
+    /// 危险操作在 PreToolUse 时直接向 Claude Code 输出拒绝决定并只提示一次（优先于 confirm_dangerous）
+    pub enforce_dangerous: bool,
+    /// Claude 等待用户响应超过该秒数时，用户回来后补发一条提示；默认 0，只在 metadata 中记录等待时长
+    pub idle_notify_secs: u64,
+    /// 优先级低于 2 的通知先攒着，等 Stop 或用户空闲后再一起发送，避免打断
+    pub defer_low_priority: bool,
+    /// 等待用户响应超过该秒数后回来时补发攒着的通知
+    pub defer_flush_idle_secs: u64,
+    /// 只为这些扩展名生成 diff（如 ["rs", "py"]），为空表示全部
+    pub diff_extensions: Vec<String>,
+    /// 额外识别为数据库迁移的命令前缀（如 "./scripts/migrate"），与内置列表合并
+    pub migration_commands: Vec<String>,
+    /// 把窗口内连续的 Read/Grep/Glob/LS 合并为一条滚动更新的通知（如 "读取 5 个文件"）
+    pub coalesce_noise_tools: bool,
+    /// 合并窗口：距上次噪音工具调用超过该秒数后重新计数
+    pub coalesce_window_secs: u64,
+    /// 按工具名覆盖通知规则，如 `[tools.Read] enabled = false`
+    pub tools: HashMap<String, ToolRule>,
+    /// 工具名或通知类型 -> 强制优先级（限制在 0-3），如 `error = 3`、`success = 0`；工具名优先
+    pub priority_overrides: HashMap<String, i64>,
+    /// 额外不通知的 Bash 命令前缀（内置的 echo/ls/pwd 等之外）
+    pub bash_ignore_prefixes: Vec<String>,
+    /// 额外视为危险、提高通知优先级的 Bash 命令前缀（内置的 rm/mv 之外）
+    pub bash_danger_prefixes: Vec<String>,
+    /// 为 true 时上面两个列表替换内置列表而不是与之合并
+    #[serde(alias = "replace_defaults")]
+    pub bash_replace_defaults: bool,
+}
+
+/// 单个工具的通知规则，未设置的字段保持内置行为
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ToolRule {
+    /// false 时不发送该工具的通知（critical 通知除外）
+    pub enabled: Option<bool>,
+    pub priority: Option<u8>,
+    /// 替换标题中的图标
+    pub icon: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            notify_phase: NotifyPhase::default(),
+            fuzzy_dedup: false,
+            passthrough_fields: HashMap::new(),
+            show_token_estimate: false,
+            summarize_created_files: false,
+            watch_paths: Vec::new(),
+            diff_style: DiffStyle::default(),
+            diff_granularity: DiffGranularity::default(),
+            max_diff_lines: 500,
+            url_privacy: UrlPrivacy::default(),
+            url_in_metadata: false,
+            command_history_size: 10,
+            always_confirm_completion: false,
+            prediff_format: HashMap::new(),
+            test_patterns: [
+                "*_test.rs", "*_test.go", "*_test.py", "test_*.py",
+                "*.spec.ts", "*.test.ts", "*.spec.tsx", "*.test.tsx",
+                "*.spec.js", "*.test.js", "*Tests.swift", "*Test.java",
+                "tests/", "test/", "__tests__/", "spec/",
+            ]
+            .map(String::from)
+            .to_vec(),
+            suppress_while_debugging: false,
+            debug_min_priority: 2,
+            debug_timeout_secs: 30 * 60,
+            compress_diffs: false,
+            suppress_env_setup: false,
+            error_transport: None,
+            socket_ack: AckStyle::default(),
+            error_transport_ack: AckStyle::default(),
+            preview_diffs: true,
+            stats_style: StatsStyle::default(),
+            large_write_bytes: 1024 * 1024,
+            queue_when_offline: false,
+            queue_max_age_secs: 60 * 60,
+            path_prefix: None,
+            path_prefix_project_name: true,
+            confirm_dangerous: false,
+            confirm_timeout_secs: 30,
+            confirm_default: Decision::default(),
+            enforce_dangerous: false,
+            idle_notify_secs: 0,
+            defer_low_priority: false,
+            defer_flush_idle_secs: 60,
+            diff_extensions: Vec::new(),
+            migration_commands: Vec::new(),
+            coalesce_noise_tools: false,
+            coalesce_window_secs: 10,
+            tools: HashMap::new(),
+            priority_overrides: HashMap::new(),
+            bash_ignore_prefixes: Vec::new(),
+            bash_danger_prefixes: Vec::new(),
+            bash_replace_defaults: false,
+        }
+    }
+}
+
+impl Config {
+    pub fn is_ignored_bash_command(&self, command: &str) -> bool {
+        self.matches_bash_prefix(command, &self.bash_ignore_prefixes, BUILTIN_BASH_IGNORE_PREFIXES)
+    }
+
+    pub fn is_dangerous_bash_command(&self, command: &str) -> bool {
+        self.matches_bash_prefix(command, &self.bash_danger_prefixes, BUILTIN_BASH_DANGER_PREFIXES)
+    }
+
+    fn matches_bash_prefix(&self, command: &str, configured: &[String], builtin: &[&str]) -> bool {
+        let builtin = if self.bash_replace_defaults { &[][..] } else { builtin };
+        configured
+            .iter()
+            .map(String::as_str)
+            .chain(builtin.iter().copied())
+            .any(|prefix| command.starts_with(prefix))
+    }
+
+    /// 配置文件路径: ~/.config/notch-hook/config.toml
+    pub fn path() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".config").join("notch-hook").join("config.toml"))
+    }
+
+    /// 从 start 开始逐级向上查找项目级配置 .notchhook.toml，最多到所在 git 仓库的根目录；
+    /// 不在 git 仓库中时只看 start 本身，不会读到上级目录里别人放的配置
+    pub fn find_project_config(start: &Path) -> Option<PathBuf> {
+        let depth = start.ancestors().position(|dir| dir.join(".git").exists()).unwrap_or(0);
+        start
+            .ancestors()
+            .take(depth + 1)
+            .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+            .find(|path| path.is_file())
+    }
+
+    /// 加载全局配置，再按字段合并项目级配置（项目优先）。全局配置有误时使用默认值，项目配置有误时忽略它
+    pub fn load(project_path: &Path) -> Self {
+        Self::load_from(Self::path().as_deref(), project_path)
+    }
+
+    fn load_from(global_path: Option<&Path>, project_path: &Path) -> Self {
+        let global = global_path.and_then(read_table).unwrap_or_default();
+
+        if let Some(path) = Self::find_project_config(project_path) {
+            if let Some(mut project) = read_table(&path) {
+                project.retain(|key, _| {
+                    let allowed = PROJECT_CONFIG_KEYS.contains(&key);
+                    if !allowed {
+                        warn!("Ignoring {} in project config {}: only the global config can set it", key, path.display());
+                    }
+                    allowed
+                });
+                let mut merged = global.clone();
+                merge_tables(&mut merged, project);
+                match merged.try_into() {
+                    Ok(config) => return config,
+                    Err(e) => warn!("Ignoring project config {}: {}", path.display(), e),
+                }
+            }
+        }
+
+        global.try_into().unwrap_or_else(|e| {
+            warn!("Invalid global config: {}, using defaults", e);
+            Self::default()
+        })
+    }
+}
+
+/// 读取并解析一个 TOML 配置文件，不存在时返回 None，解析失败时提示后返回 None
+fn read_table(path: &Path) -> Option<toml::Table> {
+    let content = fs::read_to_string(path).ok()?;
+    match toml::from_str(&content) {
+        Ok(table) => {
+            debug!("Loaded config from: {}", path.display());
+            Some(table)
+        }
+        Err(e) => {
+            warn!("Failed to parse {}: {}, ignoring it", path.display(), e);
+            None
+        }
+    }
+}
+
+/// 字段级合并：表逐层合并，其余值（包括数组）由 overlay 整体覆盖
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(overlay_table)) => merge_tables(base_table, overlay_table),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opt_in_features_are_off_by_default() {
+        let config = Config::default();
+        assert!(!config.queue_when_offline);
+        assert!(!config.summarize_created_files);
+        assert!(!config.suppress_while_debugging);
+        assert_eq!(config.idle_notify_secs, 0);
+    }
+
+    #[test]
+    fn project_config_cannot_set_transport_or_safety_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let global = dir.path().join("config.toml");
+        fs::write(&global, "enforce_dangerous = true\n").unwrap();
+        let project = dir.path().join("repo");
+        fs::create_dir_all(project.join(".git")).unwrap();
+        fs::write(
+            project.join(PROJECT_CONFIG_FILE),
+            r#"
+enforce_dangerous = false
+confirm_dangerous = false
+socket_ack = "expect-close"
+error_transport = { webhook = "https://attacker.example/hook" }
+stats_style = "verbose"
+
+[tools.Bash]
+enabled = false
+"#,
+        )
+        .unwrap();
+
+        let config = Config::load_from(Some(&global), &project);
+
+        assert!(config.enforce_dangerous);
+        assert!(config.error_transport.is_none());
+        assert_eq!(config.socket_ack, AckStyle::Ignore);
+        // 展示相关的键照常生效
+        assert_eq!(config.stats_style, StatsStyle::Verbose);
+        assert_eq!(config.tools["Bash"].enabled, Some(false));
+    }
+
+    #[test]
+    fn project_config_search_stops_at_repository_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        let project = repo.join("crates").join("app");
+        fs::create_dir_all(&project).unwrap();
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        // 仓库外的上级目录
+        fs::write(dir.path().join(PROJECT_CONFIG_FILE), "fuzzy_dedup = true\n").unwrap();
+
+        assert_eq!(Config::find_project_config(&project), None);
+
+        fs::write(repo.join(PROJECT_CONFIG_FILE), "fuzzy_dedup = true\n").unwrap();
+        assert_eq!(Config::find_project_config(&project), Some(repo.join(PROJECT_CONFIG_FILE)));
+    }
+
+    #[test]
+    fn project_config_outside_a_repository_is_only_read_from_the_project_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        fs::create_dir_all(&project).unwrap();
+        fs::write(dir.path().join(PROJECT_CONFIG_FILE), "fuzzy_dedup = true\n").unwrap();
+
+        assert_eq!(Config::find_project_config(&project), None);
+    }
+}
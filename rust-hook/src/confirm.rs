@@ -0,0 +1,313 @@
+use anyhow::Result;
+use log::{debug, info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::transport::{NoReply, NotchSocket};
+use crate::{describe_command, i18n, schema, HookEvent, NotchHook, NotificationType};
+
+/// 用户对需要确认的操作的决定
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Decision {
+    #[default]
+    Allow,
+    Deny,
+}
+
+impl Decision {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Decision::Allow => "allow",
+            Decision::Deny => "deny",
+        }
+    }
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// 解析 App 的回复：`{"decision": "deny"}`、`{"action": "allow"}`、`{"choice": "allow"}` 或纯文本 allow/deny
+pub fn parse_decision(response: &str) -> Option<Decision> {
+    let response = response.trim();
+    let word = match serde_json::from_str::<Value>(response) {
+        Ok(value) => ["decision", "action", "choice"]
+            .iter()
+            .find_map(|key| value.get(key).and_then(|v| v.as_str()))?
+            .to_lowercase(),
+        Err(_) => response.to_lowercase(),
+    };
+
+    match word.as_str() {
+        "allow" | "approve" | "yes" => Some(Decision::Allow),
+        "deny" | "reject" | "block" | "no" => Some(Decision::Deny),
+        _ => None,
+    }
+}
+
+/// Claude Code 的 PreToolUse 拒绝输出
+pub fn deny_output(reason: &str) -> Value {
+    json!({
+        "hookSpecificOutput": {
+            "hookEventName": "PreToolUse",
+            "permissionDecision": "deny",
+            "permissionDecisionReason": reason,
+        }
+    })
+}
+
+/// 把用户的选择翻译为对应 hook 事件的 stdout 输出；UserPromptSubmit 等事件只能阻止，允许时不输出
+pub fn hook_output(event_name: &str, decision: Decision, reason: &str) -> Option<Value> {
+    match (event_name, decision) {
+        ("PreToolUse" | "pre_tool_use", Decision::Deny) => Some(deny_output(reason)),
+        ("PreToolUse" | "pre_tool_use", Decision::Allow) => Some(json!({
+            "hookSpecificOutput": {
+                "hookEventName": "PreToolUse",
+                "permissionDecision": "allow",
+                "permissionDecisionReason": reason,
+            }
+        })),
+        (_, Decision::Deny) => Some(block_output(reason)),
+        (_, Decision::Allow) => None,
+    }
+}
+
+/// UserPromptSubmit 等事件的 block 决定
+fn block_output(reason: &str) -> Value {
+    json!({
+        "decision": "block",
+        "reason": reason,
+    })
+}
+
+/// 危险操作确认的 stdout 输出：拒绝时阻止工具执行，允许时不输出
+fn dangerous_output(decision: Decision, command: &str) -> Option<Value> {
+    (decision == Decision::Deny).then(|| deny_output(&i18n::tf("denied_in_notch", &[&command])))
+}
+
+/// 等待确认时两次检查之间的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// App 也可以把决定写到 responses/<request_id>，用于不方便直接回复 socket 的情况
+fn read_response_file(path: &Path) -> Option<Decision> {
+    let decision = parse_decision(&fs::read_to_string(path).ok()?)?;
+    fs::remove_file(path).ok();
+    Some(decision)
+}
+
+/// 发送确认通知，在 deadline 前等待用户的决定：socket 回复和回复文件轮流检查，哪个先到用哪个。
+/// 连不上 socket（App 未运行）时直接返回错误，不再等待；到期仍没有可识别的决定时返回 None
+fn await_decision(socket: &NotchSocket, payload: &str, response_file: &Path, deadline: Instant) -> Result<Option<Decision>> {
+    let mut reply = socket.start_request(payload)?;
+
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        match reply.poll(remaining.min(POLL_INTERVAL)) {
+            Ok(Some(response)) => match parse_decision(&response) {
+                Some(decision) => return Ok(Some(decision)),
+                None => warn!("Unrecognized confirmation reply: {}", response.trim()),
+            },
+            Ok(None) => {}
+            Err(e) => warn!("Confirmation reply over socket failed: {}", e),
+        }
+        if let Some(decision) = read_response_file(response_file) {
+            return Ok(Some(decision));
+        }
+    }
+    Ok(None)
+}
+
+impl NotchHook {
+    /// 发送带 actions 的确认通知后保持连接，等待 App 回复用户的选择（如 `{"choice":"allow"}`）。
+    /// 超时视为没有决定，让 Claude 照常继续；连接失败时返回错误，由调用方降级或排队
+    pub(crate) fn send_and_await_choice(&self, payload: &str) -> Result<()> {
+        let timeout = Duration::from_secs(self.config.confirm_timeout_secs);
+        match self.socket.request(payload, timeout) {
+            Ok(reply) => match parse_decision(&reply) {
+                Some(decision) => {
+                    debug!("User chose {:?} in the notch", decision);
+                    self.reply_decision.set(Some(decision));
+                }
+                None => warn!("Unrecognized confirmation reply: {}", reply.trim()),
+            },
+            Err(e) if e.is::<NoReply>() => info!("Confirmation timed out ({}), leaving the decision to Claude Code", e),
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+
+    /// 发送确认通知并等待用户决定，拒绝时向 stdout 输出 deny JSON 阻止工具执行
+    pub(crate) fn confirm_dangerous_operation(&self, tool_name: &str, event: &HookEvent) -> Result<()> {
+        let request_id = uuid::Uuid::new_v4().to_string();
+        let command = describe_command(tool_name, event.tool_input.as_ref());
+
+        let mut metadata = HashMap::new();
+        metadata.insert("request_id".to_string(), request_id.clone());
+        metadata.insert("actions".to_string(), "allow,deny".to_string());
+        metadata.insert("tool_name".to_string(), tool_name.to_string());
+        metadata.insert("dangerous".to_string(), "true".to_string());
+
+        let notification = self.build_notification(
+            format!("[{}] 🛡️ {}", self.project_name, i18n::t("confirm_required")),
+            command.clone(),
+            NotificationType::Confirmation,
+            3,
+            metadata,
+        );
+        let payload = serde_json::to_string(&notification)?;
+
+        if self.validate_only {
+            let errors = schema::validate_notification(&serde_json::from_str(&payload)?);
+            self.validation_results.borrow_mut().push(errors);
+            return Ok(());
+        }
+        if self.dry_run {
+            return self.print_dry_run(&notification);
+        }
+
+        // socket 回复和回复文件共用一个期限；App 未运行时不等待，直接使用默认决定
+        let deadline = Instant::now() + Duration::from_secs(self.config.confirm_timeout_secs);
+        let response_file = self.diff_dir.join("responses").join(&request_id);
+        let decision = match await_decision(&self.socket, &payload, &response_file, deadline) {
+            Ok(Some(decision)) => decision,
+            Ok(None) => {
+                info!("Confirmation timed out, using default: {:?}", self.config.confirm_default);
+                self.config.confirm_default
+            }
+            Err(e) => {
+                warn!("NotchNoti unavailable ({:#}), using default: {:?}", e, self.config.confirm_default);
+                self.config.confirm_default
+            }
+        };
+
+        debug!("Confirmation decision for {}: {:?}", command, decision);
+        if let Some(output) = dangerous_output(decision, &command) {
+            println!("{}", output);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixListener;
+
+    #[test]
+    fn parses_json_keys_and_plain_text() {
+        assert_eq!(parse_decision(r#"{"decision":"deny"}"#), Some(Decision::Deny));
+        assert_eq!(parse_decision(r#"{"action":"Allow"}"#), Some(Decision::Allow));
+        assert_eq!(parse_decision(r#"{"choice":"reject"}"#), Some(Decision::Deny));
+        assert_eq!(parse_decision(" yes \n"), Some(Decision::Allow));
+        assert_eq!(parse_decision("block"), Some(Decision::Deny));
+        assert_eq!(parse_decision(r#"{"ok":true}"#), None);
+        assert_eq!(parse_decision("maybe"), None);
+    }
+
+    #[test]
+    fn decision_displays_as_lowercase_word() {
+        assert_eq!(Decision::Allow.to_string(), "allow");
+        assert_eq!(Decision::Deny.as_str(), "deny");
+        assert_eq!(parse_decision(Decision::Deny.as_str()), Some(Decision::Deny));
+    }
+
+    #[test]
+    fn hook_output_matches_event() {
+        let deny = hook_output("PreToolUse", Decision::Deny, "no").unwrap();
+        assert_eq!(deny["hookSpecificOutput"]["permissionDecision"], "deny");
+        assert_eq!(deny["hookSpecificOutput"]["permissionDecisionReason"], "no");
+
+        let allow = hook_output("PreToolUse", Decision::Allow, "ok").unwrap();
+        assert_eq!(allow["hookSpecificOutput"]["permissionDecision"], "allow");
+
+        assert_eq!(hook_output("UserPromptSubmit", Decision::Deny, "no").unwrap()["decision"], "block");
+        assert!(hook_output("UserPromptSubmit", Decision::Allow, "ok").is_none());
+    }
+
+    /// 模拟 App：读取确认通知后回复 reply（为空时不回复），然后保持连接直到对方断开
+    fn mock_app(reply: &'static str) -> (tempfile::TempDir, NotchSocket, std::thread::JoinHandle<()>) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notch.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let app = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            reader.read_line(&mut String::new()).unwrap();
+            stream.write_all(reply.as_bytes()).unwrap();
+            reader.read_line(&mut String::new()).ok();
+        });
+        (dir, NotchSocket::new(path), app)
+    }
+
+    fn deadline_in(secs: u64) -> Instant {
+        Instant::now() + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn mock_app_deny_emits_deny_json() {
+        let (dir, socket, app) = mock_app("{\"choice\":\"deny\"}\n");
+        let decision = await_decision(&socket, r#"{"type":"confirmation"}"#, &dir.path().join("response"), deadline_in(2)).unwrap();
+        drop(socket);
+        app.join().unwrap();
+
+        assert_eq!(decision, Some(Decision::Deny));
+        let output = dangerous_output(Decision::Deny, "rm -rf build").unwrap();
+        assert_eq!(output["hookSpecificOutput"]["hookEventName"], "PreToolUse");
+        assert_eq!(output["hookSpecificOutput"]["permissionDecision"], "deny");
+    }
+
+    #[test]
+    fn mock_app_allow_emits_nothing() {
+        let (dir, socket, app) = mock_app("{\"choice\":\"allow\"}\n");
+        let decision = await_decision(&socket, r#"{"type":"confirmation"}"#, &dir.path().join("response"), deadline_in(2)).unwrap();
+        drop(socket);
+        app.join().unwrap();
+
+        assert_eq!(decision, Some(Decision::Allow));
+        assert!(dangerous_output(Decision::Allow, "rm -rf build").is_none());
+    }
+
+    #[test]
+    fn missing_app_fails_without_waiting() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = NotchSocket::new(dir.path().join("missing.sock"));
+
+        let started = Instant::now();
+        let result = await_decision(&socket, r#"{"type":"confirmation"}"#, &dir.path().join("response"), deadline_in(30));
+
+        assert!(result.is_err());
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn response_file_is_checked_while_waiting_on_the_socket() {
+        let (dir, socket, app) = mock_app("");
+        let response_file = dir.path().join("response");
+        let writer = {
+            let response_file = response_file.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(200));
+                fs::write(response_file, "deny").unwrap();
+            })
+        };
+
+        let started = Instant::now();
+        let decision = await_decision(&socket, r#"{"type":"confirmation"}"#, &response_file, deadline_in(30)).unwrap();
+        writer.join().unwrap();
+        drop(socket);
+        app.join().unwrap();
+
+        assert_eq!(decision, Some(Decision::Deny));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(!response_file.exists());
+    }
+}
@@ -0,0 +1,99 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::state;
+
+/// 相同通知在此窗口内只发送一次
+pub const DEDUP_WINDOW_SECS: u64 = 3;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DedupState {
+    /// 通知指纹 -> 最近一次发送时间（毫秒）
+    recent: HashMap<String, u64>,
+}
+
+/// 模糊去重：去掉数字和空白，让只有计数/时间不同的消息视为相同
+fn normalize(message: &str) -> String {
+    message
+        .chars()
+        .filter(|c| !c.is_ascii_digit() && !c.is_whitespace())
+        .collect()
+}
+
+pub fn fingerprint(title: &str, message: &str, notification_type: &str, fuzzy: bool) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(title.as_bytes());
+    hasher.update([0]);
+    if fuzzy {
+        hasher.update(normalize(message).as_bytes());
+    } else {
+        hasher.update(message.as_bytes());
+    }
+    hasher.update([0]);
+    hasher.update(notification_type.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// 检查窗口内是否已发送过相同指纹，并记录本次发送。返回 true 表示重复。
+pub fn check_and_record(state_path: &Path, fingerprint: &str) -> bool {
+    let now = state::now_millis();
+    let window_ms = DEDUP_WINDOW_SECS * 1000;
+
+    state::update(state_path, |dedup: &mut DedupState| {
+        dedup.recent.retain(|_, sent_at| now.saturating_sub(*sent_at) < window_ms);
+
+        if dedup.recent.contains_key(fingerprint) {
+            true
+        } else {
+            dedup.recent.insert(fingerprint.to_string(), now);
+            false
+        }
+    })
+    .unwrap_or_else(|e| {
+        warn!("Failed to update dedup state: {}", e);
+        false
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digit_differing_messages_dedup_only_when_fuzzy() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let strict = dir.path().join("strict.json");
+        assert!(!check_and_record(&strict, &fingerprint("读取", "读取了 3 个文件", "info", false)));
+        assert!(!check_and_record(&strict, &fingerprint("读取", "读取了 4 个文件", "info", false)));
+
+        let fuzzy = dir.path().join("fuzzy.json");
+        assert!(!check_and_record(&fuzzy, &fingerprint("读取", "读取了 3 个文件", "info", true)));
+        assert!(check_and_record(&fuzzy, &fingerprint("读取", "读取了 4 个文件", "info", true)));
+    }
+
+    #[test]
+    fn exact_repeat_is_a_duplicate() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dedup.json");
+        let print = fingerprint("t", "m", "info", false);
+        assert!(!check_and_record(&path, &print));
+        assert!(check_and_record(&path, &print));
+    }
+
+    #[test]
+    fn repeat_after_the_window_is_sent_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dedup.json");
+        let print = fingerprint("t", "m", "info", false);
+        // 上一次发送刚好在窗口之外
+        let sent_at = state::now_millis() - DEDUP_WINDOW_SECS * 1000 - 1;
+        std::fs::write(&path, serde_json::json!({"recent": {&print: sent_at}}).to_string()).unwrap();
+
+        assert!(!check_and_record(&path, &print));
+        assert!(check_and_record(&path, &print));
+    }
+}
@@ -0,0 +1,141 @@
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::fs;
+use std::time::Duration;
+
+use crate::{HookEvent, NotchHook};
+
+const DEMO_SOURCE: &str = "pub fn greet(name: &str) -> String {\n    format!(\"Hello, {}\", name)\n}\n";
+
+impl NotchHook {
+    /// 在临时目录里模拟一次完整的 Claude 会话，事件走真实的 handler 发送到刘海
+    pub(crate) fn run_demo(&mut self, delay: Duration) -> Result<()> {
+        let demo_dir = std::env::temp_dir().join(format!("notch-hook-demo-{}", std::process::id()));
+        fs::create_dir_all(demo_dir.join("src"))?;
+        fs::write(demo_dir.join("src/lib.rs"), DEMO_SOURCE)?;
+
+        self.project_path = demo_dir.clone();
+        self.project_name = "demo".to_string();
+        self.diff_dir = self.diff_dir.with_file_name("demo");
+        fs::create_dir_all(&self.diff_dir)?;
+
+        let events = demo_events(&format!("demo-{}", std::process::id()));
+        let total = events.len();
+
+        for (index, event) in events.into_iter().enumerate() {
+            let event: HookEvent = serde_json::from_value(event)?;
+            eprintln!("[DEMO] {}/{} {}", index + 1, total, event.hook_event_name);
+
+            // PostToolUse 前先把修改真正写到磁盘，模拟工具已执行
+            if event.hook_event_name == "PostToolUse" && event.error.is_none() {
+                self.simulate_tool(&event)?;
+            }
+
+            self.handle_event(&event)?;
+
+            if index + 1 < total {
+                std::thread::sleep(delay);
+            }
+        }
+
+        fs::remove_dir_all(&demo_dir).ok();
+
+        if self.validate_only {
+            self.report_validation()?;
+        }
+
+        Ok(())
+    }
+
+    fn simulate_tool(&self, event: &HookEvent) -> Result<()> {
+        let (Some(tool_name), Some(tool_input)) = (event.tool_name.as_deref(), event.tool_input.as_ref()) else {
+            return Ok(());
+        };
+        let Some(file_path) = self.extract_file_path(tool_name, tool_input)? else {
+            return Ok(());
+        };
+
+        match self.extract_text_content(tool_name, tool_input)? {
+            (Some(old), Some(new)) => {
+                let content = fs::read_to_string(&file_path)?;
+                fs::write(&file_path, content.replacen(&old, &new, 1))?;
+            }
+            (None, Some(new)) => {
+                if let Some(parent) = file_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(&file_path, new)?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// 覆盖会话开始、带 diff 的修改、新建文件、命令、失败、任务进度和会话结束
+fn demo_events(session_id: &str) -> Vec<Value> {
+    let edit_input = json!({
+        "file_path": "src/lib.rs",
+        "old_string": "    format!(\"Hello, {}\", name)",
+        "new_string": "    format!(\"Hello, {}!\", name.trim())"
+    });
+    let write_input = json!({
+        "file_path": "src/farewell.rs",
+        "content": "pub fn farewell(name: &str) -> String {\n    format!(\"Bye, {}\", name)\n}\n"
+    });
+    let bash_input = json!({ "command": "cargo test --all" });
+
+    vec![
+        json!({ "hook_event_name": "SessionStart", "session_id": session_id }),
+        json!({ "hook_event_name": "PreToolUse", "session_id": session_id, "tool_name": "Edit", "tool_input": edit_input }),
+        json!({ "hook_event_name": "PostToolUse", "session_id": session_id, "tool_name": "Edit", "tool_input": edit_input }),
+        json!({ "hook_event_name": "PreToolUse", "session_id": session_id, "tool_name": "Write", "tool_input": write_input }),
+        json!({ "hook_event_name": "PostToolUse", "session_id": session_id, "tool_name": "Write", "tool_input": write_input }),
+        json!({ "hook_event_name": "PreToolUse", "session_id": session_id, "tool_name": "Bash", "tool_input": bash_input }),
+        json!({
+            "hook_event_name": "PostToolUse",
+            "session_id": session_id,
+            "tool_name": "Bash",
+            "tool_input": bash_input,
+            "error": "test result: FAILED. 1 passed; 1 failed"
+        }),
+        json!({
+            "hook_event_name": "PreToolUse",
+            "session_id": session_id,
+            "tool_name": "TodoWrite",
+            "tool_input": { "todos": [
+                { "content": "Trim names", "status": "completed" },
+                { "content": "Add farewell", "status": "completed" },
+                { "content": "Fix failing test", "status": "in_progress" }
+            ] }
+        }),
+        json!({ "hook_event_name": "Notification", "session_id": session_id }),
+        json!({ "hook_event_name": "Stop", "session_id": session_id }),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn demo_covers_the_main_notification_types() {
+        let dir = tempfile::tempdir().unwrap();
+        let diff_dir = dir.path().join("diffs").join("project");
+        fs::create_dir_all(&diff_dir).unwrap();
+        let mut hook = NotchHook::with_state(dir.path().to_path_buf(), "project".to_string(), diff_dir, Config::default(), dir.path().join("notch.sock"), false);
+        hook.validate_only = true;
+
+        hook.run_demo(Duration::ZERO).unwrap();
+
+        let types: Vec<String> = hook.captured.borrow().iter().map(|notification| notification["type"].as_str().unwrap().to_string()).collect();
+        // 每个事件一条通知：会话开始、修改/新建文件的前后两条、命令和失败、任务进度、等待响应、会话结束
+        assert_eq!(
+            types,
+            ["ai", "tool_use", "success", "tool_use", "success", "tool_use", "error", "reminder", "reminder", "celebration"]
+        );
+        assert!(hook.validation_results.borrow().iter().all(|errors| errors.is_empty()));
+    }
+}
@@ -0,0 +1,212 @@
+use similar::{ChangeTag, TextDiff};
+
+use crate::i18n;
+
+/// 按扩展名识别的语言，决定用哪套规则描述修改
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Rust,
+    Python,
+    JavaScript,
+}
+
+impl Language {
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext {
+            "rs" => Some(Language::Rust),
+            "py" => Some(Language::Python),
+            "js" | "jsx" | "ts" | "tsx" | "mjs" | "cjs" => Some(Language::JavaScript),
+            _ => None,
+        }
+    }
+
+    fn comment_prefix(self) -> &'static str {
+        match self {
+            Language::Python => "#",
+            Language::Rust | Language::JavaScript => "//",
+        }
+    }
+}
+
+/// 一行代码声明的函数名
+fn function_name(line: &str, language: Language) -> Option<String> {
+    let line = line.trim_start();
+    let rest = match language {
+        Language::Rust => {
+            let line = ["pub(crate) ", "pub ", "async ", "const ", "unsafe "]
+                .iter()
+                .fold(line, |line, modifier| line.strip_prefix(modifier).unwrap_or(line));
+            line.strip_prefix("fn ")?
+        }
+        Language::Python => line.strip_prefix("async ").unwrap_or(line).strip_prefix("def ")?,
+        Language::JavaScript => {
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let line = line.strip_prefix("async ").unwrap_or(line);
+            match line.strip_prefix("function ") {
+                Some(rest) => rest,
+                // const handle = (...) => / const handle = async (...) =>
+                None => {
+                    let rest = line.strip_prefix("const ").or_else(|| line.strip_prefix("let "))?;
+                    if !rest.contains("=>") {
+                        return None;
+                    }
+                    rest
+                }
+            }
+        }
+    };
+    identifier(rest)
+}
+
+/// 一行代码声明的类型名（struct/enum/trait/class）
+fn type_name(line: &str, language: Language) -> Option<String> {
+    let line = line.trim_start();
+    let line = line.strip_prefix("pub(crate) ").or_else(|| line.strip_prefix("pub ")).or_else(|| line.strip_prefix("export ")).unwrap_or(line);
+    let keywords: &[&str] = match language {
+        Language::Rust => &["struct ", "enum ", "trait "],
+        Language::Python | Language::JavaScript => &["class "],
+    };
+    keywords.iter().find_map(|keyword| line.strip_prefix(keyword)).and_then(identifier)
+}
+
+fn is_import(line: &str, language: Language) -> bool {
+    let line = line.trim_start();
+    match language {
+        Language::Rust => line.starts_with("use ") || line.starts_with("pub use ") || line.starts_with("extern crate "),
+        Language::Python => line.starts_with("import ") || (line.starts_with("from ") && line.contains(" import ")),
+        Language::JavaScript => line.starts_with("import ") || line.contains("require("),
+    }
+}
+
+fn identifier(text: &str) -> Option<String> {
+    let name: String = text.trim_start().chars().take_while(|c| c.is_alphanumeric() || *c == '_').collect();
+    (!name.is_empty()).then_some(name)
+}
+
+/// 去掉字符串字面量内容，用于判断是否只改了字符串
+fn strip_strings(line: &str) -> String {
+    let mut result = String::new();
+    let mut quote = None;
+    for c in line.chars() {
+        match quote {
+            Some(q) if c == q => {
+                quote = None;
+                result.push(c);
+            }
+            Some(_) => {}
+            None => {
+                if matches!(c, '"' | '\'' | '`') {
+                    quote = Some(c);
+                }
+                result.push(c);
+            }
+        }
+    }
+    result
+}
+
+/// 根据增删的行启发式地给出修改的简短描述，如 "新增函数 handle_retry"、"删除 import"。
+/// 识别不出时返回 None，最多组合两条描述。
+pub fn describe_change<'a>(diff: &TextDiff<'a, 'a, '_, str>, language: Language) -> Option<String> {
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    for change in diff.iter_all_changes() {
+        let line = change.value().trim_end();
+        if line.trim().is_empty() {
+            continue;
+        }
+        match change.tag() {
+            ChangeTag::Insert => added.push(line),
+            ChangeTag::Delete => removed.push(line),
+            ChangeTag::Equal => {}
+        }
+    }
+    if added.is_empty() && removed.is_empty() {
+        return None;
+    }
+
+    let names = |lines: &[&str], extract: fn(&str, Language) -> Option<String>| -> Vec<String> {
+        lines.iter().filter_map(|line| extract(line, language)).collect()
+    };
+    let (added_fns, removed_fns) = (names(&added, function_name), names(&removed, function_name));
+    let (added_types, removed_types) = (names(&added, type_name), names(&removed, type_name));
+
+    let mut descriptions = Vec::new();
+    let mut describe_names = |prefix: &str, new: &[String], old: &[String]| {
+        // 同名的增删是修改签名，不算新增/删除
+        let only: Vec<&String> = new.iter().filter(|name| !old.contains(name)).collect();
+        match only.as_slice() {
+            [] => {}
+            [name] => descriptions.push(format!("{} {}", prefix, name)),
+            names => descriptions.push(i18n::tf("desc_many", &[&prefix, &names[0], &names.len()])),
+        }
+    };
+    describe_names(i18n::t("desc_added_fn"), &added_fns, &removed_fns);
+    describe_names(i18n::t("desc_removed_fn"), &removed_fns, &added_fns);
+    describe_names(i18n::t("desc_added_type"), &added_types, &removed_types);
+    describe_names(i18n::t("desc_removed_type"), &removed_types, &added_types);
+
+    let added_imports = added.iter().filter(|line| is_import(line, language)).count();
+    let removed_imports = removed.iter().filter(|line| is_import(line, language)).count();
+    match (added_imports, removed_imports) {
+        (0, 0) => {}
+        (_, 0) => descriptions.push(i18n::t("desc_added_import").to_string()),
+        (0, _) => descriptions.push(i18n::t("desc_removed_import").to_string()),
+        _ => descriptions.push(i18n::t("desc_changed_import").to_string()),
+    }
+
+    if descriptions.is_empty() {
+        let comment = language.comment_prefix();
+        let is_comment = |line: &&str| line.trim_start().starts_with(comment);
+        if added.iter().chain(&removed).all(is_comment) {
+            descriptions.push(i18n::t("desc_comment").to_string());
+        } else if added.len() == removed.len()
+            && added.iter().zip(&removed).all(|(new, old)| new != old && strip_strings(new) == strip_strings(old))
+        {
+            descriptions.push(i18n::t("desc_string").to_string());
+        }
+    }
+
+    descriptions.truncate(2);
+    (!descriptions.is_empty()).then(|| descriptions.join(i18n::t("desc_separator")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn describe(old: &str, new: &str, language: Language) -> Option<String> {
+        describe_change(&TextDiff::from_lines(old, new), language)
+    }
+
+    #[test]
+    fn added_function_is_named() {
+        let old = "fn run() {}\n";
+        let new = "fn run() {}\n\npub async fn handle_retry(attempt: u32) {\n    run();\n}\n";
+        assert_eq!(describe(old, new, Language::Rust), Some(format!("{} handle_retry", i18n::t("desc_added_fn"))));
+
+        let new = "function run() {}\nexport const handleRetry = async () => run();\n";
+        assert_eq!(
+            describe("function run() {}\n", new, Language::JavaScript),
+            Some(format!("{} handleRetry", i18n::t("desc_added_fn")))
+        );
+    }
+
+    #[test]
+    fn removed_import_is_described() {
+        let old = "import os\nfrom typing import List\n\nprint(os.name)\n";
+        let new = "import os\n\nprint(os.name)\n";
+        assert_eq!(describe(old, new, Language::Python), Some(i18n::t("desc_removed_import").to_string()));
+    }
+
+    #[test]
+    fn signature_changes_and_unrecognized_edits() {
+        // 同名函数的增删是修改签名，不算新增
+        assert_eq!(describe("def load(path):\n", "def load(path, strict=False):\n", Language::Python), None);
+        assert_eq!(describe("x = 1\n", "x = 2\n", Language::Python), None);
+        assert_eq!(
+            describe("const label = \"Save\";\n", "const label = \"Save all\";\n", Language::JavaScript),
+            Some(i18n::t("desc_string").to_string())
+        );
+    }
+}
@@ -0,0 +1,73 @@
+use anyhow::Result;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// 写入 diff 文件，compress 时写成 `<path>.gz`，返回实际写入的路径
+pub fn write(path: &Path, content: &str, compress: bool) -> Result<PathBuf> {
+    if !compress {
+        fs::write(path, content)?;
+        return Ok(path.to_path_buf());
+    }
+
+    let gz_path = PathBuf::from(format!("{}.gz", path.display()));
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(content.as_bytes())?;
+    fs::write(&gz_path, encoder.finish()?)?;
+
+    // 切换配置后清掉同名的旧格式文件，避免读到过期内容
+    fs::remove_file(path).ok();
+    Ok(gz_path)
+}
+
+/// 读取 diff 文件，.gz 透明解压
+pub fn read(path: &Path) -> Result<String> {
+    let bytes = fs::read(path)?;
+    if !is_compressed(path) {
+        return Ok(String::from_utf8_lossy(&bytes).into_owned());
+    }
+
+    let mut content = String::new();
+    GzDecoder::new(bytes.as_slice()).read_to_string(&mut content)?;
+    Ok(content)
+}
+
+pub fn is_compressed(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compressed_diff_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("abc.preview.diff");
+        let diff = "--- a.rs\n+++ a.rs\n@@ -1 +1 @@\n-旧\n+新\n".repeat(50);
+
+        // 先写一份未压缩的，切换到压缩后应被清掉
+        fs::write(&path, "stale").unwrap();
+        let written = write(&path, &diff, true).unwrap();
+
+        assert_eq!(written, dir.path().join("abc.preview.diff.gz"));
+        assert!(is_compressed(&written));
+        assert!(!path.exists());
+        assert!(fs::metadata(&written).unwrap().len() < diff.len() as u64);
+        assert_eq!(read(&written).unwrap(), diff);
+    }
+
+    #[test]
+    fn uncompressed_diff_is_written_as_is() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("abc.preview.diff");
+
+        let written = write(&path, "+line\n", false).unwrap();
+
+        assert_eq!(written, path);
+        assert_eq!(read(&written).unwrap(), "+line\n");
+    }
+}
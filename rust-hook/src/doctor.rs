@@ -0,0 +1,101 @@
+use anyhow::{bail, Result};
+use std::fs;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::NotchHook;
+
+/// 一项检查的结果：通过时为说明，失败时为原因和修复建议
+struct Check {
+    name: &'static str,
+    result: Result<String, (String, &'static str)>,
+}
+
+fn check_project_dir() -> Check {
+    let result = match std::env::var("CLAUDE_PROJECT_DIR") {
+        Ok(dir) if Path::new(&dir).is_dir() => Ok(dir),
+        Ok(dir) => Err((format!("{} is not a directory", dir), "Run the hook from Claude Code, or point CLAUDE_PROJECT_DIR at the project root")),
+        Err(_) => Err(("not set".to_string(), "Claude Code sets it for hooks; when testing by hand, export CLAUDE_PROJECT_DIR=$(pwd)")),
+    };
+    Check { name: "CLAUDE_PROJECT_DIR", result }
+}
+
+fn check_socket(path: &Path) -> Check {
+    let result = if !path.exists() {
+        Err((format!("{} not found", path.display()), "NotchNoti app not running; start it, or set NOTCH_SOCKET_PATH / --socket-path"))
+    } else {
+        match UnixStream::connect(path) {
+            Ok(_) => Ok(path.display().to_string()),
+            Err(e) => Err((format!("{}: {}", path.display(), e), "The socket file is stale; restart NotchNoti")),
+        }
+    };
+    Check { name: "NotchNoti socket", result }
+}
+
+fn check_diff_dir(dir: &Path) -> Check {
+    let probe = dir.join(format!(".doctor-{}", std::process::id()));
+    let result = match fs::write(&probe, b"ok") {
+        Ok(()) => {
+            fs::remove_file(&probe).ok();
+            Ok(dir.display().to_string())
+        }
+        Err(e) => Err((format!("{}: {}", dir.display(), e), "Check the permissions of ~/Library/Application Support/NotchNoti")),
+    };
+    Check { name: "Diff directory writable", result }
+}
+
+fn check_config_file(name: &'static str, path: Option<&Path>, hint: &'static str) -> Check {
+    let result = match path {
+        None => Ok("not present, using defaults".to_string()),
+        Some(path) => match fs::read_to_string(path) {
+            Err(_) => Ok(format!("{} not present, using defaults", path.display())),
+            Ok(content) => match toml::from_str::<Config>(&content) {
+                Ok(_) => Ok(path.display().to_string()),
+                Err(e) => Err((format!("{}: {}", path.display(), e.message()), hint)),
+            },
+        },
+    };
+    Check { name, result }
+}
+
+impl NotchHook {
+    /// 逐项检查常见的配置问题，输出 ✅/❌ 清单和修复建议
+    pub(crate) fn run_doctor(&self) -> Result<()> {
+        let global_config = Config::path();
+        let project_config = Config::find_project_config(&self.project_path);
+        let checks = [
+            check_project_dir(),
+            check_socket(self.socket.path()),
+            check_diff_dir(&self.diff_dir),
+            check_config_file(
+                "Global config",
+                global_config.as_deref(),
+                "Fix the file; until then the hook ignores it and uses the defaults",
+            ),
+            check_config_file(
+                "Project config",
+                project_config.as_deref(),
+                "Fix the file; until then the hook ignores it and uses the global config",
+            ),
+        ];
+
+        let mut failed = 0;
+        for check in &checks {
+            match &check.result {
+                Ok(detail) => println!("✅ {}: {}", check.name, detail),
+                Err((problem, hint)) => {
+                    failed += 1;
+                    println!("❌ {}: {}", check.name, problem);
+                    println!("   → {}", hint);
+                }
+            }
+        }
+
+        if failed > 0 {
+            bail!("{} of {} check(s) failed", failed, checks.len());
+        }
+        println!("All {} checks passed", checks.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+use std::path::Path;
+use std::sync::OnceLock;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::SyntaxSet;
+use syntect::util::as_24_bit_terminal_escaped;
+
+// diff 的 ANSI 着色：+/- 前缀按增删着色，代码部分按文件扩展名对应的语法高亮
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const CYAN: &str = "\x1b[36m";
+const THEME: &str = "base16-ocean.dark";
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+/// 给 unified diff 加 ANSI 颜色；扩展名没有对应语法时返回 None，由调用方保留纯文本
+pub fn colorize(diff: &str, file_path: &Path) -> Option<String> {
+    let syntaxes = syntax_set();
+    let syntax = syntaxes.find_syntax_by_extension(file_path.extension()?.to_str()?)?;
+    let themes = ThemeSet::load_defaults();
+    let mut highlighter = HighlightLines::new(syntax, themes.themes.get(THEME)?);
+
+    let mut out = String::with_capacity(diff.len() * 4);
+    let mut in_hunk = false;
+    for line in diff.split_inclusive('\n') {
+        let (content, newline) = match line.strip_suffix('\n') {
+            Some(content) => (content, "\n"),
+            None => (line, ""),
+        };
+
+        if content.starts_with("@@") {
+            in_hunk = true;
+            out.push_str(&format!("{}{}{}{}", CYAN, content, RESET, newline));
+            continue;
+        }
+        // 第一个 hunk 之前是文件头（---/+++、diff --git 等）
+        if !in_hunk {
+            out.push_str(&format!("{}{}{}{}", BOLD, content, RESET, newline));
+            continue;
+        }
+
+        let color = match content.chars().next() {
+            Some('+') => GREEN,
+            Some('-') => RED,
+            Some('~') => YELLOW,
+            Some(' ') => "",
+            // "\ No newline at end of file"、截断提示等
+            _ => {
+                out.push_str(line);
+                continue;
+            }
+        };
+        let code = &line[1..];
+        let ranges = highlighter.highlight_line(code, syntaxes).ok()?;
+        let escaped = as_24_bit_terminal_escaped(&ranges, false);
+        out.push_str(&format!("{}{}{}{}{}{}", color, &content[..1], RESET, escaped.trim_end_matches('\n'), RESET, newline));
+    }
+    Some(out)
+}
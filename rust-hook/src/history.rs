@@ -0,0 +1,106 @@
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::{state, timefmt, NotchHook, Notification};
+
+// 已发送通知的持久记录：每个项目一个 JSONL 文件，超过上限时轮转为 <project>.1.jsonl
+
+/// 历史文件超过该大小时轮转（只保留一份旧文件）
+const MAX_HISTORY_BYTES: u64 = 5 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// 发送时间（毫秒）
+    pub at: u64,
+    pub title: String,
+    pub message: String,
+    #[serde(rename = "type")]
+    pub notification_type: String,
+    pub priority: u8,
+}
+
+fn rotated_path(path: &Path) -> PathBuf {
+    path.with_extension("1.jsonl")
+}
+
+/// 追加一条记录，失败只打印警告，不影响通知本身
+pub fn append(path: &Path, notification: &Notification) {
+    let entry = HistoryEntry {
+        at: state::now_millis(),
+        title: notification.title.clone(),
+        message: notification.message.clone(),
+        notification_type: notification.notification_type.as_str().to_string(),
+        priority: notification.priority,
+    };
+
+    let result = (|| -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if fs::metadata(path).is_ok_and(|metadata| metadata.len() >= MAX_HISTORY_BYTES) {
+            fs::rename(path, rotated_path(path))?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        Ok(())
+    })();
+
+    if let Err(e) = result {
+        warn!("Failed to append notification history: {}", e);
+    }
+}
+
+/// 按时间顺序读取历史（含轮转出去的旧文件），跳过无法解析的行
+fn load(path: &Path) -> Vec<HistoryEntry> {
+    [rotated_path(path), path.to_path_buf()]
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .flat_map(|content| {
+            content
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+impl NotchHook {
+    /// ~/Library/Application Support/NotchNoti/history/<project>.jsonl
+    pub(crate) fn history_path(&self) -> PathBuf {
+        let project = self.diff_dir.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        self.diff_dir
+            .parent()
+            .and_then(Path::parent)
+            .unwrap_or(&self.diff_dir)
+            .join("history")
+            .join(format!("{}.jsonl", project))
+    }
+
+    pub(crate) fn handle_history_command(&self, limit: usize, notification_type: Option<String>, json: bool) -> Result<()> {
+        let entries: Vec<HistoryEntry> = load(&self.history_path())
+            .into_iter()
+            .filter(|entry| notification_type.as_ref().is_none_or(|wanted| &entry.notification_type == wanted))
+            .collect();
+        let skip = entries.len().saturating_sub(limit);
+
+        let now = state::now_millis();
+        for entry in entries.iter().skip(skip) {
+            if json {
+                println!("{}", serde_json::to_string(entry).context("Failed to serialize history entry")?);
+            } else {
+                println!(
+                    "[{}] {} {} — {}",
+                    timefmt::format_entry_time(entry.at, now, false),
+                    entry.notification_type,
+                    entry.title,
+                    entry.message.lines().next().unwrap_or("")
+                );
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,194 @@
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+// 通知文案的翻译表，NOTCH_LANG=en 时使用英文，默认中文。模板中的 {} 由 tf 依次填入参数
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    Zh,
+    En,
+}
+
+pub fn lang() -> Lang {
+    static LANG: OnceLock<Lang> = OnceLock::new();
+    *LANG.get_or_init(|| match std::env::var("NOTCH_LANG") {
+        Ok(value) if value.to_ascii_lowercase().starts_with("en") => Lang::En,
+        _ => Lang::Zh,
+    })
+}
+
+/// 取当前语言的文案，表中没有的 key 原样返回，方便发现遗漏
+pub fn t(key: &'static str) -> &'static str {
+    match (lookup(key), lang()) {
+        (Some((zh, _)), Lang::Zh) => zh,
+        (Some((_, en)), Lang::En) => en,
+        (None, _) => key,
+    }
+}
+
+/// 取文案并依次替换其中的 {}
+pub fn tf(key: &'static str, args: &[&dyn Display]) -> String {
+    let mut args = args.iter();
+    let mut parts = t(key).split("{}");
+    let mut out = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        if let Some(arg) = args.next() {
+            out.push_str(&arg.to_string());
+        }
+        out.push_str(part);
+    }
+    out
+}
+
+fn lookup(key: &str) -> Option<(&'static str, &'static str)> {
+    let pair = match key {
+        // 会话
+        "session_start" => ("会话开始", "Session started"),
+        "session_started_message" => ("Claude Code 会话已启动", "Claude Code session started"),
+        "session_end" => ("会话结束", "Session finished"),
+        "session_closed" => ("会话已关闭", "Session closed"),
+        "session_length" => ("时长 {}", "{} long"),
+        "session_counts" => ("编辑 {} 次 · 命令 {} 条 · 错误 {} 个", "{} edits · {} commands · {} errors"),
+        "session_end_errors" => ("会话结束 (⚠️ {} 个错误)", "Session finished (⚠️ {} errors)"),
+        "all_tasks_done" => ("Claude 已完成所有任务", "Claude has finished all tasks"),
+        "created_files" => ("🆕 本次会话新增 {} 个文件: {}", "🆕 {} new files this session: {}"),
+        "needs_response" => ("需要你的响应", "Needs your response"),
+        "waiting_for_choice" => ("Claude 正在等待你的选择，请查看 Claude Code 窗口", "Claude is waiting for your choice, check the Claude Code window"),
+        "response_needed" => ("需要响应", "Response needed"),
+        "compacting" => ("内存优化", "Compacting"),
+        "compacting_message" => ("正在压缩上下文以节省内存", "Compacting the context to save memory"),
+        "waited" => ("等待了 {}", "Waited {}"),
+        "resumed_after_wait" => ("Claude 等待你的响应后已继续", "Claude resumed after waiting for you"),
+        "edit_milestone" => ("本次会话已编辑 {} 次", "{} edits this session"),
+        "total" => ("累计 {}", "Total {}"),
+
+        // 文件修改
+        "about_to_edit" => ("即将修改", "About to edit"),
+        "about_to_edit_test" => ("即将修改测试", "About to edit test"),
+        "edit_done" => ("修改完成", "Edit applied"),
+        "multi_edit" => ("批量修改", "Multi-edit"),
+        "multi_edit_done" => ("批量修改完成", "Multi-edit applied"),
+        "multi_edit_estimate" => ("{} (批量修改 {} 处, 预计 {}){}", "{} ({} edits, est. {}){}"),
+        "multi_edit_plain" => ("{} (批量修改){}", "{} (multi-edit){}"),
+        "multi_edit_applied" => ("{} ({} 处修改已完成)", "{} ({} edits applied)"),
+        "estimate" => ("{} (预计 {})", "{} (est. {})"),
+        "estimate_scoped" => ("{} ({}预计 {}){}", "{} ({}est. {}){}"),
+        "no_change" => ("{} (无变化){}", "{} (no change){}"),
+        "replace_all_scope" => ("替换全部 {} 处, ", "replace all {}, "),
+        "large_write" => ("写入大文件 ({})", "Large write ({})"),
+        "outside_project" => ("⚠️ 项目外修改 {}", "⚠️ Outside the project {}"),
+        "reverted" => ("{} ↩️ 撤销了之前的修改", "{} ↩️ reverted an earlier change"),
+        "notebook_edit" => ("修改 notebook", "Edit notebook"),
+        "notebook_edit_cell" => ("修改 notebook 单元格 (cell {})", "Edit notebook cell (cell {})"),
+        "notebook_insert_cell" => ("插入 notebook 单元格 (cell {})", "Insert notebook cell (cell {})"),
+        "notebook_delete_cell" => ("删除 notebook 单元格 (cell {})", "Delete notebook cell (cell {})"),
+        "binary_file" => ("二进制文件", "binary file"),
+        "verbose_stats" => ("新增{}行 删除{}行", "{} lines added, {} removed"),
+
+        // 命令与工具
+        "run_command" => ("执行命令", "Run command"),
+        "command_done" => ("命令完成", "Command finished"),
+        "done" => ("完成", "Done"),
+        "took" => (" (耗时 {})", " (took {})"),
+        "command_failed" => ("命令失败 (退出码 {})", "Command failed (exit code {})"),
+        "tool_failed" => ("工具执行失败", "Tool failed"),
+        "recovered" => ("已恢复", "Recovered"),
+        "dangerous_operation" => ("危险操作", "Dangerous operation"),
+        "dangerous_blocked" => ("已阻止危险操作", "Dangerous operation blocked"),
+        "mass_delete" => ("批量删除", "Mass delete"),
+        "force_push" => ("强制推送", "Force push"),
+        "force_push_lease" => ("强制推送 (with-lease) {}", "Force push (with-lease) {}"),
+        "permission_change" => ("修改权限 {} ({}{})", "Change permissions {} ({}{})"),
+        "migration" => ("数据库迁移", "Database migration"),
+        "migration_up" => ("数据库迁移 (升级)", "Database migration (upgrade)"),
+        "migration_down" => ("数据库迁移 (回滚)", "Database migration (rollback)"),
+        "agent_started" => ("Agent 启动", "Agent started"),
+        "agent_done" => ("Agent 完成", "Agent finished"),
+        "subagent_done" => ("子任务完成", "Subagent finished"),
+        "subagent_done_typed" => ("{} 子任务已结束", "{} subagent finished"),
+        "subagent_done_message" => ("子任务已结束", "The subagent has finished"),
+        "ai_task_running" => ("AI 任务处理中", "AI task in progress"),
+        "ai_task_done" => ("AI 任务处理完毕", "AI task finished"),
+        "web_access" => ("网络访问", "Web access"),
+        "web_fetch_done" => ("网络请求完成", "Fetch finished"),
+        "web_search_done" => ("搜索完成", "Search finished"),
+        "web_failed" => ("网络请求失败", "Web request failed"),
+        "search_results" => ("{} 条结果", "{} results"),
+        "task_update" => ("任务更新", "Tasks updated"),
+        "task_progress" => ("进度: {}/{} 完成", "Progress: {}/{} done"),
+        "noise_read" => ("读取 {} 个文件", "Read {} files"),
+        "noise_grep" => ("搜索 {} 次", "{} searches"),
+        "noise_glob" => ("匹配 {} 次", "{} globs"),
+        "noise_ls" => ("列出 {} 个目录", "Listed {} directories"),
+        "confirm_required" => ("需要确认", "Confirmation required"),
+        "denied_in_notch" => ("用户在 NotchNoti 中拒绝了: {}", "Denied in NotchNoti: {}"),
+        "blocked_dangerous" => ("危险操作已被 notch-hook 阻止: {}", "Dangerous operation blocked by notch-hook: {}"),
+        "chosen_in_notch" => ("用户在 NotchNoti 中选择了: {}", "Chosen in NotchNoti: {}"),
+        "op_run_command" => ("执行命令: {}", "Run command: {}"),
+        "op_bash" => ("执行 Bash 命令", "Run a Bash command"),
+        "op_sensitive_file" => ("修改敏感文件: {}", "Edit sensitive file: {}"),
+        "op_edit_file" => ("修改文件", "Edit a file"),
+        "op_other" => ("执行操作: {}", "Operation: {}"),
+
+        // JetBrains
+        "jb_edit" => ("JetBrains IDE 修改", "JetBrains IDE edit"),
+        "jb_create_file" => ("JetBrains 创建文件", "JetBrains new file"),
+        "jb_file_created" => ("文件已创建", "File created"),
+        "jb_edit_done" => ("IDE 修改完成", "IDE edit applied"),
+        "jb_terminal" => ("JetBrains 终端", "JetBrains terminal"),
+        "jb_unknown_target" => ("未知目标", "unknown target"),
+        "jb_navigate" => ("跳转定义", "Go to definition"),
+        "jb_find_usages" => ("查找引用", "Find usages"),
+        "jb_search_everywhere" => ("全局搜索", "Search everywhere"),
+        "jb_search" => ("搜索", "Search"),
+        "jb_default_configuration" => ("默认配置", "default configuration"),
+        "jb_debug" => ("调试", "Debug"),
+        "jb_run" => ("运行", "Run"),
+        "jb_get_run_configurations" => ("获取运行配置", "Get run configurations"),
+        "jb_get_project_modules" => ("获取项目模块", "Get project modules"),
+        "jb_get_project_dependencies" => ("获取项目依赖", "Get project dependencies"),
+        "jb_get_project_problems" => ("获取项目问题", "Get project problems"),
+        "jb_get_vcs_status" => ("获取VCS状态", "Get VCS status"),
+        "jb_list_directory_tree" => ("列出目录树", "List directory tree"),
+        "jb_find_files_by_name" => ("按名称搜索文件", "Find files by name"),
+        "jb_find_files_by_glob" => ("按模式搜索文件", "Find files by glob"),
+        "jb_get_open_files" => ("获取打开的文件", "Get open files"),
+        "jb_open_file" => ("打开文件", "Open file"),
+        "jb_read_file" => ("读取文件内容", "Read file"),
+        "jb_get_file_problems" => ("获取文件问题", "Get file problems"),
+        "jb_reformat_file" => ("格式化文件", "Reformat file"),
+        "jb_search_text" => ("文本搜索", "Search text"),
+        "jb_search_regex" => ("正则搜索", "Search regex"),
+        "jb_symbol_info" => ("获取符号信息", "Get symbol info"),
+        "jb_rename" => ("重命名重构", "Rename refactoring"),
+        "jb_execute_run_configuration" => ("执行运行配置", "Execute run configuration"),
+        "jb_find_commit" => ("搜索提交", "Find commit"),
+        "jb_other" => ("JetBrains操作", "JetBrains action"),
+
+        // 修改描述（describe.rs）
+        "desc_many" => ("{} {} 等 {} 个", "{} {} and {} more"),
+        "desc_added_fn" => ("新增函数", "Added function"),
+        "desc_removed_fn" => ("删除函数", "Removed function"),
+        "desc_added_type" => ("新增类型", "Added type"),
+        "desc_removed_type" => ("删除类型", "Removed type"),
+        "desc_added_import" => ("新增 import", "Added imports"),
+        "desc_removed_import" => ("删除 import", "Removed imports"),
+        "desc_changed_import" => ("调整 import", "Changed imports"),
+        "desc_comment" => ("修改注释", "Changed comments"),
+        "desc_string" => ("修改字符串常量", "Changed string literals"),
+        "desc_separator" => ("，", ", "),
+
+        // 时间（timefmt.rs）
+        "just_now" => ("刚刚", "just now"),
+        "minutes_ago" => ("{}分钟前", "{}m ago"),
+        "hours_ago" => ("{}小时前", "{}h ago"),
+        "days_ago" => ("{}天前", "{}d ago"),
+        "seconds" => ("{}秒", "{}s"),
+        "minutes" => ("{}分钟", "{}m"),
+        "hours" => ("{}小时", "{}h"),
+        "hours_minutes" => ("{}小时{}分钟", "{}h {}m"),
+
+        _ => return None,
+    };
+    Some(pair)
+}
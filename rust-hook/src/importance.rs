@@ -0,0 +1,117 @@
+use std::path::Path;
+
+/// 影响通知重要性的各项信号，统一在此收集
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ImportanceContext {
+    pub priority: u8,
+    pub dangerous: bool,
+    pub watched_path: bool,
+    pub is_error: bool,
+    pub dependency_change: bool,
+    pub lines_changed: usize,
+    /// 修改的文件在项目目录之外
+    pub outside_project: bool,
+}
+
+/// 综合各项信号得到 0.0–1.0 的重要性分数，离散的 priority 保持不变以兼容旧版 App
+pub fn compute_importance(context: &ImportanceContext) -> f32 {
+    // priority 4（critical）只用于安全检测，直接视为最重要
+    if context.priority >= 4 {
+        return 1.0;
+    }
+
+    let mut score = f32::from(context.priority) / 3.0 * 0.3;
+
+    if context.dangerous {
+        score += 0.3;
+    }
+    if context.watched_path {
+        score += 0.2;
+    }
+    if context.is_error {
+        score += 0.25;
+    }
+    if context.dependency_change {
+        score += 0.15;
+    }
+    if context.outside_project {
+        score += 0.2;
+    }
+    score += (context.lines_changed as f32 / 100.0).min(1.0) * 0.15;
+
+    score.min(1.0)
+}
+
+const DEPENDENCY_FILES: &[&str] = &[
+    "Cargo.toml",
+    "Cargo.lock",
+    "package.json",
+    "package-lock.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "requirements.txt",
+    "pyproject.toml",
+    "Pipfile",
+    "Pipfile.lock",
+    "go.mod",
+    "go.sum",
+    "Gemfile",
+    "Gemfile.lock",
+    "Podfile",
+    "Package.swift",
+    "build.gradle",
+    "pom.xml",
+];
+
+const DEPENDENCY_COMMANDS: &[&str] = &[
+    "npm install",
+    "npm i ",
+    "yarn add",
+    "pnpm add",
+    "cargo add",
+    "pip install",
+    "poetry add",
+    "go get",
+    "bundle add",
+    "pod install",
+];
+
+pub fn is_dependency_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| DEPENDENCY_FILES.contains(&name))
+}
+
+pub fn is_dependency_command(command: &str) -> bool {
+    let command = command.trim_start();
+    DEPENDENCY_COMMANDS.iter().any(|prefix| command.starts_with(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dangerous_edit_to_watched_file_outranks_routine_read() {
+        let routine_read = ImportanceContext { priority: 1, ..Default::default() };
+        let dangerous_edit = ImportanceContext {
+            priority: 3,
+            dangerous: true,
+            watched_path: true,
+            lines_changed: 40,
+            ..Default::default()
+        };
+
+        assert!(compute_importance(&dangerous_edit) > compute_importance(&routine_read));
+        assert!(compute_importance(&dangerous_edit) <= 1.0);
+        assert_eq!(compute_importance(&ImportanceContext { priority: 4, ..Default::default() }), 1.0);
+    }
+
+    #[test]
+    fn recognizes_dependency_files_and_commands() {
+        assert!(is_dependency_file(Path::new("crates/app/Cargo.toml")));
+        assert!(!is_dependency_file(Path::new("src/main.rs")));
+        assert!(is_dependency_command("  cargo add serde"));
+        assert!(!is_dependency_command("cargo build"));
+    }
+}
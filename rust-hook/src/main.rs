@@ -1,19 +1,276 @@
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use sha2::{Digest, Sha256};
-use similar::{ChangeTag, TextDiff};
+use similar::{ChangeTag, DiffTag, TextDiff};
+use std::cell::{Cell, OnceCell, RefCell};
 use std::collections::HashMap;
 use std::fs;
-use std::io::{self, Read, Write};
-use std::os::unix::net::UnixStream;
+use std::io::{self, Read};
 use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use unicode_segmentation::UnicodeSegmentation;
+
+mod cassette;
+mod cleanup;
+mod coalesce;
+mod config;
+mod confirm;
+mod dedup;
+mod demo;
+mod describe;
+mod diffstore;
+mod doctor;
+mod highlight;
+mod history;
+mod i18n;
+mod importance;
+mod metrics;
+mod notebook;
+mod prediff;
+mod privacy;
+mod queue;
+mod ratelimit;
+mod schema;
+mod selftest;
+mod state;
+mod stats;
+mod timefmt;
+mod transport;
+mod worddiff;
+
+use config::{Config, DiffGranularity, DiffStyle, NotifyPhase, StatsStyle, ToolRule};
+use i18n::{t, tf};
+use importance::ImportanceContext;
+use state::{CommandRecord, SessionState};
+use transport::{AckStyle, NotchSocket, Transport};
+
+/// passthrough 字段的数量和长度上限，避免 metadata 过大
+const MAX_PASSTHROUGH_FIELDS: usize = 10;
+const MAX_PASSTHROUGH_VALUE_LEN: usize = 200;
+
+/// 估算 token 数超过该值时才在消息中显示
+const TOKEN_ESTIMATE_DISPLAY_THRESHOLD: usize = 1000;
+
+/// Stop 通知中最多直接列出的新建文件数，完整列表放在 metadata
+const MAX_LISTED_CREATED_FILES: usize = 3;
+
+/// Stop 通知消息中列出的错误数，完整列表在 metadata 中
+const MAX_LISTED_ERRORS: usize = 3;
+
+/// 会话状态中最多保留的错误记录
+const MAX_RECORDED_ERRORS: usize = 50;
+
+/// 每个文件保留的历史内容状态数，用于检测来回修改
+const MAX_FILE_STATES: usize = 20;
+
+/// 普通通知的优先级上限（0 低 … 3 高）
+const PRIORITY_MAX: u8 = 3;
+/// 保留给安全检测（批量删除等）的最高级别，App 会常驻显示并提示音；只能经 send_critical_notification 发出
+const PRIORITY_CRITICAL: u8 = 4;
+
+/// App 正在启动等短暂连不上 socket 时的重试次数，等待时间从 SOCKET_RETRY_BASE_DELAY 起每次 ×3
+const SOCKET_RETRY_ATTEMPTS: u32 = 3;
+const SOCKET_RETRY_BASE_DELAY: Duration = Duration::from_millis(50);
+
+/// diff 中修改行前后保留的上下文行数，可由 NOTCH_DIFF_CONTEXT 或 diff --context 调整，超出范围时截到 0..=MAX_DIFF_CONTEXT
+const DEFAULT_DIFF_CONTEXT: usize = 3;
+const MAX_DIFF_CONTEXT: usize = 50;
+
+fn clamp_diff_context(value: i64) -> usize {
+    let clamped = value.clamp(0, MAX_DIFF_CONTEXT as i64) as usize;
+    if clamped as i64 != value {
+        warn!("Diff context {} out of range, using {}", value, clamped);
+    }
+    clamped
+}
+
+/// NOTCH_MIN_PRIORITY，限制在 0..=PRIORITY_MAX，critical 通知总能送达
+fn min_priority_from_env() -> u8 {
+    match std::env::var("NOTCH_MIN_PRIORITY") {
+        Ok(value) => match value.trim().parse::<u8>() {
+            Ok(value) => value.min(PRIORITY_MAX),
+            Err(_) => {
+                warn!("Invalid NOTCH_MIN_PRIORITY {:?}, ignoring it", value);
+                0
+            }
+        },
+        Err(_) => 0,
+    }
+}
+
+fn diff_context_from_env() -> usize {
+    match std::env::var("NOTCH_DIFF_CONTEXT") {
+        Ok(value) => match value.trim().parse::<i64>() {
+            Ok(value) => clamp_diff_context(value),
+            Err(_) => {
+                warn!("Invalid NOTCH_DIFF_CONTEXT {:?}, using {}", value, DEFAULT_DIFF_CONTEXT);
+                DEFAULT_DIFF_CONTEXT
+            }
+        },
+        Err(_) => DEFAULT_DIFF_CONTEXT,
+    }
+}
+
+/// HTTP 降级的默认地址
+const DEFAULT_HTTP_URL: &str = "http://127.0.0.1:9876/notify";
+
+/// HTTP 降级默认关闭：NOTCH_HTTP_FALLBACK=1 使用默认地址，NOTCH_HTTP_URL 指定地址并同时启用
+fn http_fallback_url() -> Option<String> {
+    if let Some(url) = std::env::var("NOTCH_HTTP_URL").ok().filter(|url| !url.is_empty()) {
+        return Some(url);
+    }
+    matches!(std::env::var("NOTCH_HTTP_FALLBACK").as_deref(), Ok("1" | "true"))
+        .then(|| DEFAULT_HTTP_URL.to_string())
+}
+
+/// socket 不存在或拒绝连接，可能只是 App 还没准备好，值得重试
+fn is_transient_socket_error(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<io::Error>()
+            .is_some_and(|e| matches!(e.kind(), io::ErrorKind::ConnectionRefused | io::ErrorKind::NotFound))
+    })
+}
+
+/// 粗略估算 token 数：平均每 4 字节一个 token
+fn estimate_tokens(text: &str) -> usize {
+    estimate_tokens_from_bytes(text.len())
+}
+
+fn estimate_tokens_from_bytes(bytes: usize) -> usize {
+    bytes.div_ceil(4)
+}
+
+fn format_token_estimate(tokens: usize) -> String {
+    if tokens >= 1000 {
+        format!("~{:.1}k tokens", tokens as f64 / 1000.0)
+    } else {
+        format!("~{} tokens", tokens)
+    }
+}
+
+/// 按字素簇截断到最多 max 个，截断时末尾加 …，不会拆开组合 emoji、国旗或组合字符
+fn truncate_display(text: &str, max: usize) -> String {
+    match text.grapheme_indices(true).nth(max) {
+        Some((index, _)) => format!("{}…", &text[..index]),
+        None => text.to_string(),
+    }
+}
+
+/// 人类可读的文件大小，如 2.3MB
+fn format_size(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    // 保留一位小数后会显示为 1024.0 的值进到下一个单位
+    while unit < UNITS.len() - 1 && (size >= 1024.0 || (unit > 0 && size >= 1023.95)) {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{}B", bytes)
+    } else {
+        format!("{:.1}{}", size, UNITS[unit])
+    }
+}
+
+/// 片段最多显示的字符数
+const SNIPPET_MAX_CHARS: usize = 200;
+
+/// 从 unified diff 中取第一处修改及其前后各两行，用于在通知中内联预览
+fn diff_snippet(diff: &str) -> Option<String> {
+    let hunk: Vec<&str> = diff
+        .lines()
+        .skip_while(|line| !line.starts_with("@@"))
+        .skip(1)
+        .take_while(|line| !line.starts_with("@@"))
+        .collect();
+    let first_change = hunk.iter().position(|line| line.starts_with(['+', '-', '~']))?;
+    let start = first_change.saturating_sub(2);
+    let end = (first_change + 3).min(hunk.len());
+    Some(truncate_display(&hunk[start..end].join("\n"), SNIPPET_MAX_CHARS))
+}
+
+/// diff 超过 max_lines 行时保留前 max_lines 行，并在末尾注明省略的修改行数；未超过返回 None
+fn truncate_diff(diff: &str, max_lines: usize) -> Option<String> {
+    if max_lines == 0 || diff.lines().count() <= max_lines {
+        return None;
+    }
+
+    let mut truncated: String = diff.lines().take(max_lines).flat_map(|line| [line, "\n"]).collect();
+    let omitted = diff
+        .lines()
+        .skip(max_lines)
+        .filter(|line| {
+            line.starts_with(['+', '-', '~']) && !line.starts_with("+++ ") && !line.starts_with("--- ")
+        })
+        .count();
+    truncated.push_str(&format!("... ({} more changed lines omitted)\n", omitted));
+    Some(truncated)
+}
+
+/// 开头 8KB 内出现 NUL 即视为二进制内容
+fn is_binary_content(content: &[u8]) -> bool {
+    content.iter().take(8 * 1024).any(|byte| *byte == 0)
+}
+
+/// Write 的内容超过阈值或是二进制时返回其大小，这类写入不生成 diff
+fn large_write_size(tool_name: &str, tool_input: &Value, threshold: usize) -> Option<usize> {
+    if tool_name != "Write" {
+        return None;
+    }
+    let content = tool_input.get("content")?.as_str()?;
+    (content.len() >= threshold || is_binary_content(content.as_bytes())).then_some(content.len())
+}
 
 #[derive(Parser)]
 #[command(name = "notch-hook")]
 #[command(about = "NotchNoti hook for Claude Code", long_about = None)]
 struct Cli {
+    /// Build and schema-check every notification without connecting to the socket
+    #[arg(long, global = true)]
+    validate_only: bool,
+
+    /// JSON Pointer (RFC 6901) to the hook event inside a wrapping envelope, e.g. /payload/hook
+    #[arg(long, global = true)]
+    event_json_pointer: Option<String>,
+
+    /// Unix socket of the NotchNoti app (overrides NOTCH_SOCKET_PATH and the default container path)
+    #[arg(long, global = true)]
+    socket_path: Option<PathBuf>,
+
+    /// Append every incoming event and outgoing notification to a cassette (JSONL)
+    #[arg(long, global = true)]
+    record: Option<PathBuf>,
+
+    /// Print notifications to stdout instead of sending them (also set by NOTCH_DRY_RUN=1); with cleanup, only list files
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// Prefix each message to NotchNoti with a 4-byte big-endian length instead of a trailing newline (also set by NOTCH_FRAMED=1)
+    #[arg(long, global = true)]
+    framed: bool,
+
+    /// Drop notifications below this priority, 0-3 (also set by NOTCH_MIN_PRIORITY); security alerts always get through
+    #[arg(long, global = true, value_parser = clap::value_parser!(u8).range(0..=3))]
+    min_priority: Option<u8>,
+
+    /// Only deliver the most important notifications (same as --min-priority 3)
+    #[arg(long, global = true, conflicts_with = "min_priority")]
+    quiet: bool,
+
+    /// Re-drive the events of a recorded cassette through the handlers
+    #[arg(long)]
+    replay_cassette: Option<PathBuf>,
+
+    /// With --replay-cassette, fail if the notifications differ from the recorded ones
+    #[arg(long, requires = "replay_cassette")]
+    check: bool,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -22,7 +279,7 @@ struct Cli {
 enum Commands {
     /// Process Claude Code hook event
     Hook,
-    /// Generate diff preview
+    /// Generate a diff preview (--action preview) or print a stored diff file (--action show)
     Diff {
         #[arg(long)]
         action: String,
@@ -32,6 +289,75 @@ enum Commands {
         old_text: Option<String>,
         #[arg(long)]
         new_text: Option<String>,
+        /// Syntax-highlight the diff with ANSI colors, picking the grammar from the file extension
+        #[arg(long)]
+        color: bool,
+        /// Lines of context around each change (0-50, default 3; also set by NOTCH_DIFF_CONTEXT)
+        #[arg(long, allow_negative_numbers = true)]
+        context: Option<i64>,
+    },
+    /// Play a sample session through the real handlers to preview every notification type
+    Demo {
+        /// Delay between events in milliseconds
+        #[arg(long, default_value_t = 1500)]
+        delay_ms: u64,
+    },
+    /// Check the project dir, socket, diff directory and config files, with hints for anything broken
+    Doctor,
+    /// Send one sample notification of each type plus one with a diff, and report PASS/FAIL per send
+    Test {
+        /// Delay between notifications in milliseconds
+        #[arg(long, default_value_t = 500)]
+        delay_ms: u64,
+    },
+    /// Deliver notifications queued while NotchNoti was not running
+    Flush {
+        /// Only deliver notifications queued within this duration (e.g. 30m, 2h); older ones are discarded
+        #[arg(long)]
+        since: Option<String>,
+        /// Keep notifications older than --since in the queue instead of discarding them
+        #[arg(long, requires = "since")]
+        keep_old: bool,
+    },
+    /// Show the last Bash commands run in a session
+    #[command(name = "commands")]
+    CommandLog {
+        /// Session id (defaults to the most recently active session)
+        #[arg(long)]
+        session: Option<String>,
+        /// Maximum number of commands to show
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Show only absolute timestamps
+        #[arg(long)]
+        absolute: bool,
+    },
+    /// Summarize notifications, edits, commands and errors across this project's sessions
+    Stats {
+        /// Only include sessions active within this duration (e.g. 30m, 2h, 7d)
+        #[arg(long)]
+        since: Option<String>,
+        /// Print the summary as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show notifications sent for this project, oldest first
+    History {
+        /// Number of most recent notifications to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+        /// Only show notifications of this type (e.g. error, tool_use)
+        #[arg(long = "type")]
+        notification_type: Option<String>,
+        /// Print one JSON object per line
+        #[arg(long)]
+        json: bool,
+    },
+    /// Delete old diff, stats and session files from every project's diff directory
+    Cleanup {
+        /// Delete files last modified more than this many days ago
+        #[arg(long, default_value_t = 7)]
+        older_than: u64,
     },
 }
 
@@ -42,14 +368,79 @@ struct HookEvent {
     tool_input: Option<Value>,
     tool_output: Option<Value>,  // 用于 PostToolUse
     error: Option<String>,        // 用于错误情况
+    stop_hook_active: Option<bool>,  // Stop hook 自身触发的再次 Stop
+    session_id: Option<String>,
+    model: Option<Value>,  // 字符串，或 { "id", "display_name" } 对象
+    tool_use_id: Option<String>,
+    /// 流式输出的中间更新（长时间构建、后台 shell），最终结果到来前可能有多条
+    #[serde(alias = "incremental")]
+    is_partial: Option<bool>,
+    reason: Option<String>,  // 用于 SessionEnd（clear / logout / prompt_input_exit 等）
+    #[serde(alias = "subagentType")]
+    subagent_type: Option<String>,  // 用于 SubagentStop，旧版本不带
 }
 
-#[derive(Debug, Serialize)]
+/// NotchNoti 识别的通知类型，序列化为 App 使用的字符串
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum NotificationType {
+    Info,
+    Success,
+    Warning,
+    Error,
+    ToolUse,
+    Progress,
+    Ai,
+    Sync,
+    Celebration,
+    Confirmation,
+    Reminder,
+    Download,
+    Security,
+}
+
+impl NotificationType {
+    const ALL: [NotificationType; 13] = [
+        NotificationType::Info,
+        NotificationType::Success,
+        NotificationType::Warning,
+        NotificationType::Error,
+        NotificationType::ToolUse,
+        NotificationType::Progress,
+        NotificationType::Ai,
+        NotificationType::Sync,
+        NotificationType::Celebration,
+        NotificationType::Confirmation,
+        NotificationType::Reminder,
+        NotificationType::Download,
+        NotificationType::Security,
+    ];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            NotificationType::Info => "info",
+            NotificationType::Success => "success",
+            NotificationType::Warning => "warning",
+            NotificationType::Error => "error",
+            NotificationType::ToolUse => "tool_use",
+            NotificationType::Progress => "progress",
+            NotificationType::Ai => "ai",
+            NotificationType::Sync => "sync",
+            NotificationType::Celebration => "celebration",
+            NotificationType::Confirmation => "confirmation",
+            NotificationType::Reminder => "reminder",
+            NotificationType::Download => "download",
+            NotificationType::Security => "security",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 struct Notification {
     title: String,
     message: String,
     #[serde(rename = "type")]
-    notification_type: String,
+    notification_type: NotificationType,
     priority: u8,
     metadata: HashMap<String, String>,
 }
@@ -60,30 +451,734 @@ struct DiffStats {
     removed: usize,
     file: String,
     preview: bool,
+    /// 修改后的内容与本会话中该文件出现过的某个旧状态相同（来回改）
+    revert: bool,
+    /// 原文件中第一处修改所在的行号（从 1 开始），末尾追加时为最后一行 + 1
+    first_change_line: Option<usize>,
+    /// 启发式生成的修改描述，如 "新增函数 handle_retry"
+    change_description: Option<String>,
+    /// 二进制文件，diff 文件中只有大小变化
+    binary: bool,
+    /// diff 超过 max_diff_lines 被截断时，完整 diff 的路径
+    full_diff_path: Option<PathBuf>,
+    /// 生成 diff 时原文件内容的 SHA-256，App 据此判断预览是否已过时
+    source_sha256: String,
+    /// 预览时 old_string 能否在原文件中找到；false 表示修改可能无法干净地应用
+    #[serde(rename = "match", skip_serializing_if = "Option::is_none")]
+    matched: Option<bool>,
+    /// 第一处修改及其上下文（NOTCH_SHOW_SNIPPET=1）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snippet: Option<String>,
+    /// 重命名/移动文件，此时 file 为新路径
+    rename: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    old_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    new_path: Option<String>,
 }
 
 struct NotchHook {
     project_path: PathBuf,
     project_name: String,
     diff_dir: PathBuf,
-    socket_path: PathBuf,
+    /// 发往 NotchNoti 的连接，同一次调用内的多条通知尽量复用
+    socket: NotchSocket,
     session_start_time: std::time::Instant,
-    tool_start_times: std::collections::HashMap<String, std::time::Instant>,
+    /// 当前事件的工具名，用于查找 config.tools 中的规则
+    tool_name: Option<String>,
+    /// 本次 PostToolUse 对应工具的执行耗时（毫秒），由 PreToolUse 时记录在会话状态中的开始时间算出
+    tool_duration_ms: Option<u64>,
+    config: Config,
+    /// 当前事件附带的 metadata，会合并到本次调用发出的所有通知中
+    event_metadata: HashMap<String, String>,
+    /// Claude Code 会话 id，用于定位跨调用持久化的会话状态
+    session_id: String,
+    /// 当前事件的重要性信号（危险操作、关注路径、依赖变更）
+    event_signals: ImportanceContext,
+    /// 当前使用的 Claude 模型（CLAUDE_MODEL 环境变量或事件中的 model 字段）
+    model: Option<String>,
+    /// 只做格式校验，不发送（--validate-only）
+    validate_only: bool,
+    /// validate_only 模式下每条通知的校验结果（违规描述，空表示通过）
+    validation_results: RefCell<Vec<Vec<String>>>,
+    /// --record 指定的 cassette 文件
+    record_path: Option<PathBuf>,
+    /// 本次调用产生的通知，--replay-cassette --check 用来与录制结果比对
+    captured: RefCell<Vec<Value>>,
+    /// 只把通知打印到 stdout，不连接 socket（--dry-run 或 NOTCH_DRY_RUN=1）
+    dry_run: bool,
+    /// 项目的 git 分支和是否有未提交修改，第一次发送通知时才查询；不是 git 仓库时为 None
+    git_status: OnceCell<Option<(String, bool)>>,
+    /// 生成的 unified diff 带 ANSI 语法高亮（NOTCH_DIFF_COLOR 或 diff --color）
+    color_diffs: bool,
+    /// diff 的上下文行数（NOTCH_DIFF_CONTEXT 或 diff --context）
+    diff_context: usize,
+    /// Edit/Write 通知的 metadata 中附带第一处修改的代码片段（NOTCH_SHOW_SNIPPET=1）
+    show_snippet: bool,
+    /// 把每个事件的处理耗时追加到 metrics.jsonl（NOTCH_METRICS=1）
+    timing_metrics: bool,
+    /// 低于该优先级的通知直接丢弃（--min-priority/--quiet 或 NOTCH_MIN_PRIORITY）
+    min_priority: u8,
+    /// 每累计多少次编辑提示一次（NOTCH_EDIT_MILESTONE），None 表示关闭
+    edit_milestone: Option<u64>,
+    /// 确认通知发出后，用户在刘海中做出的选择
+    reply_decision: Cell<Option<confirm::Decision>>,
+}
+
+/// 附加到通知上的 diff 文件信息
+struct DiffAttachment {
+    diff_path: PathBuf,
+    file_path: PathBuf,
+    is_preview: bool,
+    lines_changed: usize,
+    revert: bool,
+    first_change_line: Option<usize>,
+    change_description: Option<String>,
+    full_diff_path: Option<PathBuf>,
+    snippet: Option<String>,
+    /// 重命名/移动前的路径
+    renamed_from: Option<String>,
+}
+
+impl DiffAttachment {
+    fn new(diff_path: PathBuf, file_path: PathBuf, stats: &DiffStats) -> Self {
+        Self {
+            diff_path,
+            file_path,
+            is_preview: stats.preview,
+            lines_changed: stats.added + stats.removed,
+            revert: stats.revert,
+            first_change_line: stats.first_change_line,
+            change_description: stats.change_description.clone(),
+            full_diff_path: stats.full_diff_path.clone(),
+            snippet: stats.snippet.clone(),
+            renamed_from: stats.old_path.clone(),
+        }
+    }
+}
+
+/// 会修改文件的工具，项目外/依赖文件/关注路径等信号只对它们有意义
+fn is_file_modifying_tool(tool_name: &str) -> bool {
+    matches!(
+        tool_name,
+        "Edit" | "Write" | "MultiEdit" | "NotebookEdit"
+            | "mcp__jetbrains__replace_text_in_file" | "mcp__jetbrains__create_new_file"
+    )
+}
+
+/// 同时有 PreToolUse 和 PostToolUse 通知的工具，受 notify_phase 配置控制
+fn is_dual_phase_tool(tool_name: &str) -> bool {
+    matches!(
+        tool_name,
+        "Edit" | "Write" | "MultiEdit" | "Bash" | "Task"
+            | "mcp__jetbrains__replace_text_in_file" | "mcp__jetbrains__create_new_file"
+    )
+}
+
+/// 用于匹配"同一条命令"的签名：Bash 取命令本身，其他工具取完整输入
+fn command_signature(tool_name: &str, tool_input: &Value) -> String {
+    let key = match tool_input.get("command").and_then(|v| v.as_str()) {
+        Some(command) => command.trim().to_string(),
+        None => tool_input.to_string(),
+    };
+
+    let mut hasher = Sha256::new();
+    hasher.update(tool_name.as_bytes());
+    hasher.update([0]);
+    hasher.update(key.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// 输出中最后一行非空内容，作为流式更新的进度
+fn last_output_line(tool_output: Option<&Value>) -> Option<String> {
+    let text = match tool_output? {
+        Value::String(text) => text.as_str(),
+        output => ["stdout", "output", "content"]
+            .iter()
+            .find_map(|key| output.get(key).and_then(|v| v.as_str()))?,
+    };
+
+    text.lines()
+        .rev()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(|line| truncate_display(line, 100))
+}
+
+/// Bash 的 tool_output：旧版是纯字符串，新版是 { stdout, stderr, exit_code } 对象
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct BashOutput {
+    stdout: String,
+    stderr: String,
+    #[serde(alias = "exitCode")]
+    exit_code: Option<i64>,
+}
+
+fn parse_bash_output(tool_output: Option<&Value>) -> BashOutput {
+    match tool_output {
+        Some(Value::String(text)) => BashOutput { stdout: text.clone(), ..Default::default() },
+        Some(output @ Value::Object(_)) => serde_json::from_value(output.clone()).unwrap_or_default(),
+        _ => BashOutput::default(),
+    }
+}
+
+/// WebFetch/WebSearch 的 tool_output：新版是带状态码、大小或搜索结果的对象，旧版是纯文本
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct WebOutput {
+    #[serde(alias = "status", alias = "statusCode")]
+    code: Option<u16>,
+    #[serde(alias = "codeText")]
+    code_text: Option<String>,
+    bytes: Option<usize>,
+    result: Option<String>,
+    results: Option<Value>,
+    error: Option<String>,
+}
+
+impl WebOutput {
+    fn result_count(&self) -> Option<usize> {
+        self.results.as_ref().and_then(|results| results.as_array()).map(Vec::len)
+    }
+
+    fn size(&self) -> Option<usize> {
+        self.bytes.or_else(|| self.result.as_ref().map(String::len))
+    }
+
+    /// 请求失败（超时、4xx/5xx 等）时返回原因
+    fn failure(&self) -> Option<String> {
+        if let Some(error) = self.error.as_ref().filter(|error| !error.trim().is_empty()) {
+            return Some(error.clone());
+        }
+        if let Some(code) = self.code.filter(|code| *code >= 400) {
+            return Some(format!("HTTP {} {}", code, self.code_text.as_deref().unwrap_or("")).trim_end().to_string());
+        }
+        // 纯文本输出只看第一行，避免把网页正文里的字样当成错误
+        let first_line = self.result.as_deref().filter(|_| self.code.is_none())?.lines().next()?.trim();
+        let lowered = first_line.to_lowercase();
+        (lowered.starts_with("error") || lowered.starts_with("failed") || lowered.contains("timed out")).then(|| first_line.to_string())
+    }
+}
+
+fn parse_web_output(tool_output: Option<&Value>) -> WebOutput {
+    match tool_output {
+        Some(Value::String(text)) => WebOutput { result: Some(text.clone()), ..Default::default() },
+        Some(output @ Value::Object(_)) => serde_json::from_value(output.clone()).unwrap_or_default(),
+        _ => WebOutput::default(),
+    }
+}
+
+/// 输出的前两行，用 | 连接后截断，用于通知预览
+fn output_preview(output: &str) -> String {
+    let preview = output.lines()
+        .filter(|line| !line.trim().is_empty())
+        .take(2)
+        .collect::<Vec<_>>()
+        .join(" | ");
+    truncate_display(&preview, 100)
+}
+
+/// 通知中展示的命令/操作简述
+fn describe_command(tool_name: &str, tool_input: Option<&Value>) -> String {
+    let detail = tool_input.and_then(|input| {
+        input
+            .get("command")
+            .or_else(|| input.get("file_path"))
+            .or_else(|| input.get("pathInProject"))
+            .and_then(|v| v.as_str())
+    });
+
+    match detail {
+        Some(detail) => truncate_display(detail, 80),
+        None => tool_name.to_string(),
+    }
+}
+
+/// 简单通配符匹配，只支持 `*`
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !text.starts_with(first) || !text[first.len()..].ends_with(last) {
+        return false;
+    }
+
+    let mut remaining = &text[first.len()..text.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match remaining.find(part) {
+            Some(index) => remaining = &remaining[index + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// 统一事件名的两种写法：PreToolUse / pre_tool_use -> pretooluse
+fn normalize_event_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| *c != '_' && *c != '-')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// NOTCH_EVENTS 为逗号分隔的事件白名单，未设置时处理所有事件
+fn event_enabled(event_name: &str) -> bool {
+    match std::env::var("NOTCH_EVENTS") {
+        Ok(allowed) if !allowed.trim().is_empty() => {
+            let event_name = normalize_event_name(event_name);
+            allowed
+                .split(',')
+                .map(|name| normalize_event_name(name.trim()))
+                .any(|name| name == event_name)
+        }
+        _ => true,
+    }
+}
+
+/// NOTCH_EDIT_MILESTONE=25 时每 25 次编辑提示一次，未设置或为 0 时关闭
+fn edit_milestone_interval() -> Option<u64> {
+    std::env::var("NOTCH_EDIT_MILESTONE")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .filter(|interval| *interval > 0)
+}
+
+fn model_name_from_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(model) if !model.is_empty() => Some(model.clone()),
+        Value::Object(obj) => obj
+            .get("id")
+            .or_else(|| obj.get("display_name"))
+            .and_then(|v| v.as_str())
+            .map(String::from),
+        _ => None,
+    }
+}
+
+/// claude-opus-4-1-20250805 -> Opus
+fn model_display_name(model: &str) -> String {
+    let lower = model.to_lowercase();
+    for family in ["Opus", "Sonnet", "Haiku"] {
+        if lower.contains(&family.to_lowercase()) {
+            return family.to_string();
+        }
+    }
+    model.to_string()
+}
+
+/// 第一处修改在原文件中的行号（从 1 开始），末尾追加时为最后一行 + 1
+fn first_changed_line(diff: &TextDiff<'_, '_, '_, str>) -> Option<usize> {
+    diff.ops()
+        .iter()
+        .find(|op| op.tag() != DiffTag::Equal)
+        .map(|op| op.old_range().start + 1)
+}
+
+fn content_hash(content: &str) -> String {
+    hex::encode(Sha256::digest(content.as_bytes()))
+}
+
+/// 替换 "[项目] 图标 文本" 格式标题中的图标
+fn replace_title_icon(title: &str, icon: &str) -> String {
+    let Some((project, rest)) = title.split_once("] ") else {
+        return title.to_string();
+    };
+    match rest.split_once(' ') {
+        Some((_, text)) => format!("{}] {} {}", project, icon, text),
+        None => format!("{}] {} {}", project, icon, rest),
+    }
+}
+
+/// Edit 的 replace_all：替换所有出现的位置而不只是第一处
+fn is_replace_all(tool_input: &Value) -> bool {
+    tool_input.get("replace_all").and_then(|v| v.as_bool()).unwrap_or(false)
+}
+
+/// 区分并发会话用的 emoji，按会话 id 哈希选取
+const SESSION_ACCENT_EMOJIS: &[&str] = &["🔴", "🟠", "🟡", "🟢", "🔵", "🟣", "🟤", "⚫", "🔶", "🔷", "💠", "🌀"];
+
+/// 会话的稳定强调色：色相 (0-359) 和 emoji，同一会话 id 每次结果相同
+fn session_accent(session_id: &str) -> (u16, &'static str) {
+    let digest = Sha256::digest(session_id.as_bytes());
+    let hash = u64::from_be_bytes(digest[..8].try_into().expect("sha256 digest has 32 bytes"));
+    ((hash % 360) as u16, SESSION_ACCENT_EMOJIS[(hash / 360) as usize % SESSION_ACCENT_EMOJIS.len()])
+}
+
+/// 只根据 Edit 的 old_string/new_string 估算增删行数，不读取文件；
+/// 只要计数、不需要 diff 文件时使用，结果与完整 diff 一致（替换发生在行内时同样计为 +1 -1）
+fn estimate_diff_stats(old_text: &str, new_text: &str) -> (usize, usize) {
+    // 片段末尾通常没有换行，补齐后才不会把未改动的最后一行算作修改
+    let with_newline = |text: &str| if text.is_empty() || text.ends_with('\n') { text.to_string() } else { format!("{}\n", text) };
+    let (old_text, new_text) = (with_newline(old_text), with_newline(new_text));
+    let diff = TextDiff::from_lines(&old_text, &new_text);
+    diff.iter_all_changes().fold((0, 0), |(added, removed), change| match change.tag() {
+        ChangeTag::Insert => (added + 1, removed),
+        ChangeTag::Delete => (added, removed + 1),
+        ChangeTag::Equal => (added, removed),
+    })
+}
+
+/// 文件编辑类工具按输入估算的增删行数，其他工具返回 None
+fn estimate_edit_lines(tool_name: &str, tool_input: &Value) -> Option<(usize, usize)> {
+    let text = |value: &Value, key: &str| value.get(key).and_then(|v| v.as_str()).map(String::from);
+    match tool_name {
+        "Edit" => Some(estimate_diff_stats(&text(tool_input, "old_string")?, &text(tool_input, "new_string")?)),
+        "mcp__jetbrains__replace_text_in_file" => Some(estimate_diff_stats(&text(tool_input, "oldText")?, &text(tool_input, "newText")?)),
+        "Write" => Some((text(tool_input, "content")?.lines().count(), 0)),
+        "mcp__jetbrains__create_new_file" => Some((text(tool_input, "text")?.lines().count(), 0)),
+        "MultiEdit" => Some(
+            tool_input
+                .get("edits")
+                .and_then(|v| v.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|edit| Some(estimate_diff_stats(&text(edit, "old_string")?, &text(edit, "new_string")?)))
+                .fold((0, 0), |(a, r), (added, removed)| (a + added, r + removed)),
+        ),
+        _ => None,
+    }
+}
+
+/// Bash 命令的分类结果
+struct BashClassification {
+    should_notify: bool,
+    priority: u8,
+    icon: &'static str,
+    /// 标题中代替"执行命令"的简述，如 "make test"
+    label: Option<String>,
+}
+
+/// 构建工具的目标：make/just/task 取第一个目标，cargo 取子命令及其参数
+fn parse_build_target(command: &str) -> Option<(String, String)> {
+    let segment = command.split(['&', ';', '|']).next()?;
+    let mut tokens = segment.split_whitespace();
+    let runner = tokens.next()?;
+
+    let target = match runner {
+        "make" | "just" | "task" => {
+            let mut target = None;
+            while let Some(token) = tokens.next() {
+                // 带参数的选项（make -C dir / -f file）
+                if matches!(token, "-C" | "-f" | "--directory" | "--file" | "--justfile" | "--taskfile") {
+                    tokens.next();
+                } else if !token.starts_with('-') && !token.contains('=') {
+                    target = Some(token.to_string());
+                    break;
+                }
+            }
+            target?
+        }
+        "cargo" => tokens.take(3).collect::<Vec<_>>().join(" "),
+        _ => return None,
+    };
+
+    (!target.is_empty()).then(|| (runner.to_string(), target))
+}
+
+/// 权限修改命令（chmod/chown/chattr）的解析结果
+struct PermissionChange {
+    tool: String,
+    mode: String,
+    targets: Vec<String>,
+    recursive: bool,
+}
+
+impl PermissionChange {
+    /// 所有人可写（777、o+w、a+w）
+    fn world_writable(&self) -> bool {
+        if self.tool != "chmod" {
+            return false;
+        }
+        if self.mode.chars().all(|c| c.is_ascii_digit()) {
+            return self.mode.chars().last().and_then(|c| c.to_digit(8)).is_some_and(|others| others & 2 != 0);
+        }
+        self.mode.split(',').any(|clause| {
+            let (who, perms) = clause.split_once(['+', '=']).unwrap_or(("", ""));
+            (who.contains('o') || who.contains('a')) && perms.contains('w')
+        })
+    }
+}
+
+fn parse_permission_change(command: &str) -> Option<PermissionChange> {
+    let segment = command.split(['&', ';', '|']).next()?;
+    let mut tokens = segment.split_whitespace().peekable();
+    if tokens.peek() == Some(&"sudo") {
+        tokens.next();
+    }
+    let tool = tokens.next().filter(|tool| matches!(*tool, "chmod" | "chown" | "chattr"))?;
+
+    let mut recursive = false;
+    let mut mode = None;
+    let mut targets = Vec::new();
+    for token in tokens {
+        if token == "-R" || token == "--recursive" || (token.starts_with('-') && token[1..].contains('R')) {
+            recursive = true;
+        } else if token.starts_with('-') && mode.is_none() && !(tool == "chmod" && token[1..].chars().all(|c| "rwxXst".contains(c))) {
+            // 其他选项（-v、-f 等）；chmod -x 这类是权限而不是选项
+            continue;
+        } else if mode.is_none() {
+            mode = Some(token.to_string());
+        } else {
+            targets.push(token.trim_matches(|c| c == '"' || c == '\'').to_string());
+        }
+    }
+
+    Some(PermissionChange { tool: tool.to_string(), mode: mode?, targets, recursive })
+}
+
+/// `git push --force` 类命令的解析结果
+struct ForcePush {
+    /// --force-with-lease：远端被别人更新过时会拒绝，风险较低
+    with_lease: bool,
+    remote: Option<String>,
+    branch: Option<String>,
+}
+
+impl ForcePush {
+    /// "origin main"，未指定时为空
+    fn target(&self) -> String {
+        [self.remote.as_deref(), self.branch.as_deref()].into_iter().flatten().collect::<Vec<_>>().join(" ")
+    }
+}
+
+fn parse_force_push(command: &str) -> Option<ForcePush> {
+    command.split(['&', ';', '|']).find_map(|segment| {
+        let tokens: Vec<&str> = segment.split_whitespace().collect();
+        if tokens.len() < 2 || tokens[0] != "git" || tokens[1] != "push" {
+            return None;
+        }
+
+        let mut force = false;
+        let mut with_lease = false;
+        let mut positional = Vec::new();
+        for token in &tokens[2..] {
+            if token.starts_with("--force-with-lease") || token.starts_with("--force-if-includes") {
+                with_lease = true;
+            } else if *token == "--force" || (token.starts_with('-') && !token.starts_with("--") && token.contains('f')) {
+                force = true;
+            } else if !token.starts_with('-') {
+                positional.push(token.to_string());
+            }
+        }
+        // refspec "+main" 同样是强制推送
+        if positional.get(1).is_some_and(|refspec| refspec.starts_with('+')) {
+            force = true;
+        }
+        if !force && !with_lease {
+            return None;
+        }
+
+        let mut positional = positional.into_iter();
+        Some(ForcePush {
+            // 同时给了 --force 和 --force-with-lease 时，git 以 --force 为准
+            with_lease: with_lease && !force,
+            remote: positional.next(),
+            branch: positional.next().map(|refspec| refspec.trim_start_matches('+').to_string()),
+        })
+    })
+}
+
+/// 会修改数据库结构的迁移命令前缀
+const MIGRATION_COMMANDS: &[&str] = &[
+    "alembic upgrade",
+    "alembic downgrade",
+    "rails db:",
+    "bin/rails db:",
+    "rake db:",
+    "prisma migrate",
+    "npx prisma migrate",
+    "diesel migration",
+    "sqlx migrate",
+    "python manage.py migrate",
+    "flyway ",
+    "knex migrate:",
+    "sequelize db:migrate",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MigrationDirection {
+    Up,
+    Down,
+}
+
+/// 识别迁移命令（内置前缀加配置的 extra），并尽量判断方向；无法判断时为 None
+fn parse_migration(command: &str, extra: &[String]) -> Option<Option<MigrationDirection>> {
+    let command = command.trim_start();
+    let matched = MIGRATION_COMMANDS
+        .iter()
+        .copied()
+        .chain(extra.iter().map(String::as_str))
+        .any(|prefix| command.starts_with(prefix));
+    if !matched {
+        return None;
+    }
+
+    // 前缀本身也参与判断，如 "alembic downgrade"、"rails db:rollback"
+    let segment = command.split(['&', ';', '|']).next().unwrap_or(command);
+    let words: Vec<String> = segment
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .map(|word| word.to_ascii_lowercase())
+        .collect();
+    let has = |candidates: &[&str]| words.iter().any(|word| candidates.contains(&word.as_str()));
+
+    let direction = if has(&["downgrade", "down", "rollback", "revert", "undo", "redo", "reset"]) {
+        Some(MigrationDirection::Down)
+    } else if has(&["upgrade", "up", "migrate", "run", "deploy", "dev"]) {
+        Some(MigrationDirection::Up)
+    } else {
+        None
+    };
+    Some(direction)
+}
+
+/// 构建目标的优先级：发布类提高，清理类降低
+fn build_target_priority(target: &str) -> u8 {
+    let name = target.split_whitespace().next().unwrap_or(target);
+    match name {
+        "deploy" | "release" | "publish" | "install" => 2,
+        "clean" | "fmt" | "format" | "lint" | "check" => 0,
+        _ => 1,
+    }
+}
+
+/// 代理转发时 hook 事件可能包在更大的 JSON 里，按 JSON Pointer 取出；没有指定时整个输入就是事件
+fn unwrap_event(value: Value, event_json_pointer: Option<&str>) -> Result<Value> {
+    let Some(pointer) = event_json_pointer else {
+        return Ok(value);
+    };
+    let nested = value
+        .pointer(pointer)
+        .with_context(|| format!("JSON pointer {} not found in input", pointer))?;
+    if !nested.is_object() {
+        anyhow::bail!("JSON pointer {} does not point to an object", pointer);
+    }
+    Ok(nested.clone())
+}
+
+/// 设置了 path_prefix 且 path_prefix_project_name 时，通知中显示的子项目名（前缀的最后一级目录）
+fn subproject_name(config: &Config) -> Option<&str> {
+    let prefix = config.path_prefix.as_deref().filter(|_| config.path_prefix_project_name)?;
+    Path::new(prefix).file_name()?.to_str()
+}
+
+/// 环境配置类命令（source/export/nvm 等），本身没有可关注的结果
+const ENV_SETUP_PREFIXES: &[&str] = &["source ", ". ", "export ", "nvm ", "conda ", "pyenv "];
+
+fn is_env_setup_command(command: &str) -> bool {
+    ENV_SETUP_PREFIXES.iter().any(|prefix| command.starts_with(prefix))
+}
+
+/// 根据命令前缀分类，suppress_env_setup 时环境配置命令不通知，否则降为最低优先级
+fn classify_bash_command(command: &str, config: &Config) -> BashClassification {
+    let (should_notify, priority, icon) = if config.is_ignored_bash_command(command) {
+        (false, 0, "")  // 忽略的命令（内置 + 配置）
+    } else if let Some(push) = parse_force_push(command).filter(|push| push.with_lease) {
+        let label = tf("force_push_lease", &[&push.target()]).trim_end().to_string();
+        return BashClassification { should_notify: true, priority: 2, icon: "🔀", label: Some(label) };
+    } else if command.starts_with("git ") {
+        (true, 2, "🔀")  // Git 操作
+    } else if command.starts_with("npm ") || command.starts_with("yarn ") || command.starts_with("pnpm ") {
+        (true, 2, "📦")  // 包管理器
+    } else if config.is_dangerous_bash_command(command) {
+        (true, 3, "⚠️")  // 危险操作（内置 + 配置）
+    } else if command.starts_with("docker ") || command.starts_with("kubectl ") {
+        (true, 2, "🐳")  // 容器操作
+    } else if let Some(change) = parse_permission_change(command) {
+        // 所有人可写或递归修改可能影响安全，单独提升到 3
+        let priority = if change.world_writable() || change.recursive { 3 } else { 1 };
+        let recursive = if change.recursive { " -R" } else { "" };
+        let label = tf("permission_change", &[&change.targets.join(" "), &change.mode, &recursive]);
+        return BashClassification { should_notify: true, priority, icon: "🔑", label: Some(label) };
+    } else if let Some((runner, target)) = parse_build_target(command) {
+        let priority = build_target_priority(&target);
+        return BashClassification { should_notify: true, priority, icon: "🔨", label: Some(format!("{} {}", runner, target)) };
+    } else if command.starts_with("make ") || command.starts_with("cargo ") || command.starts_with("go ") {
+        (true, 1, "🔨")  // 构建命令
+    } else if command.starts_with("pytest") || command.starts_with("jest") || command.starts_with("test") {
+        (true, 1, "🧪")  // 测试命令
+    } else if is_env_setup_command(command) {
+        (!config.suppress_env_setup, 0, "🌱")  // 环境配置
+    } else {
+        (true, 1, "💻")  // 其他命令
+    };
+
+    // 限制最高优先级为 2
+    BashClassification { should_notify, priority: priority.min(2), icon, label: None }
+}
+
+/// 递归删除（rm -r/-rf/--recursive）或 find -delete 这类一次删掉大量文件的命令
+fn is_mass_delete(command: &str) -> bool {
+    command
+        .split([';', '|', '&'])
+        .any(|segment| {
+            let tokens: Vec<&str> = segment.split_whitespace().collect();
+            let tokens = match tokens.first() {
+                Some(&"sudo") => &tokens[1..],
+                _ => &tokens[..],
+            };
+            match tokens.first() {
+                Some(&"rm") => tokens[1..].iter().any(|token| {
+                    *token == "--recursive"
+                        || (token.starts_with('-') && !token.starts_with("--") && token.contains(['r', 'R']))
+                }),
+                Some(&"find") => tokens.contains(&"-delete"),
+                _ => false,
+            }
+        })
+}
+
+/// 提取 `touch a b && ...` 中被 touch 的文件（跳过选项，遇到命令分隔符停止）
+fn parse_touch_targets(command: &str) -> Vec<String> {
+    let mut targets = Vec::new();
+    let mut at_command_start = true;
+    let mut in_touch = false;
+
+    for token in command.split_whitespace() {
+        if matches!(token, "&&" | "||" | ";" | "|") {
+            at_command_start = true;
+            in_touch = false;
+            continue;
+        }
+
+        let ends_command = token.ends_with(';');
+        let token = token.trim_end_matches(';');
+
+        if at_command_start {
+            in_touch = token == "touch";
+        } else if in_touch && !token.starts_with('-') {
+            let target = token.trim_matches(|c| c == '"' || c == '\'');
+            if !target.is_empty() {
+                targets.push(target.to_string());
+            }
+        }
+
+        at_command_start = ends_command;
+        if ends_command {
+            in_touch = false;
+        }
+    }
+
+    targets
 }
 
 impl NotchHook {
-    fn new() -> Result<Self> {
+    /// socket_path 为 --socket-path 参数，优先于 NOTCH_SOCKET_PATH 和默认位置
+    fn new(socket_path: Option<PathBuf>, dry_run: bool) -> Result<Self> {
         // 优先使用 CLAUDE_PROJECT_DIR 环境变量，这是最可靠的项目路径
         let project_path = std::env::var("CLAUDE_PROJECT_DIR")
             .map(PathBuf::from)
             .unwrap_or_else(|_| {
-                eprintln!("[WARNING] CLAUDE_PROJECT_DIR not set, falling back to current dir");
+                warn!("CLAUDE_PROJECT_DIR not set, falling back to current dir");
                 std::env::current_dir().unwrap()
             });
         
-        eprintln!("[DEBUG] Using project path: {}", project_path.display());
+        debug!("Using project path: {}", project_path.display());
         
-        let project_name = project_path
+        let mut project_name = project_path
             .file_name()
             .and_then(|n| n.to_str())
             .unwrap_or("unknown")
@@ -99,75 +1194,477 @@ impl NotchHook {
 
         fs::create_dir_all(&diff_dir)?;
 
-        // Unix Socket 路径 - 统一使用 com.qingchang.notchnoti
+        // monorepo 子项目：NOTCH_PATH_PREFIX 优先于配置文件
+        let mut config = Config::load(&project_path);
+        if let Ok(prefix) = std::env::var("NOTCH_PATH_PREFIX") {
+            config.path_prefix = Some(prefix).filter(|p| !p.is_empty());
+        }
+        // 状态仍存放在仓库级的 diff 目录，只有显示用的项目名换成子项目
+        if let Some(name) = subproject_name(&config) {
+            project_name = name.to_string();
+        }
+
+        // Unix Socket 路径 - 默认统一使用 com.qingchang.notchnoti，开发版或测试时可覆盖
         let home_dir = dirs::home_dir()
             .context("Could not find home directory")?;
 
-        let socket_path = home_dir.join("Library/Containers/com.qingchang.notchnoti/Data/.notch.sock");
+        let socket_path = socket_path
+            .or_else(|| std::env::var_os("NOTCH_SOCKET_PATH").filter(|p| !p.is_empty()).map(PathBuf::from))
+            .unwrap_or_else(|| home_dir.join("Library/Containers/com.qingchang.notchnoti/Data/.notch.sock"));
 
         if !socket_path.exists() {
-            eprintln!("[WARNING] Unix Socket not found at: {}", socket_path.display());
-            eprintln!("[INFO] NotchNoti可能未运行，请确保应用已启动");
+            warn!("Unix Socket not found at: {}", socket_path.display());
+            warn!("NotchNoti可能未运行，请确保应用已启动（可运行 notch-hook doctor 检查）");
         } else {
-            eprintln!("[DEBUG] Found Unix Socket at: {}", socket_path.display());
+            debug!("Found Unix Socket at: {}", socket_path.display());
         }
 
-        Ok(Self {
+        Ok(Self::with_state(project_path, project_name, diff_dir, config, socket_path, dry_run))
+    }
+
+    /// 项目路径、diff 目录和配置确定之后的构造（测试中直接指向临时目录）
+    fn with_state(
+        project_path: PathBuf,
+        project_name: String,
+        diff_dir: PathBuf,
+        config: Config,
+        socket_path: PathBuf,
+        dry_run: bool,
+    ) -> Self {
+        Self {
             project_path,
             project_name,
             diff_dir,
-            socket_path,
+            socket: NotchSocket::new(socket_path),
             session_start_time: std::time::Instant::now(),
-            tool_start_times: std::collections::HashMap::new(),
-        })
+            tool_name: None,
+            tool_duration_ms: None,
+            config,
+            event_metadata: HashMap::new(),
+            session_id: "default".to_string(),
+            event_signals: ImportanceContext::default(),
+            model: std::env::var("CLAUDE_MODEL").ok().filter(|m| !m.is_empty()),
+            validate_only: false,
+            validation_results: RefCell::new(Vec::new()),
+            record_path: None,
+            captured: RefCell::new(Vec::new()),
+            git_status: OnceCell::new(),
+            dry_run: dry_run || matches!(std::env::var("NOTCH_DRY_RUN").as_deref(), Ok("1" | "true")),
+            color_diffs: matches!(std::env::var("NOTCH_DIFF_COLOR").as_deref(), Ok("1" | "true")),
+            diff_context: diff_context_from_env(),
+            show_snippet: matches!(std::env::var("NOTCH_SHOW_SNIPPET").as_deref(), Ok("1" | "true")),
+            timing_metrics: metrics::timing_enabled(),
+            min_priority: min_priority_from_env(),
+            edit_milestone: edit_milestone_interval(),
+            reply_decision: Cell::new(None),
+        }
     }
 
-    fn process_hook_event(mut self) -> Result<()> {
+    fn process_hook_event(mut self, event_json_pointer: Option<&str>) -> Result<()> {
         let mut input = String::new();
         io::stdin().read_to_string(&mut input)?;
+        self.process_input(&input, event_json_pointer)?;
+
+        if self.validate_only {
+            self.report_validation()?;
+        }
+
+        Ok(())
+    }
+
+    fn process_input(&mut self, input: &str, event_json_pointer: Option<&str>) -> Result<()> {
+        // 通常是单个 JSON 对象；部分转发工具会一次写入多行 NDJSON，按顺序逐个处理，空白行自然跳过
+        for (index, value) in serde_json::Deserializer::from_str(input).into_iter::<Value>().enumerate() {
+            let value = value.with_context(|| format!("Invalid hook event #{}", index + 1))?;
+            let event_value = unwrap_event(value, event_json_pointer)?;
+            let event: HookEvent = serde_json::from_value(event_value.clone())?;
+            if let Some(path) = &self.record_path {
+                cassette::append(path, cassette::Record::Event(event_value))?;
+            }
+            if !self.timing_metrics {
+                self.handle_event(&event)?;
+                continue;
+            }
 
-        let event: HookEvent = serde_json::from_str(&input)?;
+            let started = std::time::Instant::now();
+            let monotonic_ns = metrics::monotonic_ns();
+            let result = self.handle_event(&event);
+            let (socket_time, socket_sends) = self.socket.take_write_time();
+            metrics::append_timing(
+                &self.diff_dir,
+                &metrics::Timing {
+                    pid: std::process::id(),
+                    monotonic_ns,
+                    at: state::now_millis(),
+                    event: &event.hook_event_name,
+                    tool: event.tool_name.as_deref(),
+                    total_ms: metrics::millis(started.elapsed()),
+                    socket_ms: metrics::millis(socket_time),
+                    socket_sends,
+                },
+            );
+            result?;
+        }
+        Ok(())
+    }
 
+    /// 处理单个 hook 事件：准备事件级上下文后分发到各 handler
+    fn handle_event(&mut self, event: &HookEvent) -> Result<()> {
         // 记录调试信息
-        eprintln!(
-            "[DEBUG] Hook Event: {}, Tool: {}",
+        debug!(
+            "Hook Event: {}, Tool: {}",
             event.hook_event_name,
             event.tool_name.as_deref().unwrap_or("unknown")
         );
 
+        if !event_enabled(&event.hook_event_name) {
+            debug!("Event {} not in NOTCH_EVENTS, ignoring", event.hook_event_name);
+            return Ok(());
+        }
+
+        if let Some(session_id) = &event.session_id {
+            self.session_id = session_id.clone();
+        }
+
+        if let Some(model) = event.model.as_ref().and_then(model_name_from_value) {
+            self.model = Some(model);
+        }
+
+        self.event_metadata = self.passthrough_metadata(event);
+        if let Some(model) = &self.model {
+            self.event_metadata.insert("model".to_string(), model.clone());
+        }
+        self.event_signals = self.collect_event_signals(event);
+        if self.touches_test_file(event) {
+            self.event_metadata.insert("category".to_string(), "tests".to_string());
+        }
+        if let Some(tokens) = self.estimate_event_tokens(event) {
+            self.event_metadata.insert("token_estimate".to_string(), tokens.to_string());
+        }
+
+        let is_notification = matches!(event.hook_event_name.as_str(), "Notification" | "notification");
+        let idle_ms = if is_notification { None } else { self.take_idle_gap() };
+        if let Some(idle_ms) = idle_ms {
+            self.event_metadata.insert("idle_ms".to_string(), idle_ms.to_string());
+            self.event_metadata.insert("idle".to_string(), format!("⏱️ {}", tf("waited", &[&timefmt::format_duration(idle_ms)])));
+        }
+
+        self.tool_name = event.tool_name.clone();
+        self.tool_duration_ms = self.track_tool_timing(event);
+        if let Some(duration_ms) = self.tool_duration_ms {
+            self.event_metadata.insert("duration_ms".to_string(), duration_ms.to_string());
+        }
+
+        // 会话结束或用户刚空闲回来，是补发攒着的低优先级通知的时机
+        let is_stop = matches!(event.hook_event_name.as_str(), "Stop" | "stop");
+        if is_stop || idle_ms.is_some_and(|ms| ms >= self.config.defer_flush_idle_secs * 1000) {
+            self.flush_deferred();
+        }
+
         // 支持两种命名格式: PascalCase 和 snake_case
         match event.hook_event_name.as_str() {
-            "PreToolUse" | "pre_tool_use" => self.handle_pre_tool_use(&event)?,
-            "PostToolUse" | "post_tool_use" => self.handle_post_tool_use(&event)?,
-            "Stop" | "stop" => self.handle_stop()?,
+            "PreToolUse" | "pre_tool_use" => self.handle_pre_tool_use(event)?,
+            "PostToolUse" | "post_tool_use" => self.handle_post_tool_use(event)?,
+            "Stop" | "stop" => self.handle_stop(event)?,
+            "SubagentStop" | "subagent_stop" => self.handle_subagent_stop(event)?,
             "Notification" | "notification" => self.handle_notification()?,
             "SessionStart" | "session_start" => self.handle_session_start()?,
-            "UserPromptSubmit" | "user_prompt_submit" => self.handle_user_prompt_submit(&event)?,
+            "SessionEnd" | "session_end" => self.handle_session_end(event)?,
+            "UserPromptSubmit" | "user_prompt_submit" => self.handle_user_prompt_submit(event)?,
             "PreCompact" | "pre_compact" => self.handle_pre_compact()?,
             _ => {
-                eprintln!("[DEBUG] Unhandled event: {}", event.hook_event_name);
+                debug!("Unhandled event: {}", event.hook_event_name);
             }
         }
 
+        if let Some(idle_ms) = idle_ms.filter(|ms| self.config.idle_notify_secs > 0 && *ms >= self.config.idle_notify_secs * 1000) {
+            self.send_notification(
+                format!("[{}] ⏱️ {}", self.project_name, tf("waited", &[&timefmt::format_duration(idle_ms)])),
+                t("resumed_after_wait").to_string(),
+                NotificationType::Info,
+                0,
+            )?;
+        }
+
         Ok(())
     }
 
+    /// 取出上次 Notification 以来 Claude 等待用户的时长（毫秒）
+    fn take_idle_gap(&self) -> Option<u64> {
+        if !self.session_state_path().exists() {
+            return None;
+        }
+        let now = state::now_millis();
+        self.update_session_state(|session| session.waiting_since.take())
+            .flatten()
+            .map(|since| now.saturating_sub(since))
+    }
+
+    /// 汇总 --validate-only 的结果，有违规时返回错误让进程以非零退出
+    fn report_validation(&self) -> Result<()> {
+        let results = self.validation_results.borrow();
+        let invalid = results.iter().filter(|errors| !errors.is_empty()).count();
+
+        eprintln!("[VALIDATE] {} notification(s) built, {} invalid", results.len(), invalid);
+        if invalid > 0 {
+            anyhow::bail!("{} notification(s) violate the NotchNoti schema", invalid);
+        }
+        Ok(())
+    }
+
+    /// 按配置把 tool_input 中的指定字段原样复制到 metadata（前缀 input_）
+    fn passthrough_metadata(&self, event: &HookEvent) -> HashMap<String, String> {
+        let mut metadata = HashMap::new();
+
+        let (Some(tool_name), Some(tool_input)) = (&event.tool_name, &event.tool_input) else {
+            return metadata;
+        };
+        let Some(fields) = self.config.passthrough_fields.get(tool_name) else {
+            return metadata;
+        };
+
+        for field in fields.iter().take(MAX_PASSTHROUGH_FIELDS) {
+            if let Some(value) = tool_input.get(field) {
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                metadata.insert(
+                    format!("input_{}", field),
+                    truncate_display(&value, MAX_PASSTHROUGH_VALUE_LEN),
+                );
+            }
+        }
+
+        metadata
+    }
+
+    /// 收集与具体通知无关、只取决于事件本身的重要性信号
+    fn collect_event_signals(&self, event: &HookEvent) -> ImportanceContext {
+        let mut signals = ImportanceContext::default();
+
+        let (Some(tool_name), Some(tool_input)) = (event.tool_name.as_deref(), event.tool_input.as_ref()) else {
+            return signals;
+        };
+
+        signals.dangerous = self.is_dangerous_operation(tool_name, &event.tool_input).unwrap_or(false);
+
+        let modified_path = if is_file_modifying_tool(tool_name) { self.extract_file_path(tool_name, tool_input) } else { Ok(None) };
+        if let Ok(Some(file_path)) = modified_path {
+            let relative_path = self.project_relative_path(&file_path).to_string_lossy();
+            signals.watched_path = self
+                .config
+                .watch_paths
+                .iter()
+                .any(|watched| relative_path.starts_with(watched.as_str()) || file_path.starts_with(watched));
+            signals.dependency_change = importance::is_dependency_file(&file_path);
+            signals.outside_project = self.is_outside_project(&file_path);
+        }
+
+        if let Some(command) = tool_input.get("command").and_then(|v| v.as_str()) {
+            signals.dependency_change |= importance::is_dependency_command(command);
+        }
+
+        signals
+    }
+
+    /// 解析符号链接后不在项目目录下（文件可能还不存在，此时解析其父目录）
+    fn is_outside_project(&self, file_path: &Path) -> bool {
+        let resolve = |path: &Path| {
+            path.canonicalize()
+                .ok()
+                .or_else(|| Some(path.parent()?.canonicalize().ok()?.join(path.file_name()?)))
+                .unwrap_or_else(|| path.to_path_buf())
+        };
+        !resolve(file_path).starts_with(resolve(&self.project_path))
+    }
+
+    /// diff_extensions 为空时所有文件都生成 diff，否则只有列出的扩展名
+    fn diff_enabled_for(&self, file_path: &Path) -> bool {
+        if self.config.diff_extensions.is_empty() {
+            return true;
+        }
+        file_path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| self.config.diff_extensions.iter().any(|allowed| allowed.trim_start_matches('.').eq_ignore_ascii_case(ext)))
+    }
+
+    /// 按 test_patterns 判断是否为测试文件：以 / 结尾的匹配目录，其他按文件名通配
+    fn is_test_file(&self, file_path: &Path) -> bool {
+        let relative_path = self.get_relative_path(file_path);
+        let file_name = file_path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+
+        self.config.test_patterns.iter().any(|pattern| match pattern.strip_suffix('/') {
+            Some(dir) => relative_path.split('/').rev().skip(1).any(|component| component == dir),
+            None => wildcard_match(pattern, file_name),
+        })
+    }
+
+    fn touches_test_file(&self, event: &HookEvent) -> bool {
+        let (Some(tool_name), Some(tool_input)) = (event.tool_name.as_deref(), event.tool_input.as_ref()) else {
+            return false;
+        };
+        if !matches!(tool_name, "Edit" | "Write" | "MultiEdit") {
+            return false;
+        }
+
+        matches!(self.extract_file_path(tool_name, tool_input), Ok(Some(path)) if self.is_test_file(&path))
+    }
+
+    /// 估算本次读取/修改对上下文的 token 消耗（需开启 show_token_estimate）
+    fn estimate_event_tokens(&self, event: &HookEvent) -> Option<usize> {
+        if !self.config.show_token_estimate {
+            return None;
+        }
+
+        let tool_name = event.tool_name.as_deref()?;
+        let tool_input = event.tool_input.as_ref()?;
+
+        match tool_name {
+            "Read" => {
+                let path = self.extract_file_path(tool_name, tool_input).ok()??;
+                let size = fs::metadata(path).ok()?.len();
+                Some(estimate_tokens_from_bytes(size as usize))
+            }
+            "MultiEdit" => {
+                let edits = tool_input.get("edits").and_then(|v| v.as_array())?;
+                Some(
+                    edits
+                        .iter()
+                        .flat_map(|edit| ["old_string", "new_string"].map(|key| edit.get(key)))
+                        .flatten()
+                        .filter_map(|v| v.as_str())
+                        .map(estimate_tokens)
+                        .sum(),
+                )
+            }
+            "Edit" | "Write" => {
+                let (old_text, new_text) = self.extract_text_content(tool_name, tool_input).ok()?;
+                if old_text.is_none() && new_text.is_none() {
+                    return None;
+                }
+                Some(
+                    old_text.as_deref().map_or(0, estimate_tokens)
+                        + new_text.as_deref().map_or(0, estimate_tokens),
+                )
+            }
+            _ => None,
+        }
+    }
+
+    /// PreToolUse 记录开始时间，最终的 PostToolUse 取出并返回耗时；没有对应的 Pre 时返回 None
+    fn track_tool_timing(&self, event: &HookEvent) -> Option<u64> {
+        let tool_name = event.tool_name.as_deref()?;
+        let key = event.tool_use_id.clone().unwrap_or_else(|| tool_name.to_string());
+        let now = state::now_millis();
+
+        match event.hook_event_name.as_str() {
+            "PreToolUse" | "pre_tool_use" => {
+                self.update_session_state(|session| {
+                    // 被拒绝或中断的调用不会有 Post，清理掉一小时前的记录
+                    session.tool_started_at.retain(|_, started| now.saturating_sub(*started) < 60 * 60 * 1000);
+                    session.tool_started_at.insert(key, now);
+                });
+                None
+            }
+            "PostToolUse" | "post_tool_use" if event.is_partial != Some(true) => {
+                if !self.session_state_path().exists() {
+                    return None;
+                }
+                self.update_session_state(|session| session.tool_started_at.remove(&key))
+                    .flatten()
+                    .map(|started| now.saturating_sub(started))
+            }
+            _ => None,
+        }
+    }
+
+    /// 完成通知标题后的耗时，如 " (耗时 2.3s)"
+    fn duration_suffix(&self) -> String {
+        match self.tool_duration_ms {
+            Some(duration_ms) => tf("took", &[&timefmt::format_elapsed(duration_ms)]),
+            None => String::new(),
+        }
+    }
+
+    /// 大操作在消息末尾附加 token 估算
+    fn token_suffix(&self, event: &HookEvent) -> String {
+        match self.estimate_event_tokens(event) {
+            Some(tokens) if tokens >= TOKEN_ESTIMATE_DISPLAY_THRESHOLD => {
+                format!(" [{}]", format_token_estimate(tokens))
+            }
+            _ => String::new(),
+        }
+    }
+
     fn handle_pre_tool_use(&self, event: &HookEvent) -> Result<()> {
         let tool_name = event.tool_name.as_deref().unwrap_or("");
 
+        // 新建文件和命令历史的记录与通知阶段无关，放在阶段过滤之前
+        self.track_created_files(tool_name, event);
+        self.record_command(tool_name, event);
+        self.track_debug_session(tool_name);
+        if self.config.coalesce_noise_tools && !coalesce::is_noise_tool(tool_name) {
+            coalesce::reset(&self.noise_state_path());
+        }
+
+        // enforce_dangerous 时直接拒绝危险操作，只提示一次已阻止，不再发危险提醒和工具通知；
+        // 否则可以等待用户在刘海中确认，拒绝时阻止工具执行
+        if self.enforcing_dangerous() {
+            let details = self.format_operation_details(tool_name, &event.tool_input);
+            println!("{}", confirm::deny_output(&tf("blocked_dangerous", &[&details])));
+
+            let mut metadata = HashMap::new();
+            metadata.insert("blocked".to_string(), "true".to_string());
+            metadata.insert("tool_name".to_string(), tool_name.to_string());
+            return self.send_notification_with_metadata(
+                format!("[{}] 🛑 {}", self.project_name, t("dangerous_blocked")),
+                details,
+                NotificationType::Security,
+                3,
+                metadata,
+            );
+        } else if self.config.confirm_dangerous && self.event_signals.dangerous {
+            return self.confirm_dangerous_operation(tool_name, event);
+        }
+
+        // 危险操作先单独提醒一次，不受 notify_phase 影响，之后照常走各工具的处理
+        if self.event_signals.dangerous {
+            let mut metadata = HashMap::new();
+            metadata.insert("dangerous".to_string(), "true".to_string());
+            metadata.insert("tool_name".to_string(), tool_name.to_string());
+
+            self.send_notification_with_metadata(
+                format!("[{}] ⚠️ {}", self.project_name, t("dangerous_operation")),
+                self.format_operation_details(tool_name, &event.tool_input),
+                NotificationType::Warning,
+                3,
+                metadata,
+            )?;
+        }
+
+        if is_dual_phase_tool(tool_name) && !self.config.notify_phase.allows_pre() {
+            debug!("Skipping PreToolUse notification for {} (notify_phase = post)", tool_name);
+            return Ok(());
+        }
+
         // 根据工具类型选择合适的通知类型
         let _notification_type = match tool_name {
-            "Edit" | "MultiEdit" | "Write" => "tool_use",
-            "Bash" => "warning",
-            "Task" => "ai",
-            "Read" | "Grep" | "Glob" | "LS" => "info",
-            "WebFetch" | "WebSearch" => "download",
-            "TodoWrite" => "reminder",
-            _ if tool_name.starts_with("mcp__") => "sync",
-            _ => "info",
+            "Edit" | "MultiEdit" | "Write" => NotificationType::ToolUse,
+            "Bash" => NotificationType::Warning,
+            "Task" => NotificationType::Ai,
+            "Read" | "Grep" | "Glob" | "LS" => NotificationType::Info,
+            "WebFetch" | "WebSearch" => NotificationType::Download,
+            "TodoWrite" => NotificationType::Reminder,
+            _ if tool_name.starts_with("mcp__") => NotificationType::Sync,
+            _ => NotificationType::Info,
         };
         
         match tool_name {
+            "NotebookEdit" => {
+                self.handle_notebook_edit(tool_name, event)?;
+            }
             "MultiEdit" => {
                 // MultiEdit 特殊处理：显示批量修改数量
                 if let Some(tool_input) = &event.tool_input {
@@ -175,22 +1672,44 @@ impl NotchHook {
                         let relative_path = self.get_relative_path(&file_path);
                         
                         // 提取 edits 数组的长度
-                        let edits_count = if let Some(edits) = tool_input.get("edits").and_then(|v| v.as_array()) {
-                            edits.len()
-                        } else {
-                            0
-                        };
+                        let edits = tool_input.get("edits").and_then(|v| v.as_array());
+                        let edits_count = edits.map_or(0, |edits| edits.len());
+
+                        // 依次应用所有修改，生成一份合并的 diff
+                        if let Some(edits) = edits.filter(|_| self.config.preview_diffs && self.diff_enabled_for(&file_path)) {
+                            if let Ok((diff_path, stats)) = self.generate_multi_edit_preview_diff(&file_path, edits) {
+                                let message = tf(
+                                    "multi_edit_estimate",
+                                    &[&relative_path, &edits_count, &self.format_stats(&stats), &self.token_suffix(event)],
+                                );
+
+                                self.send_notification_with_diff(
+                                    format!("[{}] 📝 {}", self.project_name, t("multi_edit")),
+                                    message,
+                                    NotificationType::ToolUse,
+                                    2,
+                                    DiffAttachment::new(diff_path, file_path.clone(), &stats),
+                                    tool_name,
+                                )?;
+                                return Ok(());
+                            }
+                        }
                         
+                        let (added, removed) = estimate_edit_lines(tool_name, tool_input).unwrap_or((0, 0));
+
                         let message = if edits_count > 0 {
-                            format!("{} (批量修改 {} 处)", relative_path, edits_count)
+                            tf(
+                                "multi_edit_estimate",
+                                &[&relative_path, &edits_count, &self.format_line_stats(added, removed), &self.token_suffix(event)],
+                            )
                         } else {
-                            format!("{} (批量修改)", relative_path)
+                            tf("multi_edit_plain", &[&relative_path, &self.token_suffix(event)])
                         };
                         
                         self.send_notification(
-                            format!("[{}] 📝 批量修改", self.project_name),
+                            format!("[{}] 📝 {}", self.project_name, t("multi_edit")),
                             message,
-                            "tool_use",
+                            NotificationType::ToolUse,
                             2,
                         )?;
                     }
@@ -205,18 +1724,17 @@ impl NotchHook {
                     // 尝试生成diff预览
                     if let Some(ref file_path) = file_path {
                         // 只有当有old_text和new_text时才生成diff
-                        if old_text.is_some() && new_text.is_some() {
-                            if let Ok((diff_path, stats)) = self.generate_preview_diff(file_path, old_text.as_deref(), new_text.as_deref()) {
+                        if old_text.is_some() && new_text.is_some() && self.diff_enabled_for(file_path) {
+                            if let Ok((diff_path, stats)) = self.generate_preview_diff(file_path, old_text.as_deref(), new_text.as_deref(), false) {
                                 let relative_path = self.get_relative_path(file_path);
-                                let message = format!("{} (预计 +{} -{})", relative_path, stats.added, stats.removed);
+                                let message = tf("estimate", &[&relative_path, &self.format_stats(&stats)]);
                                 
                                 self.send_notification_with_diff(
-                                    format!("[{}] ✏️ JetBrains IDE 修改", self.project_name),
+                                    format!("[{}] ✏️ {}", self.project_name, t("jb_edit")),
                                     message,
-                                    "sync",
+                                    NotificationType::Sync,
                                     2,
-                                    Some(diff_path),
-                                    Some(file_path.clone()),
+                                    DiffAttachment::new(diff_path, file_path.clone(), &stats),
                                     tool_name,
                                 )?;
                                 return Ok(());
@@ -228,9 +1746,9 @@ impl NotchHook {
                     if let Some(file_path) = file_path {
                         let relative_path = self.get_relative_path(&file_path);
                         self.send_notification(
-                            format!("[{}] ✏️ JetBrains IDE 修改", self.project_name),
+                            format!("[{}] ✏️ {}", self.project_name, t("jb_edit")),
                             relative_path,
-                            "sync",
+                            NotificationType::Sync,
                             2,
                         )?;
                     }
@@ -243,9 +1761,9 @@ impl NotchHook {
                     if let Some(file_path) = file_path {
                         let relative_path = self.get_relative_path(&file_path);
                         self.send_notification(
-                            format!("[{}] 🆕 JetBrains 创建文件", self.project_name),
+                            format!("[{}] 🆕 {}", self.project_name, t("jb_create_file")),
                             relative_path,
-                            "sync",
+                            NotificationType::Sync,
                             2,
                         )?;
                     }
@@ -256,19 +1774,19 @@ impl NotchHook {
                     let target = tool_input.get("symbol")
                         .or_else(|| tool_input.get("query"))
                         .and_then(|v| v.as_str())
-                        .unwrap_or("未知目标");
+                        .unwrap_or(t("jb_unknown_target"));
                     
                     let (icon, action) = match tool_name {
-                        "mcp__jetbrains__navigate_to_definition" => ("🎯", "跳转定义"),
-                        "mcp__jetbrains__find_usages" => ("🔗", "查找引用"),
-                        "mcp__jetbrains__search_everywhere" => ("🌐", "全局搜索"),
-                        _ => ("🔍", "搜索"),
+                        "mcp__jetbrains__navigate_to_definition" => ("🎯", t("jb_navigate")),
+                        "mcp__jetbrains__find_usages" => ("🔗", t("jb_find_usages")),
+                        "mcp__jetbrains__search_everywhere" => ("🌐", t("jb_search_everywhere")),
+                        _ => ("🔍", t("jb_search")),
                     };
                     
                     self.send_notification(
                         format!("[{}] {} JetBrains {}", self.project_name, icon, action),
-                        target.chars().take(80).collect::<String>(),
-                        "sync",
+                        truncate_display(target, 80),
+                        NotificationType::Sync,
                         1,
                     )?;
                 }
@@ -277,18 +1795,18 @@ impl NotchHook {
                 if let Some(tool_input) = &event.tool_input {
                     let config_name = tool_input.get("configuration")
                         .and_then(|v| v.as_str())
-                        .unwrap_or("默认配置");
+                        .unwrap_or(t("jb_default_configuration"));
                     
                     let (icon, action) = if tool_name.contains("debug") {
-                        ("🐞", "调试")
+                        ("🐞", t("jb_debug"))
                     } else {
-                        ("▶️", "运行")
+                        ("▶️", t("jb_run"))
                     };
                     
                     self.send_notification(
                         format!("[{}] {} JetBrains {}", self.project_name, icon, action),
                         config_name.to_string(),
-                        "sync",
+                        NotificationType::Sync,
                         2,
                     )?;
                 }
@@ -298,19 +1816,70 @@ impl NotchHook {
                     let file_path = self.extract_file_path(tool_name, tool_input)?;
                     let (old_text, new_text) = self.extract_text_content(tool_name, tool_input)?;
                     
+                    // 测试文件单独用 🧪 标识，方便区分测试和实现代码的修改
+                    let is_test = file_path.as_deref().is_some_and(|path| self.is_test_file(path));
+
+                    // replace_all 时标注实际会替换的处数
+                    let replace_all = tool_name == "Edit" && is_replace_all(tool_input);
+                    let occurrences = match (&file_path, old_text.as_deref()) {
+                        (Some(path), Some(old)) if replace_all && !old.is_empty() => {
+                            fs::read_to_string(path).map(|content| content.matches(old).count()).unwrap_or(1)
+                        }
+                        _ => 1,
+                    };
+                    let scope = if replace_all { tf("replace_all_scope", &[&occurrences]) } else { String::new() };
+
+                    // 大文件/二进制内容不做 diff，提醒可能让仓库膨胀
+                    if let (Some(file_path), Some(size)) = (&file_path, large_write_size(tool_name, tool_input, self.config.large_write_bytes)) {
+                        let mut metadata = HashMap::new();
+                        metadata.insert("large_write".to_string(), "true".to_string());
+                        metadata.insert("size_bytes".to_string(), size.to_string());
+
+                        self.send_notification_with_metadata(
+                            format!("[{}] 📦 {}", self.project_name, tf("large_write", &[&format_size(size)])),
+                            self.get_relative_path(file_path),
+                            NotificationType::Warning,
+                            2,
+                            metadata,
+                        )?;
+                        return Ok(());
+                    }
+
                     // 生成预览diff
-                    if let Some(ref file_path) = file_path {
-                        if let Ok((diff_path, stats)) = self.generate_preview_diff(file_path, old_text.as_deref(), new_text.as_deref()) {
+                    if let Some(file_path) = file_path.as_ref().filter(|path| self.config.preview_diffs && self.diff_enabled_for(path)) {
+                        if let Ok((diff_path, stats)) = self.generate_preview_diff(file_path, old_text.as_deref(), new_text.as_deref(), replace_all) {
                             let relative_path = self.get_relative_path(file_path);
-                            let message = format!("{} (预计 +{} -{})", relative_path, stats.added, stats.removed);
+
+                            // 替换前后内容相同，没有 diff 可看
+                            if !stats.binary && stats.added == 0 && stats.removed == 0 {
+                                let metadata = HashMap::from([("no_change".to_string(), "true".to_string())]);
+                                self.send_notification_with_metadata(
+                                    format!("[{}] ✏️ {}", self.project_name, t("about_to_edit")),
+                                    tf("no_change", &[&relative_path, &self.token_suffix(event)]),
+                                    NotificationType::ToolUse,
+                                    1,
+                                    metadata,
+                                )?;
+                                return Ok(());
+                            }
+
+                            let message = tf(
+                                "estimate_scoped",
+                                &[&relative_path, &scope, &self.format_stats(&stats), &self.token_suffix(event)],
+                            );
+                            
+                            let title = if is_test {
+                                format!("[{}] 🧪 {}", self.project_name, t("about_to_edit_test"))
+                            } else {
+                                format!("[{}] ⏸️ {}", self.project_name, t("about_to_edit"))
+                            };
                             
                             self.send_notification_with_diff(
-                                format!("[{}] ⏸️ 即将修改", self.project_name),
+                                title,
                                 message,
-                                "tool_use",  // 改为 tool_use，表示工具操作而非警告
+                                NotificationType::ToolUse,  // 改为 tool_use，表示工具操作而非警告
                                 2,  // 降低优先级从 3→2
-                                Some(diff_path),
-                                Some(file_path.clone()),
+                                DiffAttachment::new(diff_path, file_path.clone(), &stats),
                                 tool_name,
                             )?;
                             return Ok(());
@@ -320,11 +1889,30 @@ impl NotchHook {
                     // 发送普通通知
                     if let Some(file_path) = file_path {
                         let relative_path = self.get_relative_path(&file_path);
-                        self.send_notification(
-                            format!("[{}] ✏️ 即将修改", self.project_name),
-                            relative_path,
-                            "tool_use",  // 改为 tool_use
+                        let title = if is_test {
+                            format!("[{}] 🧪 {}", self.project_name, t("about_to_edit_test"))
+                        } else {
+                            format!("[{}] ✏️ {}", self.project_name, t("about_to_edit"))
+                        };
+                        // 不生成 diff 时仍按 old/new 片段给出行数估算
+                        let (message, metadata) = match (old_text.as_deref(), new_text.as_deref()) {
+                            (Some(old), Some(new)) if old == new => {
+                                (tf("no_change", &[&relative_path, &self.token_suffix(event)]), HashMap::from([("no_change".to_string(), "true".to_string())]))
+                            }
+                            (Some(old), Some(new)) => {
+                                let (added, removed) = estimate_diff_stats(old, new);
+                                let (added, removed) = (added * occurrences, removed * occurrences);
+                                let metadata = HashMap::from([("lines_changed".to_string(), (added + removed).to_string())]);
+                                (tf("estimate_scoped", &[&relative_path, &scope, &self.format_line_stats(added, removed), &self.token_suffix(event)]), metadata)
+                            }
+                            _ => (format!("{}{}", relative_path, self.token_suffix(event)), HashMap::new()),
+                        };
+                        self.send_notification_with_metadata(
+                            title,
+                            message,
+                            NotificationType::ToolUse,  // 改为 tool_use
                             2,  // 降低优先级从 3→2
+                            metadata,
                         )?;
                     }
                 }
@@ -332,12 +1920,12 @@ impl NotchHook {
             "mcp__jetbrains__execute_terminal_command" => {
                 if let Some(tool_input) = &event.tool_input {
                     if let Some(command) = tool_input.get("command").and_then(|v| v.as_str()) {
-                        let cmd_preview: String = command.chars().take(80).collect();
+                        let cmd_preview = truncate_display(command, 80);
                         
                         self.send_notification(
-                            format!("[{}] 💻 JetBrains 终端", self.project_name),
-                            format!("{}", cmd_preview),
-                            "sync",
+                            format!("[{}] 💻 {}", self.project_name, t("jb_terminal")),
+                            cmd_preview,
+                            NotificationType::Sync,
                             2,
                         )?;
                     }
@@ -346,35 +1934,52 @@ impl NotchHook {
             "Bash" => {
                 if let Some(tool_input) = &event.tool_input {
                     if let Some(command) = tool_input.get("command").and_then(|v| v.as_str()) {
-                        let cmd_preview: String = command.chars().take(80).collect();
-                        
-                        // 根据命令类型分类
-                        let (should_notify, priority, icon) = if command.starts_with("git ") {
-                            (true, 2, "🔀")  // Git 操作
-                        } else if command.starts_with("npm ") || command.starts_with("yarn ") || command.starts_with("pnpm ") {
-                            (true, 2, "📦")  // 包管理器
-                        } else if command.starts_with("rm ") || command.starts_with("mv ") {
-                            (true, 3, "⚠️")  // 危险操作
-                        } else if command.starts_with("docker ") || command.starts_with("kubectl ") {
-                            (true, 2, "🐳")  // 容器操作
-                        } else if command.starts_with("make ") || command.starts_with("cargo ") || command.starts_with("go ") {
-                            (true, 1, "🔨")  // 构建命令
-                        } else if command.starts_with("pytest") || command.starts_with("jest") || command.starts_with("test") {
-                            (true, 1, "🧪")  // 测试命令
-                        } else if command.starts_with("echo") || command.starts_with("ls") || 
-                                  command.starts_with("pwd") || command.starts_with("date") ||
-                                  command.starts_with("curl localhost:9876") {
-                            (false, 0, "")  // 忽略的命令
+                        // curl/wget 命令中的 URL 按 url_privacy 处理后再展示
+                        let displayed_command = if command.starts_with("curl ") || command.starts_with("wget ") {
+                            privacy::redact_command_urls(command, self.config.url_privacy)
                         } else {
-                            (true, 1, "💻")  // 其他命令
+                            command.to_string()
                         };
+                        let cmd_preview = truncate_display(&displayed_command, 80);
                         
-                        if should_notify {
+                        let class = classify_bash_command(command, &self.config);
+
+                        if is_mass_delete(command) {
+                            self.send_critical_notification(
+                                format!("[{}] 🚨 {}", self.project_name, t("mass_delete")),
+                                cmd_preview,
+                                NotificationType::Security,
+                            )?;
+                        } else if let Some(direction) = parse_migration(command, &self.config.migration_commands) {
+                            // 回滚可能丢数据，与批量删除同级
+                            if direction == Some(MigrationDirection::Down) {
+                                self.send_critical_notification(
+                                    format!("[{}] 🗄️ {}", self.project_name, t("migration_down")),
+                                    cmd_preview,
+                                    NotificationType::Security,
+                                )?;
+                            } else {
+                                let label = if direction == Some(MigrationDirection::Up) { t("migration_up") } else { t("migration") };
+                                self.send_notification(
+                                    format!("[{}] 🗄️ {}", self.project_name, label),
+                                    cmd_preview,
+                                    NotificationType::ToolUse,
+                                    3,
+                                )?;
+                            }
+                        } else if let Some(push) = parse_force_push(command).filter(|push| !push.with_lease) {
+                            let target = push.target();
+                            self.send_critical_notification(
+                                format!("[{}] ⚠️ {}", self.project_name, t("force_push")),
+                                if target.is_empty() { cmd_preview } else { format!("{} → {}", target, cmd_preview) },
+                                NotificationType::Security,
+                            )?;
+                        } else if class.should_notify {
                             self.send_notification(
-                                format!("[{}] {} 执行命令", self.project_name, icon),
-                                format!("{}...", cmd_preview),
-                                "tool_use",  // 统一用 tool_use，不再根据优先级判断
-                                priority.min(2),  // 限制最高优先级为 2
+                                format!("[{}] {} {}", self.project_name, class.icon, class.label.as_deref().unwrap_or(t("run_command"))),
+                                cmd_preview,
+                                NotificationType::ToolUse,  // 统一用 tool_use，不再根据优先级判断
+                                class.priority,
                             )?;
                         }
                     }
@@ -388,7 +1993,7 @@ impl NotchHook {
                     
                     let description = tool_input.get("description")
                         .and_then(|v| v.as_str())
-                        .unwrap_or("AI 任务处理中");
+                        .unwrap_or(t("ai_task_running"));
                     
                     let icon = match subagent_type {
                         "statusline-setup" => "⚙️",
@@ -397,9 +2002,9 @@ impl NotchHook {
                     };
                     
                     self.send_notification(
-                        format!("[{}] {} Agent 启动", self.project_name, icon),
+                        format!("[{}] {} {}", self.project_name, icon, t("agent_started")),
                         format!("{} ({})", description, subagent_type),
-                        "ai",
+                        NotificationType::Ai,
                         2,
                     )?;
                 }
@@ -408,10 +2013,8 @@ impl NotchHook {
                 // 搜索和读取操作 - 低优先级通知
                 if let Some(tool_input) = &event.tool_input {
                     let target = match tool_name {
-                        "Read" => tool_input.get("file_path").and_then(|v| v.as_str()),
-                        "Grep" => tool_input.get("pattern").and_then(|v| v.as_str()),
-                        "Glob" => tool_input.get("pattern").and_then(|v| v.as_str()),
-                        "LS" => tool_input.get("path").and_then(|v| v.as_str()),
+                        "Read" | "LS" => self.extract_file_path(tool_name, tool_input)?.map(|path| self.get_relative_path(&path)),
+                        "Grep" | "Glob" => tool_input.get("pattern").and_then(|v| v.as_str()).map(String::from),
                         _ => None,
                     };
                     
@@ -424,29 +2027,48 @@ impl NotchHook {
                             _ => "ℹ️",
                         };
                         
-                        self.send_notification(
-                            format!("[{}] {} {}", self.project_name, icon, tool_name),
-                            target.chars().take(100).collect::<String>(),
-                            "info",
+                        let mut message = truncate_display(&target, 100);
+                        if tool_name == "Read" {
+                            message.push_str(&self.token_suffix(event));
+                        }
+
+                        // 合并模式下窗口内的连续调用原地更新同一条通知，标题显示累计次数
+                        let mut title = format!("[{}] {} {}", self.project_name, icon, tool_name);
+                        let mut metadata = HashMap::new();
+                        if self.config.coalesce_noise_tools {
+                            let window_ms = self.config.coalesce_window_secs * 1000;
+                            if let Some(group) = coalesce::record(&self.noise_state_path(), tool_name, window_ms) {
+                                metadata.insert("notification_id".to_string(), format!("noise-{}", group.started_at));
+                                if group.total() > 1 {
+                                    title = format!("[{}] {} {}", self.project_name, icon, group.summary());
+                                    metadata.insert("coalesced_count".to_string(), group.total().to_string());
+                                }
+                            }
+                        }
+
+                        self.send_notification_with_metadata(
+                            title,
+                            message,
+                            NotificationType::Info,
                             0,  // 低优先级
+                            metadata,
                         )?;
                     }
                 }
             }
             "WebFetch" | "WebSearch" => {
                 if let Some(tool_input) = &event.tool_input {
-                    let url_or_query = tool_input.get("url")
-                        .or_else(|| tool_input.get("query"))
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("");
-                    
                     let icon = if tool_name == "WebSearch" { "🔎" } else { "🌐" };
-                    
-                    self.send_notification(
-                        format!("[{}] {} 网络访问", self.project_name, icon),
-                        url_or_query.chars().take(100).collect::<String>(),
-                        "download",
+
+                    let mut metadata = HashMap::new();
+                    let displayed = self.web_target(tool_input, &mut metadata);
+
+                    self.send_notification_with_metadata(
+                        format!("[{}] {} {}", self.project_name, icon, t("web_access")),
+                        truncate_display(&displayed, 100),
+                        NotificationType::Download,
                         1,
+                        metadata,
                     )?;
                 }
             }
@@ -459,9 +2081,9 @@ impl NotchHook {
                         ).count();
                         
                         self.send_notification(
-                            format!("[{}] 📋 任务更新", self.project_name),
-                            format!("进度: {}/{} 完成", completed, total),
-                            "reminder",
+                            format!("[{}] 📋 {}", self.project_name, t("task_update")),
+                            tf("task_progress", &[&completed, &total]),
+                            NotificationType::Reminder,
                             1,
                         )?;
                     }
@@ -469,49 +2091,68 @@ impl NotchHook {
             }
             // JetBrains MCP 其他工具的处理
             tool if tool.starts_with("mcp__jetbrains__") => {
+                // 重命名/移动文件：附带 旧路径 → 新路径 的 diff
+                let rename_paths = event
+                    .tool_input
+                    .as_ref()
+                    .filter(|_| self.config.preview_diffs)
+                    .and_then(|tool_input| self.extract_rename_paths(tool, tool_input));
+                if let Some((old_path, new_path)) = rename_paths {
+                    match self.generate_rename_preview(&old_path, &new_path) {
+                        Ok((diff_path, stats)) => {
+                            self.send_notification_with_diff(
+                                format!("[{}] ✏️ JetBrains {}", self.project_name, t("jb_rename")),
+                                format!("{} → {}", self.get_relative_path(&old_path), self.get_relative_path(&new_path)),
+                                NotificationType::Sync,
+                                2,
+                                DiffAttachment::new(diff_path, new_path, &stats),
+                                tool_name,
+                            )?;
+                            return Ok(());
+                        }
+                        Err(e) => warn!("Failed to generate rename preview: {}", e),
+                    }
+                }
+
                 // 根据工具名称分类处理
                 let (icon, action, priority) = match tool {
                     // 项目信息类
-                    "mcp__jetbrains__get_run_configurations" => ("⚙️", "获取运行配置", 0),
-                    "mcp__jetbrains__get_project_modules" => ("📦", "获取项目模块", 0),
-                    "mcp__jetbrains__get_project_dependencies" => ("🔗", "获取项目依赖", 0),
-                    "mcp__jetbrains__get_project_problems" => ("⚠️", "获取项目问题", 1),
-                    "mcp__jetbrains__get_project_vcs_status" => ("🔀", "获取VCS状态", 1),
+                    "mcp__jetbrains__get_run_configurations" => ("⚙️", t("jb_get_run_configurations"), 0),
+                    "mcp__jetbrains__get_project_modules" => ("📦", t("jb_get_project_modules"), 0),
+                    "mcp__jetbrains__get_project_dependencies" => ("🔗", t("jb_get_project_dependencies"), 0),
+                    "mcp__jetbrains__get_project_problems" => ("⚠️", t("jb_get_project_problems"), 1),
+                    "mcp__jetbrains__get_project_vcs_status" => ("🔀", t("jb_get_vcs_status"), 1),
                     
                     // 文件操作类
-                    "mcp__jetbrains__list_directory_tree" => ("🌳", "列出目录树", 0),
-                    "mcp__jetbrains__find_files_by_name_keyword" => ("🔍", "按名称搜索文件", 1),
-                    "mcp__jetbrains__find_files_by_glob" => ("📁", "按模式搜索文件", 1),
-                    "mcp__jetbrains__get_all_open_file_paths" => ("📂", "获取打开的文件", 0),
-                    "mcp__jetbrains__open_file_in_editor" => ("📝", "打开文件", 1),
-                    "mcp__jetbrains__get_file_text_by_path" => ("📖", "读取文件内容", 0),
-                    "mcp__jetbrains__get_file_problems" => ("🔴", "获取文件问题", 1),
-                    "mcp__jetbrains__reformat_file" => ("✨", "格式化文件", 2),
+                    "mcp__jetbrains__list_directory_tree" => ("🌳", t("jb_list_directory_tree"), 0),
+                    "mcp__jetbrains__find_files_by_name_keyword" => ("🔍", t("jb_find_files_by_name"), 1),
+                    "mcp__jetbrains__find_files_by_glob" => ("📁", t("jb_find_files_by_glob"), 1),
+                    "mcp__jetbrains__get_all_open_file_paths" => ("📂", t("jb_get_open_files"), 0),
+                    "mcp__jetbrains__open_file_in_editor" => ("📝", t("jb_open_file"), 1),
+                    "mcp__jetbrains__get_file_text_by_path" => ("📖", t("jb_read_file"), 0),
+                    "mcp__jetbrains__get_file_problems" => ("🔴", t("jb_get_file_problems"), 1),
+                    "mcp__jetbrains__reformat_file" => ("✨", t("jb_reformat_file"), 2),
                     
                     // 搜索和分析类
-                    "mcp__jetbrains__search_in_files_by_text" => ("🔎", "文本搜索", 1),
-                    "mcp__jetbrains__search_in_files_by_regex" => ("🔍", "正则搜索", 1),
-                    "mcp__jetbrains__get_symbol_info" => ("ℹ️", "获取符号信息", 0),
-                    "mcp__jetbrains__rename_refactoring" => ("✏️", "重命名重构", 2),
+                    "mcp__jetbrains__search_in_files_by_text" => ("🔎", t("jb_search_text"), 1),
+                    "mcp__jetbrains__search_in_files_by_regex" => ("🔍", t("jb_search_regex"), 1),
+                    "mcp__jetbrains__get_symbol_info" => ("ℹ️", t("jb_symbol_info"), 0),
+                    "mcp__jetbrains__rename_refactoring" => ("✏️", t("jb_rename"), 2),
                     
                     // 执行类
-                    "mcp__jetbrains__execute_run_configuration" => ("▶️", "执行运行配置", 2),
+                    "mcp__jetbrains__execute_run_configuration" => ("▶️", t("jb_execute_run_configuration"), 2),
                     
                     // Git类
-                    "mcp__jetbrains__find_commit_by_message" => ("📜", "搜索提交", 1),
+                    "mcp__jetbrains__find_commit_by_message" => ("📜", t("jb_find_commit"), 1),
                     
                     // 默认
-                    _ => ("🔧", "JetBrains操作", 1),
+                    _ => ("🔧", t("jb_other"), 1),
                 };
                 
                 // 提取有意义的参数信息
                 let detail = if let Some(tool_input) = &event.tool_input {
-                    if let Some(path) = tool_input.get("directoryPath")
-                        .or_else(|| tool_input.get("pathInProject"))
-                        .or_else(|| tool_input.get("filePath"))
-                        .or_else(|| tool_input.get("path"))
-                        .and_then(|v| v.as_str()) {
-                        path.chars().take(80).collect()
+                    if let Some(path) = self.extract_file_path(tool_name, tool_input)? {
+                        truncate_display(&self.get_relative_path(&path), 80)
                     } else if let Some(pattern) = tool_input.get("pattern")
                         .or_else(|| tool_input.get("globPattern"))
                         .or_else(|| tool_input.get("nameKeyword"))
@@ -519,7 +2160,7 @@ impl NotchHook {
                         .or_else(|| tool_input.get("regexPattern"))
                         .or_else(|| tool_input.get("text"))
                         .and_then(|v| v.as_str()) {
-                        pattern.chars().take(80).collect()
+                        truncate_display(pattern, 80)
                     } else if let Some(config) = tool_input.get("configurationName")
                         .and_then(|v| v.as_str()) {
                         config.to_string()
@@ -541,7 +2182,7 @@ impl NotchHook {
                     self.send_notification(
                         format!("[{}] {} JetBrains {}", self.project_name, icon, action),
                         message,
-                        "sync",
+                        NotificationType::Sync,
                         priority,
                     )?;
                 }
@@ -555,28 +2196,150 @@ impl NotchHook {
     fn handle_post_tool_use(&self, event: &HookEvent) -> Result<()> {
         let tool_name = event.tool_name.as_deref().unwrap_or("");
 
+        let signature = event
+            .tool_input
+            .as_ref()
+            .map(|tool_input| command_signature(tool_name, tool_input));
+
+        // 同一次工具调用的所有更新共用一个 notification_id，App 据此原地更新
+        let notification_id = event
+            .tool_use_id
+            .clone()
+            .or_else(|| signature.as_ref().map(|sig| sig[..16].to_string()));
+
+        if event.is_partial == Some(true) && event.error.is_none() {
+            return self.send_partial_update(tool_name, event, notification_id);
+        }
+
+        let streamed = notification_id.filter(|id| {
+            self.update_session_state(|session| {
+                let before = session.streaming_invocations.len();
+                session.streaming_invocations.retain(|streaming| streaming != id);
+                session.streaming_invocations.len() != before
+            })
+            .unwrap_or(false)
+        });
+
+        if tool_name == "Bash" {
+            self.update_session_state(|session| session.command_count += 1);
+        }
+
+        // 新版 Bash 输出带退出码，非零时和 error 字段一样按失败处理
+        let bash_output = (tool_name == "Bash").then(|| parse_bash_output(event.tool_output.as_ref()));
+        let exit_code = bash_output.as_ref().and_then(|output| output.exit_code).filter(|code| *code != 0);
+        let error = event.error.clone().or_else(|| {
+            let output = bash_output.as_ref()?;
+            exit_code?;
+            let preview = if output.stderr.trim().is_empty() { output_preview(&output.stdout) } else { output_preview(&output.stderr) };
+            Some(preview)
+        });
+
         // 检查是否有错误
-        if let Some(error) = &event.error {
+        if let Some(error) = &error {
+            let now = state::now_millis();
+            let error_message = truncate_display(error, 200);
+            self.update_session_state(|session| {
+                if let Some(signature) = &signature {
+                    session.failed_commands.insert(signature.clone(), now);
+                }
+                session.error_count += 1;
+                session.errors.push(state::ErrorRecord { tool: tool_name.to_string(), message: error_message, at: now });
+                let overflow = session.errors.len().saturating_sub(MAX_RECORDED_ERRORS);
+                session.errors.drain(..overflow);
+            });
+
             let mut metadata = HashMap::new();
             metadata.insert("event_type".to_string(), "tool_error".to_string());
             metadata.insert("tool_name".to_string(), tool_name.to_string());
             metadata.insert("error_message".to_string(), error.clone());
+            if let Some(id) = &streamed {
+                metadata.insert("notification_id".to_string(), id.clone());
+            }
 
+            let (title, message) = match exit_code {
+                Some(code) => {
+                    metadata.insert("exit_code".to_string(), code.to_string());
+                    let message = if error.is_empty() { describe_command(tool_name, event.tool_input.as_ref()) } else { error.clone() };
+                    (tf("command_failed", &[&code]), message)
+                }
+                None => (t("tool_failed").to_string(), format!("{}: {}", tool_name, truncate_display(error, 100))),
+            };
             self.send_notification_with_metadata(
-                format!("[{}] ❌ 工具执行失败", self.project_name),
-                format!("{}: {}", tool_name, error.chars().take(100).collect::<String>()),
-                "error",
+                format!("[{}] ❌ {}", self.project_name, title),
+                message,
+                NotificationType::Error,
                 3,
                 metadata,
             )?;
             return Ok(());
         }
+
+        self.track_edit_milestone(tool_name, event.tool_input.as_ref())?;
+
+        // 之前失败过的同一命令这次成功了
+        if let Some(signature) = signature.filter(|sig| self.load_session_state().failed_commands.contains_key(sig)) {
+            let recovered = self
+                .update_session_state(|session| session.failed_commands.remove(&signature).is_some())
+                .unwrap_or(false);
+
+            if recovered {
+                let mut metadata = HashMap::new();
+                metadata.insert("event_type".to_string(), "tool_recovered".to_string());
+                metadata.insert("tool_name".to_string(), tool_name.to_string());
+
+                self.send_notification_with_metadata(
+                    format!("[{}] ✅ {}", self.project_name, t("recovered")),
+                    format!("{}: {}", tool_name, describe_command(tool_name, event.tool_input.as_ref())),
+                    NotificationType::Success,
+                    2,
+                    metadata,
+                )?;
+                return Ok(());
+            }
+        }
+
+        // 有过流式更新的调用，用最终结果把进度通知切换为完成
+        if let Some(id) = streamed {
+            let mut metadata = HashMap::new();
+            metadata.insert("notification_id".to_string(), id);
+            metadata.insert("partial".to_string(), "false".to_string());
+            metadata.insert("tool_name".to_string(), tool_name.to_string());
+
+            let detail = describe_command(tool_name, event.tool_input.as_ref());
+            let message = match last_output_line(event.tool_output.as_ref()) {
+                Some(line) => format!("{}: {}", detail, line),
+                None => detail,
+            };
+
+            return self.send_notification_with_metadata(
+                format!("[{}] ✅ {}{}", self.project_name, t("done"), self.duration_suffix()),
+                message,
+                NotificationType::Success,
+                1,
+                metadata,
+            );
+        }
+
+        if is_dual_phase_tool(tool_name) && !self.config.notify_phase.allows_post() {
+            debug!("Skipping PostToolUse notification for {} (notify_phase = pre)", tool_name);
+            return Ok(());
+        }
         
         match tool_name {
             "MultiEdit" => {
                 // MultiEdit 完成通知
                 if let Some(tool_input) = &event.tool_input {
                     if let Ok(Some(file_path)) = self.extract_file_path(tool_name, tool_input) {
+                        if self.send_applied_diff_notification(
+                            format!("[{}] ✅ {}{}", self.project_name, t("multi_edit_done"), self.duration_suffix()),
+                            &file_path,
+                            tool_name,
+                            tool_input,
+                            event.tool_output.as_ref(),
+                        )? {
+                            return Ok(());
+                        }
+
                         let relative_path = self.get_relative_path(&file_path);
                         
                         // 提取 edits 数组的长度
@@ -587,15 +2350,15 @@ impl NotchHook {
                         };
                         
                         let message = if edits_count > 0 {
-                            format!("{} ({} 处修改已完成)", relative_path, edits_count)
+                            tf("multi_edit_applied", &[&relative_path, &edits_count])
                         } else {
                             relative_path
                         };
                         
                         self.send_notification(
-                            format!("[{}] ✅ 批量修改完成", self.project_name),
+                            format!("[{}] ✅ {}{}", self.project_name, t("multi_edit_done"), self.duration_suffix()),
                             message,
-                            "success",
+                            NotificationType::Success,
                             0,  // 降低完成通知的优先级
                         )?;
                     }
@@ -604,14 +2367,23 @@ impl NotchHook {
             "mcp__jetbrains__replace_text_in_file" | "mcp__jetbrains__create_new_file" => {
                 if let Some(tool_input) = &event.tool_input {
                     if let Ok(Some(file_path)) = self.extract_file_path(tool_name, tool_input) {
+                        let action = if tool_name.contains("create") { t("jb_file_created") } else { t("jb_edit_done") };
+
+                        if self.send_applied_diff_notification(
+                            format!("[{}] ✅ JetBrains {}{}", self.project_name, action, self.duration_suffix()),
+                            &file_path,
+                            tool_name,
+                            tool_input,
+                            event.tool_output.as_ref(),
+                        )? {
+                            return Ok(());
+                        }
+
                         let relative_path = self.get_relative_path(&file_path);
-                        let icon = if tool_name.contains("create") { "🆕" } else { "✏️" };
-                        let action = if tool_name.contains("create") { "文件已创建" } else { "IDE 修改完成" };
-                        
                         self.send_notification(
-                            format!("[{}] ✅ JetBrains {}", self.project_name, action),
+                            format!("[{}] ✅ JetBrains {}{}", self.project_name, action, self.duration_suffix()),
                             relative_path,
-                            "success",
+                            NotificationType::Success,
                             0,
                         )?;
                     }
@@ -620,78 +2392,471 @@ impl NotchHook {
             "Edit" | "Write" => {
                 if let Some(tool_input) = &event.tool_input {
                     if let Ok(Some(file_path)) = self.extract_file_path(tool_name, tool_input) {
+                        let large_write = large_write_size(tool_name, tool_input, self.config.large_write_bytes).is_some();
+                        if !large_write && self.send_applied_diff_notification(
+                            format!("[{}] ✅ {}{}", self.project_name, t("edit_done"), self.duration_suffix()),
+                            &file_path,
+                            tool_name,
+                            tool_input,
+                            event.tool_output.as_ref(),
+                        )? {
+                            return Ok(());
+                        }
+
                         let relative_path = self.get_relative_path(&file_path);
                         self.send_notification(
-                            format!("[{}] ✅ 修改完成", self.project_name),
+                            format!("[{}] ✅ {}{}", self.project_name, t("edit_done"), self.duration_suffix()),
                             relative_path,
-                            "success",
+                            NotificationType::Success,
                             0,  // 降低完成通知的优先级
                         )?;
+                        return Ok(());
                     }
                 }
+
+                // 无法确定文件时默认不通知，除非要求总是确认
+                self.send_minimal_completion(tool_name, event.tool_input.as_ref())?;
             }
             "Task" => {
                 self.send_notification(
-                    format!("[{}] ✨ Agent 完成", self.project_name),
-                    "AI 任务处理完毕".to_string(),
-                    "success",
+                    format!("[{}] ✨ {}{}", self.project_name, t("agent_done"), self.duration_suffix()),
+                    t("ai_task_done").to_string(),
+                    NotificationType::Success,
                     1,
                 )?;
             }
             "Bash" => {
                 // Bash 命令完成，可以显示部分输出
-                if let Some(tool_output) = &event.tool_output {
-                    if let Some(output) = tool_output.as_str() {
-                        let preview: String = output.lines()
-                            .take(2)
-                            .collect::<Vec<_>>()
-                            .join(" | ")
-                            .chars()
-                            .take(100)
-                            .collect();
-                        
-                        if !preview.is_empty() {
-                            self.send_notification(
-                                format!("[{}] ✅ 命令完成", self.project_name),
-                                preview,
-                                "success",
-                                0,
-                            )?;
-                        }
-                    }
+                let preview = output_preview(&bash_output.unwrap_or_default().stdout);
+
+                if !preview.is_empty() {
+                    self.send_notification(
+                        format!("[{}] ✅ {}{}", self.project_name, t("command_done"), self.duration_suffix()),
+                        preview,
+                        NotificationType::Success,
+                        0,
+                    )?;
+                } else {
+                    // 没有输出可预览时默认不通知，除非要求总是确认
+                    self.send_minimal_completion(tool_name, event.tool_input.as_ref())?;
                 }
             }
-            _ => {}
+            "WebFetch" | "WebSearch" => {
+                self.send_web_result(tool_name, event)?;
+            }
+            _ => {}
         }
         
         Ok(())
     }
 
-    fn handle_stop(&self) -> Result<()> {
+    /// WebFetch 的 URL（按 url_privacy 裁剪）或 WebSearch 的查询词
+    fn web_target(&self, tool_input: &Value, metadata: &mut HashMap<String, String>) -> String {
+        match tool_input.get("url").and_then(|v| v.as_str()) {
+            Some(url) => {
+                if self.config.url_in_metadata {
+                    metadata.insert("url".to_string(), url.to_string());
+                }
+                privacy::display_url(url, self.config.url_privacy)
+            }
+            None => tool_input.get("query").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        }
+    }
+
+    /// WebFetch/WebSearch 完成：报告状态码、响应大小或搜索结果数，失败时升级为错误
+    fn send_web_result(&self, tool_name: &str, event: &HookEvent) -> Result<()> {
+        let output = parse_web_output(event.tool_output.as_ref());
+
+        let mut metadata = HashMap::new();
+        metadata.insert("tool_name".to_string(), tool_name.to_string());
+        let target = event
+            .tool_input
+            .as_ref()
+            .map(|tool_input| self.web_target(tool_input, &mut metadata))
+            .unwrap_or_default();
+
+        let mut details = Vec::new();
+        if let Some(code) = output.code {
+            metadata.insert("http_status".to_string(), code.to_string());
+            details.push(format!("HTTP {}", code));
+        }
+        if let Some(count) = output.result_count() {
+            metadata.insert("result_count".to_string(), count.to_string());
+            details.push(tf("search_results", &[&count]));
+        } else if let Some(size) = output.size() {
+            metadata.insert("response_bytes".to_string(), size.to_string());
+            details.push(format_size(size));
+        }
+
+        if let Some(failure) = output.failure() {
+            metadata.insert("error_message".to_string(), failure.clone());
+            return self.send_notification_with_metadata(
+                format!("[{}] ❌ {}{}", self.project_name, t("web_failed"), self.duration_suffix()),
+                format!("{} · {}", truncate_display(&target, 60), truncate_display(&failure, 80)),
+                NotificationType::Error,
+                2,
+                metadata,
+            );
+        }
+
+        let (icon, title) = if tool_name == "WebSearch" { ("🔎", t("web_search_done")) } else { ("🌐", t("web_fetch_done")) };
+        let mut message = truncate_display(&target, 80);
+        if !details.is_empty() {
+            message = format!("{} · {}", message, details.join(" · "));
+        }
+
+        self.send_notification_with_metadata(
+            format!("[{}] {} {}{}", self.project_name, icon, title, self.duration_suffix()),
+            message,
+            NotificationType::Download,
+            0,
+            metadata,
+        )
+    }
+
+    /// 流式输出的中间更新：记录该调用正在流式输出，并用最新一行进度更新同一条通知
+    fn send_partial_update(&self, tool_name: &str, event: &HookEvent, notification_id: Option<String>) -> Result<()> {
+        let Some(id) = notification_id else {
+            return Ok(());
+        };
+
+        self.update_session_state(|session| {
+            if !session.streaming_invocations.contains(&id) {
+                session.streaming_invocations.push(id.clone());
+            }
+        });
+
+        let Some(line) = last_output_line(event.tool_output.as_ref()) else {
+            return Ok(());
+        };
+
+        let mut metadata = HashMap::new();
+        metadata.insert("notification_id".to_string(), id);
+        metadata.insert("partial".to_string(), "true".to_string());
+        metadata.insert("tool_name".to_string(), tool_name.to_string());
+
+        self.send_notification_with_metadata(
+            format!("[{}] ⏳ {}", self.project_name, describe_command(tool_name, event.tool_input.as_ref())),
+            line,
+            NotificationType::Progress,
+            0,
+            metadata,
+        )
+    }
+
+    /// always_confirm_completion 开启时，即使没有可展示的内容也发送一条简单的完成通知
+    fn send_minimal_completion(&self, tool_name: &str, tool_input: Option<&Value>) -> Result<()> {
+        if !self.config.always_confirm_completion {
+            return Ok(());
+        }
+
         self.send_notification(
-            format!("[{}] 🎉 会话结束", self.project_name),
-            "Claude 已完成所有任务".to_string(),
-            "celebration",
+            format!("[{}] ✅ {}{}", self.project_name, t("done"), self.duration_suffix()),
+            describe_command(tool_name, tool_input),
+            NotificationType::Success,
+            0,
+        )
+    }
+
+    /// post 模式下 Pre 阶段的通知被跳过，由 PostToolUse 发送带实际 diff 的合并通知。
+    /// 返回是否已发送。
+    fn send_applied_diff_notification(
+        &self,
+        title: String,
+        file_path: &Path,
+        tool_name: &str,
+        tool_input: &Value,
+        tool_output: Option<&Value>,
+    ) -> Result<bool> {
+        if self.config.notify_phase != NotifyPhase::Post || !self.diff_enabled_for(file_path) {
+            return Ok(false);
+        }
+
+        match self.generate_applied_diff(file_path, tool_name, tool_input, tool_output) {
+            Ok((diff_path, stats)) => {
+                let relative_path = self.get_relative_path(file_path);
+                let message = format!("{} ({})", relative_path, self.format_stats(&stats));
+
+                self.send_notification_with_diff(
+                    title,
+                    message,
+                    NotificationType::Success,
+                    2,  // 唯一的一条通知，保持与 Pre 阶段相同的优先级
+                    DiffAttachment::new(diff_path, file_path.to_path_buf(), &stats),
+                    tool_name,
+                )?;
+                Ok(true)
+            }
+            Err(e) => {
+                debug!("Failed to generate applied diff: {}", e);
+                Ok(false)
+            }
+        }
+    }
+
+    /// 记录即将新建的文件：Write 到不存在的路径、JetBrains 创建文件、Bash 中的 touch
+    fn track_created_files(&self, tool_name: &str, event: &HookEvent) {
+        let Some(tool_input) = &event.tool_input else {
+            return;
+        };
+
+        let created: Vec<PathBuf> = match tool_name {
+            "Write" | "mcp__jetbrains__create_new_file" => self
+                .extract_file_path(tool_name, tool_input)
+                .ok()
+                .flatten()
+                .filter(|path| !path.exists())
+                .into_iter()
+                .collect(),
+            "Bash" => tool_input
+                .get("command")
+                .and_then(|v| v.as_str())
+                .map(|command| {
+                    parse_touch_targets(command)
+                        .into_iter()
+                        .map(|target| self.project_path.join(target))
+                        .filter(|path| !path.exists())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        };
+
+        if created.is_empty() {
+            return;
+        }
+
+        let relative_paths: Vec<String> = created.iter().map(|path| self.get_relative_path(path)).collect();
+        self.update_session_state(|session| {
+            for path in relative_paths {
+                if !session.created_files.contains(&path) {
+                    session.created_files.push(path);
+                }
+            }
+        });
+    }
+
+    /// 把会通知的 Bash 命令（脱敏后）追加到会话的命令历史，只保留最近 N 条
+    fn record_command(&self, tool_name: &str, event: &HookEvent) {
+        if tool_name != "Bash" || self.config.command_history_size == 0 {
+            return;
+        }
+        let Some(command) = event.tool_input.as_ref().and_then(|input| input.get("command")).and_then(|v| v.as_str()) else {
+            return;
+        };
+        if !classify_bash_command(command, &self.config).should_notify {
+            return;
+        }
+
+        let redacted = privacy::redact_secrets(&privacy::redact_command_urls(command, self.config.url_privacy));
+        let limit = self.config.command_history_size;
+        let now = state::now_millis();
+        self.update_session_state(|session| {
+            session.recent_commands.push(CommandRecord { command: redacted, at: now });
+            let overflow = session.recent_commands.len().saturating_sub(limit);
+            session.recent_commands.drain(..overflow);
+        });
+    }
+
+    /// JetBrains 调试启动时记录调试状态，停止/终止类工具清除
+    fn track_debug_session(&self, tool_name: &str) {
+        if !self.config.suppress_while_debugging || !tool_name.starts_with("mcp__jetbrains__") {
+            return;
+        }
+
+        if tool_name == "mcp__jetbrains__debug_configuration" {
+            let now = state::now_millis();
+            self.update_session_state(|session| session.debugging_since = Some(now));
+            debug!("Debug session started, raising minimum priority");
+        } else if ["stop", "terminate", "kill"].iter().any(|word| tool_name.contains(word)) {
+            self.update_session_state(|session| session.debugging_since = None);
+            debug!("Debug session ended");
+        }
+    }
+
+    /// 调试期间（未超时）低于阈值的通知被压制
+    fn suppressed_by_debugging(&self, priority: u8) -> bool {
+        if !self.config.suppress_while_debugging || priority >= self.config.debug_min_priority {
+            return false;
+        }
+
+        match self.load_session_state().debugging_since {
+            Some(since) => {
+                state::now_millis().saturating_sub(since) < self.config.debug_timeout_secs * 1000
+            }
+            None => false,
+        }
+    }
+
+    fn session_state_path(&self) -> PathBuf {
+        self.diff_dir.join(state::session_file_name(&self.session_id))
+    }
+
+    fn load_session_state(&self) -> SessionState {
+        state::load(&self.session_state_path())
+    }
+
+    /// 累计本会话的编辑次数，每跨过 NOTCH_EDIT_MILESTONE 的整数倍提示一次
+    fn track_edit_milestone(&self, tool_name: &str, tool_input: Option<&Value>) -> Result<()> {
+        let Some((added, removed)) = tool_input.and_then(|input| estimate_edit_lines(tool_name, input)) else {
+            return Ok(());
+        };
+
+        // 计数始终累计（会话总结要用），只有设置了间隔才提示
+        let interval = self.edit_milestone;
+        let milestone = self.update_session_state(|session| {
+            session.edit_count += 1;
+            session.edit_lines_added += added as u64;
+            session.edit_lines_removed += removed as u64;
+            interval
+                .filter(|interval| session.edit_count % interval == 0)
+                .map(|_| (session.edit_count, session.edit_lines_added, session.edit_lines_removed))
+        });
+
+        if let Some((count, added, removed)) = milestone.flatten() {
+            let mut metadata = HashMap::new();
+            metadata.insert("edit_count".to_string(), count.to_string());
+            metadata.insert("lines_added".to_string(), added.to_string());
+            metadata.insert("lines_removed".to_string(), removed.to_string());
+
+            self.send_notification_with_metadata(
+                format!("[{}] 📊 {}", self.project_name, tf("edit_milestone", &[&count])),
+                tf("total", &[&self.format_line_stats(added as usize, removed as usize)]),
+                NotificationType::Info,
+                1,
+                metadata,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// 读取-修改-写回会话状态，失败时只记录日志，不影响通知
+    fn update_session_state<R>(&self, f: impl FnOnce(&mut SessionState) -> R) -> Option<R> {
+        match state::update(&self.session_state_path(), f) {
+            Ok(result) => Some(result),
+            Err(e) => {
+                warn!("Failed to update session state: {}", e);
+                None
+            }
+        }
+    }
+
+    fn handle_stop(&self, event: &HookEvent) -> Result<()> {
+        // Stop hook 的动作又触发了 Stop，不再重复庆祝，避免循环刷屏
+        if event.stop_hook_active == Some(true) {
+            debug!("stop_hook_active is set, skipping Stop notification");
+            return Ok(());
+        }
+
+        let mut message = t("all_tasks_done").to_string();
+        let mut metadata = HashMap::new();
+        let session = self.load_session_state();
+
+        if !session.recent_commands.is_empty() {
+            let commands: Vec<&str> = session.recent_commands.iter().map(|record| record.command.as_str()).collect();
+            metadata.insert("recent_commands".to_string(), commands.join("\n"));
+        }
+
+        if self.config.summarize_created_files {
+            let created_files = session.created_files;
+            if !created_files.is_empty() {
+                let mut listed = created_files
+                    .iter()
+                    .take(MAX_LISTED_CREATED_FILES)
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                if created_files.len() > MAX_LISTED_CREATED_FILES {
+                    listed.push_str(", …");
+                }
+
+                message = tf("created_files", &[&created_files.len(), &listed]);
+                metadata.insert("created_files_count".to_string(), created_files.len().to_string());
+                metadata.insert("created_files".to_string(), created_files.join("\n"));
+            }
+        }
+
+        // 上次 Stop 以来的错误汇总到这条通知里，之后清空
+        let errors = self.update_session_state(|session| std::mem::take(&mut session.errors)).unwrap_or_default();
+        let title = if errors.is_empty() {
+            format!("[{}] 🎉 {}", self.project_name, t("session_end"))
+        } else {
+            let mut listed = errors
+                .iter()
+                .take(MAX_LISTED_ERRORS)
+                .map(|record| format!("{}: {}", record.tool, truncate_display(record.message.lines().next().unwrap_or(""), 60)))
+                .collect::<Vec<_>>()
+                .join("; ");
+            if errors.len() > MAX_LISTED_ERRORS {
+                listed.push_str("; …");
+            }
+            message = format!("{}\n❌ {}", message, listed);
+
+            metadata.insert("error_count".to_string(), errors.len().to_string());
+            metadata.insert(
+                "errors".to_string(),
+                errors.iter().map(|record| format!("{}: {}", record.tool, record.message)).collect::<Vec<_>>().join("\n"),
+            );
+            format!("[{}] 🎉 {}", self.project_name, tf("session_end_errors", &[&errors.len()]))
+        };
+
+        self.send_notification_with_metadata(
+            title,
+            message,
+            NotificationType::Celebration,
             2,
+            metadata,
+        )?;
+        Ok(())
+    }
+
+    fn handle_subagent_stop(&self, event: &HookEvent) -> Result<()> {
+        if event.stop_hook_active == Some(true) {
+            debug!("stop_hook_active is set, skipping SubagentStop notification");
+            return Ok(());
+        }
+
+        let mut metadata = HashMap::new();
+        metadata.insert("event_type".to_string(), "subagent_stop".to_string());
+        let message = match event.subagent_type.as_deref().filter(|subagent_type| !subagent_type.is_empty()) {
+            Some(subagent_type) => {
+                metadata.insert("subagent_type".to_string(), subagent_type.to_string());
+                tf("subagent_done_typed", &[&subagent_type])
+            }
+            None => t("subagent_done_message").to_string(),
+        };
+
+        self.send_notification_with_metadata(
+            format!("[{}] 🤖 {}", self.project_name, t("subagent_done")),
+            message,
+            NotificationType::Ai,
+            1,
+            metadata,
         )?;
         Ok(())
     }
 
     fn handle_notification(&self) -> Result<()> {
         // Notification hook 会在 Claude Code 等待用户输入或需要权限时触发
-        eprintln!("[NOTIFICATION] Claude Code is waiting for user interaction");
+        info!("Claude Code is waiting for user interaction");
 
-        self.send_notification(
-            format!("[{}] 🔔 需要你的响应", self.project_name),
-            "Claude 正在等待你的选择，请查看 Claude Code 窗口".to_string(),
-            "reminder",
+        let now = state::now_millis();
+        self.update_session_state(|session| session.waiting_since = Some(now));
+
+        let mut metadata = HashMap::new();
+        metadata.insert("event_type".to_string(), "notification".to_string());
+        self.send_notification_with_metadata(
+            format!("[{}] 🔔 {}", self.project_name, t("needs_response")),
+            t("waiting_for_choice").to_string(),
+            NotificationType::Reminder,
             3,
+            metadata,
         )?;
         Ok(())
     }
     
     fn handle_session_start(&self) -> Result<()> {
-        eprintln!("[DEBUG] Session started for project: {}", self.project_name);
+        debug!("Session started for project: {}", self.project_name);
 
         // 发送会话开始通知
         let mut metadata = HashMap::new();
@@ -699,27 +2864,70 @@ impl NotchHook {
         metadata.insert("session_id".to_string(), format!("{}", std::process::id()));
         metadata.insert("project".to_string(), self.project_name.clone());  // 添加项目名称
 
+        let now = state::now_millis();
+        self.update_session_state(|session| session.started_at = Some(now));
+
+        let title = match self.model.as_deref() {
+            Some(model) => format!("[{}] 🚀 {} ({})", self.project_name, t("session_start"), model_display_name(model)),
+            None => format!("[{}] 🚀 {}", self.project_name, t("session_start")),
+        };
+
         self.send_notification_with_metadata(
-            format!("[{}] 🚀 会话开始", self.project_name),
-            "Claude Code 会话已启动".to_string(),
-            "ai",
+            title,
+            t("session_started_message").to_string(),
+            NotificationType::Ai,
             0,  // 低优先级
             metadata,
         )?;
         Ok(())
     }
     
+    /// 会话关闭时汇总时长和编辑/命令/错误次数，metadata 中带完整计数供 App 渲染总结卡片
+    fn handle_session_end(&self, event: &HookEvent) -> Result<()> {
+        let session = self.load_session_state();
+        let elapsed_ms = session.started_at.map(|started| state::now_millis().saturating_sub(started));
+
+        let mut parts = Vec::new();
+        if let Some(elapsed_ms) = elapsed_ms {
+            parts.push(tf("session_length", &[&timefmt::format_duration(elapsed_ms)]));
+        }
+        parts.push(tf("session_counts", &[&session.edit_count, &session.command_count, &session.error_count]));
+
+        let mut metadata = HashMap::new();
+        metadata.insert("event_type".to_string(), "session_end".to_string());
+        if let Some(elapsed_ms) = elapsed_ms {
+            metadata.insert("session_elapsed_ms".to_string(), elapsed_ms.to_string());
+        }
+        metadata.insert("edit_count".to_string(), session.edit_count.to_string());
+        metadata.insert("lines_added".to_string(), session.edit_lines_added.to_string());
+        metadata.insert("lines_removed".to_string(), session.edit_lines_removed.to_string());
+        metadata.insert("command_count".to_string(), session.command_count.to_string());
+        metadata.insert("error_count".to_string(), session.error_count.to_string());
+        if let Some(reason) = &event.reason {
+            metadata.insert("reason".to_string(), reason.clone());
+        }
+
+        self.send_notification_with_metadata(
+            format!("[{}] 👋 {}", self.project_name, t("session_closed")),
+            parts.join(" · "),
+            NotificationType::Info,
+            1,
+            metadata,
+        )?;
+        Ok(())
+    }
+
     fn handle_user_prompt_submit(&self, event: &HookEvent) -> Result<()> {
-        eprintln!("[DEBUG] UserPromptSubmit event received");
-        eprintln!("[DEBUG] Tool input: {:?}", event.tool_input);
+        debug!("UserPromptSubmit event received");
+        debug!("Tool input: {:?}", event.tool_input);
 
         // 检查是否是确认对话框（Claude Code 询问用户）
         if let Some(tool_input) = &event.tool_input {
-            eprintln!("[DEBUG] Raw input: {}", tool_input);
+            debug!("Raw input: {}", tool_input);
 
             // 尝试解析为字符串（可能是 JSON 或纯文本）
             if let Some(input_str) = tool_input.as_str() {
-                eprintln!("[DEBUG] Input string: {}", input_str);
+                debug!("Input string: {}", input_str);
 
                 // 检测是否包含选项（例如："Allow", "Deny", "Accept", "Reject"）
                 let has_options = input_str.contains("allow") ||
@@ -730,23 +2938,32 @@ impl NotchHook {
                                  input_str.contains("no");
 
                 if has_options {
-                    eprintln!("[DEBUG] Detected confirmation prompt!");
+                    debug!("Detected confirmation prompt!");
 
                     // 发送交互式通知到刘海
                     let mut metadata = HashMap::new();
                     metadata.insert("prompt_type".to_string(), "user_confirmation".to_string());
                     metadata.insert("prompt_text".to_string(), input_str.to_string());
+                    metadata.insert("actions".to_string(), "allow,deny".to_string());
 
                     self.send_notification_with_metadata(
-                        format!("[{}] 📋 需要响应", self.project_name),
-                        format!("{}", input_str.chars().take(200).collect::<String>()),
-                        "confirmation",
+                        format!("[{}] 📋 {}", self.project_name, t("response_needed")),
+                        truncate_display(input_str, 200),
+                        NotificationType::Confirmation,
                         3,
                         metadata,
                     )?;
+
+                    // 把用户在刘海中的选择转成 hook 输出；超时或没有回复时不输出，Claude 照常继续
+                    if let Some(decision) = self.reply_decision.take() {
+                        let reason = tf("chosen_in_notch", &[&decision]);
+                        if let Some(output) = confirm::hook_output(&event.hook_event_name, decision, &reason) {
+                            println!("{}", output);
+                        }
+                    }
                 }
             } else if let Some(obj) = tool_input.as_object() {
-                eprintln!("[DEBUG] Input is object: {:?}", obj);
+                debug!("Input is object: {:?}", obj);
                 // 可能是结构化的确认请求
             }
         }
@@ -756,53 +2973,85 @@ impl NotchHook {
     
     fn handle_pre_compact(&self) -> Result<()> {
         self.send_notification(
-            format!("[{}] 🗜️ 内存优化", self.project_name),
-            "正在压缩上下文以节省内存".to_string(),
-            "info",
+            format!("[{}] 🗜️ {}", self.project_name, t("compacting")),
+            t("compacting_message").to_string(),
+            NotificationType::Info,
             0,
         )?;
         Ok(())
     }
 
     fn extract_file_path(&self, tool_name: &str, tool_input: &Value) -> Result<Option<PathBuf>> {
+        let field = |keys: &[&str]| keys.iter().find_map(|key| tool_input.get(*key).and_then(|v| v.as_str()));
         let path_str = match tool_name {
-            "Edit" | "Write" | "MultiEdit" => {
-                tool_input.get("file_path").and_then(|v| v.as_str())
-            }
-            "mcp__jetbrains__replace_text_in_file" | "mcp__jetbrains__create_new_file" => {
-                tool_input.get("pathInProject").and_then(|v| v.as_str())
+            "Edit" | "Write" | "MultiEdit" | "Read" => field(&["file_path"]),
+            "NotebookEdit" => field(&["notebook_path"]),
+            "LS" | "Grep" | "Glob" => field(&["path"]),
+            _ if tool_name.starts_with("mcp__jetbrains__") => {
+                field(&["pathInProject", "filePath", "path", "directoryPath"])
             }
             _ => None,
         };
 
-        if let Some(path_str) = path_str {
-            // 统一的路径处理策略
-            let path = if path_str.starts_with('/') {
-                // 看起来像绝对路径
-                let abs_path = PathBuf::from(path_str);
-                
-                // 检查是否真的是绝对路径（文件存在）
-                if abs_path.exists() {
-                    eprintln!("[DEBUG] Using absolute path: {}", abs_path.display());
-                    abs_path
-                } else {
-                    // 可能是错误的绝对路径格式（如 /README.md），当作相对路径处理
-                    let relative = path_str.trim_start_matches('/');
-                    let resolved = self.project_path.join(relative);
-                    eprintln!("[DEBUG] Converted false absolute path {} to {}", path_str, resolved.display());
-                    resolved
-                }
+        Ok(path_str.map(|path_str| self.resolve_input_path(path_str)))
+    }
+
+    /// 工具输入中的路径统一解析为绝对路径：展开 ~/，相对路径相对于项目根
+    fn resolve_input_path(&self, path_str: &str) -> PathBuf {
+        // 统一的路径处理策略
+        let home_path = path_str.strip_prefix("~/").and_then(|rest| Some(dirs::home_dir()?.join(rest)));
+        if let Some(home_path) = home_path {
+            debug!("Expanded home path {} to {}", path_str, home_path.display());
+            home_path
+        } else if path_str.starts_with('/') {
+            // 看起来像绝对路径
+            let abs_path = PathBuf::from(path_str);
+            
+            // 检查是否真的是绝对路径（文件或其所在目录存在，根目录除外）
+            let parent_exists = abs_path.parent().is_some_and(|parent| parent != Path::new("/") && parent.exists());
+            if abs_path.exists() || parent_exists {
+                debug!("Using absolute path: {}", abs_path.display());
+                abs_path
             } else {
-                // 相对路径 - 所有工具都统一相对于项目根
-                let resolved = self.project_path.join(path_str);
-                eprintln!("[DEBUG] Resolved relative path {} to {}", path_str, resolved.display());
+                // 可能是错误的绝对路径格式（如 /README.md），当作相对路径处理
+                let relative = path_str.trim_start_matches('/');
+                let resolved = self.project_path.join(relative);
+                debug!("Converted false absolute path {} to {}", path_str, resolved.display());
                 resolved
-            };
-            
-            Ok(Some(path))
+            }
         } else {
-            Ok(None)
+            // 相对路径 - 所有工具都统一相对于项目根
+            let resolved = self.project_path.join(path_str);
+            debug!("Resolved relative path {} to {}", path_str, resolved.display());
+            resolved
+        }
+    }
+
+    /// 重命名/移动文件的旧路径和新路径：显式的 oldPath/newPath 等字段，
+    /// 或 rename_refactoring 重命名的符号与文件同名（如 Java 类）时按 newName 推出新文件名
+    fn extract_rename_paths(&self, tool_name: &str, tool_input: &Value) -> Option<(PathBuf, PathBuf)> {
+        let field = |key: &str| tool_input.get(key).and_then(|v| v.as_str()).filter(|s| !s.is_empty());
+        let explicit = [("oldPath", "newPath"), ("sourcePath", "targetPath"), ("old_path", "new_path")]
+            .iter()
+            .find_map(|(old, new)| Some((field(old)?, field(new)?)));
+        if let Some((old_path, new_path)) = explicit {
+            return Some((self.resolve_input_path(old_path), self.resolve_input_path(new_path)));
+        }
+
+        if tool_name != "mcp__jetbrains__rename_refactoring" {
+            return None;
+        }
+        let old_path = self.resolve_input_path(field("pathInProject")?);
+        let (symbol, new_name) = (field("symbolName")?, field("newName")?);
+        if old_path.file_stem()?.to_str()? != symbol {
+            return None;
         }
+        let file_name = match old_path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) => format!("{}.{}", new_name, ext),
+            None => new_name.to_string(),
+        };
+        let new_path = old_path.with_file_name(file_name);
+        Some((old_path, new_path))
     }
 
     fn extract_text_content(&self, tool_name: &str, tool_input: &Value) -> Result<(Option<String>, Option<String>)> {
@@ -834,26 +3083,40 @@ impl NotchHook {
         file_path: &Path,
         old_text: Option<&str>,
         new_text: Option<&str>,
+        replace_all: bool,
     ) -> Result<(PathBuf, DiffStats)> {
-        let file_id = self.generate_file_id(file_path);
-        
         // 读取原文件内容
-        let original_content = if file_path.exists() {
-            fs::read_to_string(file_path)?
+        let original_bytes = if file_path.exists() {
+            fs::read(file_path)?
         } else {
-            String::new()
+            Vec::new()
         };
+
+        // 二进制文件不做文本 diff，只记录大小变化
+        if is_binary_content(&original_bytes) || new_text.is_some_and(|text| is_binary_content(text.as_bytes())) {
+            let new_size = match (old_text, new_text) {
+                (Some(old), Some(new)) => (original_bytes.len() + new.len()).saturating_sub(old.len()),
+                (None, Some(new)) => new.len(),
+                _ => original_bytes.len(),
+            };
+            return self.write_binary_diff(file_path, &original_bytes, new_size, true);
+        }
+        let original_content = String::from_utf8(original_bytes)?;
         
         // 生成修改后的内容
         let modified_content = if let (Some(old), Some(new)) = (old_text, new_text) {
             // Edit操作：替换文本
-            let result = original_content.replacen(old, new, 1);
+            let result = if replace_all {
+                original_content.replace(old, new)
+            } else {
+                original_content.replacen(old, new, 1)
+            };
             
             // 调试：检查替换是否发生
             if result == original_content {
-                eprintln!("[DEBUG] Warning: Text replacement didn't occur!");
-                eprintln!("[DEBUG] Looking for: {:?}", old);
-                eprintln!("[DEBUG] File starts with: {:?}", original_content.lines().next());
+                debug!("Warning: Text replacement didn't occur!");
+                debug!("Looking for: {:?}", old);
+                debug!("File starts with: {:?}", original_content.lines().next());
             }
             
             result
@@ -863,9 +3126,131 @@ impl NotchHook {
         } else {
             original_content.clone()
         };
-        
+
+        let matched = old_text.map(|old| original_content.contains(old));
+        self.write_diff(file_path, &original_content, &modified_content, true, matched)
+    }
+
+    /// MultiEdit 预览：在内存中按顺序应用每一处修改，生成一份合并的 diff。
+    /// 某处 old_string 找不到时记录序号并跳过，继续应用其余修改
+    fn generate_multi_edit_preview_diff(&self, file_path: &Path, edits: &[Value]) -> Result<(PathBuf, DiffStats)> {
+        let original_content = if file_path.exists() {
+            fs::read_to_string(file_path)?
+        } else {
+            String::new()
+        };
+
+        let mut modified_content = original_content.clone();
+        let mut all_matched = true;
+        for (index, edit) in edits.iter().enumerate() {
+            let old = edit.get("old_string").and_then(|v| v.as_str());
+            let new = edit.get("new_string").and_then(|v| v.as_str());
+            let (Some(old), Some(new)) = (old, new) else {
+                debug!("MultiEdit edit #{} is missing old_string/new_string, skipped", index);
+                continue;
+            };
+            // 空 old_string 用于在新文件中写入内容
+            if old.is_empty() && modified_content.is_empty() {
+                modified_content = new.to_string();
+                continue;
+            }
+            if old.is_empty() || !modified_content.contains(old) {
+                debug!("MultiEdit edit #{} does not match the current content, skipped", index);
+                all_matched = false;
+                continue;
+            }
+
+            modified_content = if is_replace_all(edit) {
+                modified_content.replace(old, new)
+            } else {
+                modified_content.replacen(old, new, 1)
+            };
+        }
+
+        self.write_diff(file_path, &original_content, &modified_content, true, Some(all_matched))
+    }
+
+    /// PostToolUse 阶段：文件已被修改，用磁盘上的内容和修改前的内容生成实际生效的diff
+    fn generate_applied_diff(
+        &self,
+        file_path: &Path,
+        tool_name: &str,
+        tool_input: &Value,
+        tool_output: Option<&Value>,
+    ) -> Result<(PathBuf, DiffStats)> {
+        let modified_content = if file_path.exists() {
+            fs::read_to_string(file_path)?
+        } else {
+            String::new()
+        };
+
+        // 优先使用 Claude Code 在 tool_output 中返回的原始文件内容
+        let original_content = match tool_output
+            .and_then(|output| output.get("originalFile"))
+            .and_then(|v| v.as_str())
+        {
+            Some(original) => original.to_string(),
+            None => self.reconstruct_original_content(tool_name, tool_input, &modified_content),
+        };
+
+        self.write_diff(file_path, &original_content, &modified_content, false, None)
+    }
+
+    /// 通过反向应用 old/new 文本还原修改前的内容
+    fn reconstruct_original_content(&self, tool_name: &str, tool_input: &Value, modified_content: &str) -> String {
+        let mut content = modified_content.to_string();
+
+        if tool_name == "MultiEdit" {
+            if let Some(edits) = tool_input.get("edits").and_then(|v| v.as_array()) {
+                // 逆序撤销每一处修改
+                for edit in edits.iter().rev() {
+                    let old = edit.get("old_string").and_then(|v| v.as_str());
+                    let new = edit.get("new_string").and_then(|v| v.as_str());
+                    if let (Some(old), Some(new)) = (old, new) {
+                        if !new.is_empty() {
+                            content = content.replacen(new, old, 1);
+                        }
+                    }
+                }
+            }
+            return content;
+        }
+
+        match self.extract_text_content(tool_name, tool_input) {
+            Ok((Some(old), Some(new))) if !new.is_empty() && is_replace_all(tool_input) => content.replace(&new, &old),
+            Ok((Some(old), Some(new))) if !new.is_empty() => content.replacen(&new, &old, 1),
+            // Write/创建文件无法还原原内容，按新文件处理
+            _ => String::new(),
+        }
+    }
+
+    fn write_diff(
+        &self,
+        file_path: &Path,
+        original_content: &str,
+        modified_content: &str,
+        preview: bool,
+        matched: Option<bool>,
+    ) -> Result<(PathBuf, DiffStats)> {
+        let file_id = self.generate_file_id(file_path);
+        let kind = if preview { "preview" } else { "applied" };
+        // 预览是否过时由 App 对比磁盘上的文件判断，必须在规范化之前计算
+        let source_sha256 = content_hash(original_content);
+        let (raw_original, raw_modified) = (original_content, modified_content);
+
+        // 按扩展名配置的预处理（如 JSON 格式化），让格式变化不淹没真正的修改
+        let prediff_format = file_path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.config.prediff_format.get(ext).copied());
+        let (original_content, modified_content) = match prediff_format {
+            Some(format) => prediff::apply(format, original_content, modified_content),
+            None => (original_content.into(), modified_content.into()),
+        };
+        let (original_content, modified_content) = (original_content.as_ref(), modified_content.as_ref());
+
         // 生成diff
-        let diff = TextDiff::from_lines(&original_content, &modified_content);
+        let diff = TextDiff::from_lines(original_content, modified_content);
         
         // 计算统计
         let mut added = 0;
@@ -879,48 +3264,257 @@ impl NotchHook {
             }
         }
         
-        // 保存diff文件
-        let diff_path = self.diff_dir.join(format!("{}.preview.diff", file_id));
-        let unified_diff = diff
-            .unified_diff()
-            .context_radius(3)
-            .header(&format!("--- {}", file_path.display()), &format!("+++ {}", file_path.display()))
-            .to_string();
-        fs::write(&diff_path, unified_diff)?;
-        
-        // 保存统计信息
-        let stats = DiffStats {
-            added,
-            removed,
-            file: file_path.to_string_lossy().to_string(),
-            preview: true,
+        // 行号要能在磁盘上的原文件中定位；规范化（如压缩 JSON 展开）后的行号在原文件里不存在，按原始内容重新比较
+        let first_change_line = match prediff_format {
+            Some(_) => first_changed_line(&TextDiff::from_lines(raw_original, raw_modified)),
+            None => first_changed_line(&diff),
         };
-        
-        let stats_path = self.diff_dir.join(format!("{}.preview.stats.json", file_id));
+
+        let change_description = file_path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(describe::Language::from_extension)
+            .and_then(|language| describe::describe_change(&diff, language));
+
+        // 保存diff文件
+        let diff_path = self.diff_dir.join(format!("{}.{}.diff", file_id, kind));
+        let unified_diff = match self.config.diff_style {
+            DiffStyle::Unified => {
+                let path = file_path.display().to_string();
+                match self.config.diff_granularity {
+                    DiffGranularity::Line => diff.unified_diff().context_radius(self.diff_context).header(&path, &path).to_string(),
+                    DiffGranularity::Word => worddiff::render(&diff, &path, self.diff_context),
+                }
+            }
+            DiffStyle::Git => {
+                // 可直接 git apply 的补丁：a/ b/ 前缀 + 项目相对路径，新文件用 /dev/null
+                let relative_path = self.project_relative_path(file_path).display();
+                let is_new_file = original_content.is_empty() && !file_path.exists();
+                let old_header = if is_new_file { "/dev/null".to_string() } else { format!("a/{}", relative_path) };
+                let mut patch = format!("diff --git a/{} b/{}\n", relative_path, relative_path);
+                if is_new_file {
+                    patch.push_str("new file mode 100644\n");
+                }
+                patch.push_str(
+                    &diff
+                        .unified_diff()
+                        .context_radius(self.diff_context)
+                        .header(&old_header, &format!("b/{}", relative_path))
+                        .to_string(),
+                );
+                patch
+            }
+        };
+        let snippet = if self.show_snippet { diff_snippet(&unified_diff) } else { None };
+        // 超长的 diff 在刘海里没法看，截断后另存一份完整的
+        let (unified_diff, full_diff_path) = match truncate_diff(&unified_diff, self.config.max_diff_lines) {
+            Some(truncated) => {
+                let full_path = self.diff_dir.join(format!("{}.{}.full.diff", file_id, kind));
+                let full_path = diffstore::write(&full_path, &self.colorize_diff(unified_diff, file_path), self.config.compress_diffs)?;
+                (truncated, Some(full_path))
+            }
+            None => (unified_diff, None),
+        };
+        let unified_diff = self.colorize_diff(unified_diff, file_path);
+        let diff_path = diffstore::write(&diff_path, &unified_diff, self.config.compress_diffs)?;
+        
+        let revert = self.record_content_states(file_path, original_content, modified_content);
+
+        // 保存统计信息
+        let stats = DiffStats {
+            added,
+            removed,
+            file: file_path.to_string_lossy().to_string(),
+            preview,
+            revert,
+            first_change_line,
+            change_description,
+            binary: false,
+            full_diff_path,
+            source_sha256,
+            matched,
+            snippet,
+            rename: false,
+            old_path: None,
+            new_path: None,
+        };
+        
+        let stats_path = self.diff_dir.join(format!("{}.{}.stats.json", file_id, kind));
         fs::write(&stats_path, serde_json::to_string(&stats)?)?;
         
         Ok((diff_path, stats))
     }
 
+    /// NOTCH_DIFF_COLOR=1 或 diff --color 时给 unified diff 加 ANSI 颜色；git 补丁保持原样以便 git apply
+    fn colorize_diff(&self, diff: String, file_path: &Path) -> String {
+        if !self.color_diffs || self.config.diff_style == DiffStyle::Git {
+            return diff;
+        }
+        highlight::colorize(&diff, file_path).unwrap_or(diff)
+    }
+
+    fn write_binary_diff(&self, file_path: &Path, original: &[u8], new_size: usize, preview: bool) -> Result<(PathBuf, DiffStats)> {
+        let file_id = self.generate_file_id(file_path);
+        let kind = if preview { "preview" } else { "applied" };
+
+        let diff_path = self.diff_dir.join(format!("{}.{}.diff", file_id, kind));
+        let content = format!("Binary file changed ({} bytes → {} bytes)\n", original.len(), new_size);
+        let diff_path = diffstore::write(&diff_path, &content, self.config.compress_diffs)?;
+
+        let stats = DiffStats {
+            added: 0,
+            removed: 0,
+            file: file_path.to_string_lossy().to_string(),
+            preview,
+            revert: false,
+            first_change_line: None,
+            change_description: None,
+            binary: true,
+            full_diff_path: None,
+            source_sha256: hex::encode(Sha256::digest(original)),
+            matched: None,
+            snippet: None,
+            rename: false,
+            old_path: None,
+            new_path: None,
+        };
+        let stats_path = self.diff_dir.join(format!("{}.{}.stats.json", file_id, kind));
+        fs::write(&stats_path, serde_json::to_string(&stats)?)?;
+
+        Ok((diff_path, stats))
+    }
+
+    /// 重命名/移动文件的预览：git 风格的 rename from/to 头，两个文件都可读时附上内容 diff
+    fn generate_rename_preview(&self, old_path: &Path, new_path: &Path) -> Result<(PathBuf, DiffStats)> {
+        let file_id = self.generate_file_id(old_path);
+        let old_relative = self.project_relative_path(old_path).display().to_string();
+        let new_relative = self.project_relative_path(new_path).display().to_string();
+
+        let mut content = format!(
+            "diff --git a/{} b/{}\nrename from {}\nrename to {}\n",
+            old_relative, new_relative, old_relative, new_relative
+        );
+        let original = fs::read_to_string(old_path).ok();
+        let (mut added, mut removed) = (0, 0);
+        if let (Some(original), Ok(modified)) = (&original, fs::read_to_string(new_path)) {
+            let diff = TextDiff::from_lines(original.as_str(), modified.as_str());
+            for change in diff.iter_all_changes() {
+                match change.tag() {
+                    ChangeTag::Insert => added += 1,
+                    ChangeTag::Delete => removed += 1,
+                    ChangeTag::Equal => {}
+                }
+            }
+            content.push_str(
+                &diff
+                    .unified_diff()
+                    .context_radius(self.diff_context)
+                    .header(&format!("a/{}", old_relative), &format!("b/{}", new_relative))
+                    .to_string(),
+            );
+        }
+
+        let diff_path = self.diff_dir.join(format!("{}.preview.diff", file_id));
+        let diff_path = diffstore::write(&diff_path, &content, self.config.compress_diffs)?;
+
+        let stats = DiffStats {
+            added,
+            removed,
+            file: new_path.to_string_lossy().to_string(),
+            preview: true,
+            revert: false,
+            first_change_line: None,
+            change_description: None,
+            binary: false,
+            full_diff_path: None,
+            source_sha256: original.as_deref().map(content_hash).unwrap_or_default(),
+            matched: None,
+            snippet: None,
+            rename: true,
+            old_path: Some(old_path.to_string_lossy().to_string()),
+            new_path: Some(new_path.to_string_lossy().to_string()),
+        };
+        let stats_path = self.diff_dir.join(format!("{}.preview.stats.json", file_id));
+        fs::write(&stats_path, serde_json::to_string(&stats)?)?;
+
+        Ok((diff_path, stats))
+    }
+
+    /// 记录文件在本会话中出现过的内容状态，返回修改后的内容是否回到了某个之前的状态
+    fn record_content_states(&self, file_path: &Path, original_content: &str, modified_content: &str) -> bool {
+        if original_content == modified_content {
+            return false;
+        }
+
+        let original_hash = content_hash(original_content);
+        let modified_hash = content_hash(modified_content);
+        let key = self.get_relative_path(file_path);
+
+        self.update_session_state(|session| {
+            let history = session.file_states.entry(key).or_default();
+
+            // 预览和实际应用会对同一次修改各记录一次，重复的变换沿用上次的判断
+            if history.states.len() >= 2
+                && history.states[history.states.len() - 2..] == [original_hash.clone(), modified_hash.clone()]
+            {
+                return history.last_revert;
+            }
+
+            let revert = history.states.contains(&modified_hash);
+            for hash in [original_hash, modified_hash] {
+                history.states.retain(|seen| *seen != hash);
+                history.states.push(hash);
+            }
+            let overflow = history.states.len().saturating_sub(MAX_FILE_STATES);
+            history.states.drain(..overflow);
+            history.last_revert = revert;
+
+            revert
+        })
+        .unwrap_or(false)
+    }
+
     fn generate_file_id(&self, file_path: &Path) -> String {
         let mut hasher = Sha256::new();
         hasher.update(file_path.to_string_lossy().as_bytes());
         hex::encode(hasher.finalize())
     }
 
+    /// 统一的增删行数展示，按 stats_style 配置
+    fn format_stats(&self, stats: &DiffStats) -> String {
+        if stats.binary {
+            return t("binary_file").to_string();
+        }
+        self.format_line_stats(stats.added, stats.removed)
+    }
+
+    fn format_line_stats(&self, added: usize, removed: usize) -> String {
+        match self.config.stats_style {
+            StatsStyle::Short => format!("+{} -{}", added, removed),
+            StatsStyle::Verbose => tf("verbose_stats", &[&added, &removed]),
+        }
+    }
+
+    /// 相对项目根的路径，用于补丁和配置匹配；展示用 get_relative_path
+    fn project_relative_path<'a>(&self, file_path: &'a Path) -> &'a Path {
+        file_path.strip_prefix(&self.project_path).unwrap_or(file_path)
+    }
+
     fn get_relative_path(&self, file_path: &Path) -> String {
-        file_path
-            .strip_prefix(&self.project_path)
-            .unwrap_or(file_path)
-            .to_string_lossy()
-            .to_string()
+        let relative = self.project_relative_path(file_path);
+        // 子项目内的文件再去掉 path_prefix，子项目外的保持仓库相对路径
+        let relative = match self.config.path_prefix.as_deref() {
+            Some(prefix) => relative.strip_prefix(prefix.trim_matches('/')).unwrap_or(relative),
+            None => relative,
+        };
+        relative.to_string_lossy().to_string()
     }
 
     fn send_notification(
         &self,
         title: String,
         message: String,
-        notification_type: &str,
+        notification_type: NotificationType,
         priority: u8,
     ) -> Result<()> {
         self.send_notification_with_metadata(title, message, notification_type, priority, HashMap::new())
@@ -930,56 +3524,403 @@ impl NotchHook {
         &self,
         title: String,
         message: String,
-        notification_type: &str,
+        notification_type: NotificationType,
+        priority: u8,
+        extra_metadata: HashMap<String, String>,
+    ) -> Result<()> {
+        self.notify(title, message, notification_type, priority.min(PRIORITY_MAX), extra_metadata)
+    }
+
+    /// 安全检测专用，使用保留的 PRIORITY_CRITICAL
+    fn send_critical_notification(&self, title: String, message: String, notification_type: NotificationType) -> Result<()> {
+        self.notify(title, message, notification_type, PRIORITY_CRITICAL, HashMap::new())
+    }
+
+    fn notify(
+        &self,
+        title: String,
+        message: String,
+        notification_type: NotificationType,
         priority: u8,
         extra_metadata: HashMap<String, String>,
     ) -> Result<()> {
+        let notification = self.build_notification(title, message, notification_type, priority, extra_metadata);
+        self.deliver(notification)
+    }
+
+    fn build_notification(
+        &self,
+        title: String,
+        message: String,
+        notification_type: NotificationType,
+        priority: u8,
+        extra_metadata: HashMap<String, String>,
+    ) -> Notification {
         let mut metadata = HashMap::new();
         metadata.insert("source".to_string(), "claude-code".to_string());
         metadata.insert("project".to_string(), self.project_name.clone());
         metadata.insert("project_path".to_string(), self.project_path.to_string_lossy().to_string());
         metadata.insert("session_duration".to_string(), format!("{:.1}", self.session_start_time.elapsed().as_secs_f64()));
+        if self.session_id != "default" {
+            let (hue, emoji) = session_accent(&self.session_id);
+            metadata.insert("session_accent_color".to_string(), hue.to_string());
+            metadata.insert("session_accent_emoji".to_string(), emoji.to_string());
+        }
+        if let Some((branch, dirty)) = self.git_status() {
+            metadata.insert("git_branch".to_string(), branch.clone());
+            metadata.insert("git_dirty".to_string(), dirty.to_string());
+        }
+        metadata.extend(self.event_metadata.clone());
 
         // 合并额外的 metadata
         for (key, value) in extra_metadata {
             metadata.insert(key, value);
         }
 
-        let notification = Notification {
+        Notification {
             title,
             message,
-            notification_type: notification_type.to_string(),
+            notification_type,
             priority,
             metadata,
-        };
+        }
+    }
+
+    fn git_status(&self) -> Option<&(String, bool)> {
+        self.git_status
+            .get_or_init(|| {
+                let git = |args: &[&str]| {
+                    Command::new("git")
+                        .arg("-C")
+                        .arg(&self.project_path)
+                        .args(args)
+                        .output()
+                        .ok()
+                        .filter(|output| output.status.success())
+                        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+                };
+                let branch = git(&["rev-parse", "--abbrev-ref", "HEAD"])?;
+                // 不扫描未跟踪文件，大仓库里也足够快
+                let dirty = !git(&["status", "--porcelain", "--untracked-files=no"])?.is_empty();
+                Some((branch, dirty))
+            })
+            .as_ref()
+    }
+
+    /// 发送成功后计入 metrics、会话的按类型计数（供 stats 子命令汇总）和通知历史
+    fn record_delivery(&self, notification: &Notification) {
+        let notification_type = notification.notification_type.as_str();
+        metrics::record(&self.diff_dir, metrics::Event::Notification(notification_type));
+        self.update_session_state(|session| *session.notification_counts.entry(notification_type.to_string()).or_default() += 1);
+        history::append(&self.history_path(), notification);
+    }
+
+    fn tool_rule(&self) -> Option<&ToolRule> {
+        self.config.tools.get(self.tool_name.as_deref()?)
+    }
+
+    /// config.priority_overrides 中的强制优先级，工具名优先于通知类型
+    fn priority_override(&self, notification_type: NotificationType) -> Option<u8> {
+        let overrides = &self.config.priority_overrides;
+        self.tool_name
+            .as_deref()
+            .and_then(|tool_name| overrides.get(tool_name))
+            .or_else(|| overrides.get(notification_type.as_str()))
+            .map(|&priority| priority.clamp(0, PRIORITY_MAX as i64) as u8)
+    }
+
+    /// 所有通知的统一出口：计算重要性、去重，再发送
+    fn deliver(&self, mut notification: Notification) -> Result<()> {
+        // config.tools 中的规则；critical 通知是安全提醒，不受影响
+        if let Some(rule) = self.tool_rule().filter(|_| notification.priority < PRIORITY_CRITICAL) {
+            if rule.enabled == Some(false) {
+                debug!("Suppressed by tool rule: {}", notification.title);
+                metrics::record(&self.diff_dir, metrics::Event::Suppressed("tool_rule"));
+                return Ok(());
+            }
+            if let Some(priority) = rule.priority {
+                notification.priority = priority.min(PRIORITY_MAX);
+            }
+            if let Some(icon) = &rule.icon {
+                notification.title = replace_title_icon(&notification.title, icon);
+            }
+        }
+        if notification.priority < PRIORITY_CRITICAL {
+            if let Some(priority) = self.priority_override(notification.notification_type) {
+                notification.priority = priority;
+            }
+        }
+
+        // 修改项目目录以外的文件：提高优先级并在消息前突出标注（消息中已是绝对路径）
+        if self.event_signals.outside_project {
+            notification.message = tf("outside_project", &[&notification.message]);
+            if notification.priority < PRIORITY_MAX {
+                notification.priority += 1;
+            }
+            notification.metadata.insert("outside_project".to_string(), "true".to_string());
+        }
+
+        // 优先级的所有调整都在上面完成，这里按最终优先级过滤
+        if notification.priority < self.min_priority {
+            debug!("Below min priority {}: {}", self.min_priority, notification.title);
+            metrics::record(&self.diff_dir, metrics::Event::Suppressed("min_priority"));
+            return Ok(());
+        }
+
+        let lines_changed = notification
+            .metadata
+            .get("lines_changed")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+        let importance = importance::compute_importance(&ImportanceContext {
+            priority: notification.priority,
+            is_error: notification.notification_type == NotificationType::Error,
+            lines_changed,
+            ..self.event_signals
+        });
+        notification.metadata.insert("importance".to_string(), format!("{:.2}", importance));
+
+        if self.suppressed_by_debugging(notification.priority) {
+            debug!("Suppressed during debugging: {}", notification.title);
+            metrics::record(&self.diff_dir, metrics::Event::Suppressed("debugging"));
+            return Ok(());
+        }
+
+        let value = serde_json::to_value(&notification)?;
+        if let Some(path) = &self.record_path {
+            if let Err(e) = cassette::append(path, cassette::Record::Notification(value.clone())) {
+                warn!("Failed to record notification: {}", e);
+            }
+        }
+        self.captured.borrow_mut().push(value);
+
+        if self.validate_only {
+            let errors = schema::validate_notification(&serde_json::to_value(&notification)?);
+            for error in &errors {
+                eprintln!("[VALIDATE] {}: {}", notification.title, error);
+            }
+            self.validation_results.borrow_mut().push(errors);
+            return Ok(());
+        }
+
+        // dry run 不经过去重/限流，也不写历史和 metrics，每次都能看到完整输出
+        if self.dry_run {
+            return self.send_via_socket(&notification);
+        }
+
+        let fingerprint = dedup::fingerprint(
+            &notification.title,
+            &notification.message,
+            notification.notification_type.as_str(),
+            self.config.fuzzy_dedup,
+        );
+        if dedup::check_and_record(&self.diff_dir.join("dedup.json"), &fingerprint) {
+            debug!("Suppressed duplicate notification: {}", notification.title);
+            metrics::record(&self.diff_dir, metrics::Event::Suppressed("dedup"));
+            return Ok(());
+        }
+
+        if self.config.defer_low_priority && notification.priority < 2 {
+            debug!("Deferred low-priority notification: {}", notification.title);
+            if let Err(e) = queue::push(&self.deferred_path(), serde_json::to_value(&notification)?) {
+                warn!("Failed to defer notification: {}", e);
+            }
+            return Ok(());
+        }
+
+        // 短时间内大量工具调用时丢弃低优先级通知；错误通知和等待用户响应的 Notification hook 提醒不受限
+        let rate_limit_exempt = notification.notification_type == NotificationType::Error
+            || notification.metadata.get("event_type").is_some_and(|event_type| event_type == "notification");
+        if !rate_limit_exempt && !ratelimit::acquire(&self.diff_dir.join("ratelimit.json"), notification.priority) {
+            debug!("Rate limited: {}", notification.title);
+            metrics::record(&self.diff_dir, metrics::Event::Suppressed("ratelimit"));
+            return Ok(());
+        }
+
+        // 优先走 Unix Socket；App 在远程机器或虚拟机中时可选降级到 HTTP
+        match self.send_via_socket(&notification) {
+            Ok(()) => {
+                self.record_delivery(&notification);
+                self.drain_pending_queue();
+            }
+            // App 在运行但拒绝了这条通知，不降级也不排队
+            Err(e) if e.is::<transport::Rejected>() => error!("{}", e),
+            Err(socket_error) => {
+                metrics::record(&self.diff_dir, metrics::Event::SocketFailure);
+                match self.send_via_http_fallback(&notification, socket_error) {
+                    Ok(()) => self.record_delivery(&notification),
+                    Err(e) => {
+                        error!("Failed to send notification: {:#}", e);
+                        warn!("请确保NotchNoti应用正在运行");
+                        if self.config.queue_when_offline {
+                            if let Err(e) = queue::push(&self.queue_path(), serde_json::to_value(&notification)?) {
+                                warn!("Failed to queue notification: {}", e);
+                            }
+                        }
+                    }
+                }
+            }
+        }
 
-        // 只使用 Unix Socket，不再降级到HTTP
-        if let Err(e) = self.send_via_socket(&notification) {
-            eprintln!("[ERROR] Failed to send notification via socket: {}", e);
-            eprintln!("[INFO] 请确保NotchNoti应用正在运行");
+        // 错误和高优先级通知额外发到 error_transport，失败不影响主通道
+        if let Some(error_transport) = &self.config.error_transport {
+            if notification.notification_type == NotificationType::Error || notification.priority >= 3 {
+                match error_transport.send(&serde_json::to_string(&notification)?, self.config.error_transport_ack, self.socket.framed) {
+                    Ok(ack) => debug!("Error transport ack: {}", ack.summary()),
+                    Err(e) => warn!("Failed to deliver to error transport ({}): {}", error_transport.describe(), e),
+                }
+            }
         }
 
         Ok(())
     }
     
-    fn send_via_socket(&self, notification: &Notification) -> Result<()> {
-        // 连接到 Unix Socket
-        let mut stream = UnixStream::connect(&self.socket_path)
-            .context("Failed to connect to Unix socket")?;
+    fn noise_state_path(&self) -> PathBuf {
+        self.diff_dir.join("noise.json")
+    }
 
-        // 序列化并发送 JSON
-        let json = serde_json::to_string(notification)?;
-        stream.write_all(json.as_bytes())
-            .context("Failed to write to socket")?;
+    fn queue_path(&self) -> PathBuf {
+        self.diff_dir.join("pending.json")
+    }
+
+    /// defer_low_priority 攒着的通知，与离线队列分开，避免下一次成功发送就把它们补发出去
+    fn deferred_path(&self) -> PathBuf {
+        self.diff_dir.join("deferred.json")
+    }
+
+    fn flush_deferred(&self) {
+        if !self.deferred_path().exists() {
+            return;
+        }
 
-        // 读取响应（可选）
-        let mut response = String::new();
-        stream.read_to_string(&mut response).ok();
+        match self.flush_queue(&self.deferred_path(), None, false) {
+            Ok(0) => {}
+            Ok(sent) => info!("Delivered {} deferred notification(s)", sent),
+            Err(e) => warn!("Failed to deliver deferred notifications: {}", e),
+        }
+    }
+
+    /// 连接恢复后自动补发离线期间的通知，超过 queue_max_age_secs 的直接丢弃
+    fn drain_pending_queue(&self) {
+        let queue_path = self.queue_path();
+        if !queue_path.exists() {
+            return;
+        }
+
+        let cutoff = state::now_millis().saturating_sub(self.config.queue_max_age_secs * 1000);
+        match self.flush_queue(&queue_path, Some(cutoff), false) {
+            Ok(0) => {}
+            Ok(sent) => info!("Delivered {} queued notification(s)", sent),
+            Err(e) => warn!("Failed to drain notification queue: {}", e),
+        }
+    }
+
+    /// 按顺序补发队列中 cutoff 之后的通知，发送失败时把剩余的放回队列。返回补发数量。
+    /// 补发成功的通知和直接发送的一样计入统计和历史
+    fn flush_queue(&self, queue_path: &Path, cutoff: Option<u64>, keep_old: bool) -> Result<usize> {
+        // dry run 只打印会补发的通知，队列保持不变
+        if self.dry_run {
+            let queue: queue::PendingQueue = state::load(queue_path);
+            let entries: Vec<_> = queue.entries.iter().filter(|entry| cutoff.is_none_or(|cutoff| entry.queued_at >= cutoff)).collect();
+            for entry in &entries {
+                self.print_dry_run(&entry.notification)?;
+            }
+            return Ok(entries.len());
+        }
+
+        let mut entries = queue::take(queue_path, cutoff, keep_old)?.into_iter();
+        let mut sent = 0;
+
+        while let Some(entry) = entries.next() {
+            if let Err(e) = self.socket.send(&entry.notification.to_string(), self.config.socket_ack) {
+                let remaining: Vec<_> = std::iter::once(entry).chain(entries).collect();
+                queue::requeue(queue_path, remaining)?;
+                return Err(e);
+            }
+            match serde_json::from_value::<Notification>(entry.notification) {
+                Ok(notification) => self.record_delivery(&notification),
+                Err(e) => debug!("Skipping bookkeeping for unrecognized queued notification: {}", e),
+            }
+            sent += 1;
+        }
+
+        Ok(sent)
+    }
+
+    fn handle_flush_command(&self, since: Option<String>, keep_old: bool) -> Result<()> {
+        let cutoff = match since {
+            Some(since) => Some(state::now_millis().saturating_sub(queue::parse_duration(&since)?.as_millis() as u64)),
+            None => None,
+        };
+
+        let sent = self.flush_queue(&self.queue_path(), cutoff, keep_old)?;
+        println!("Delivered {} queued notification(s)", sent);
+        Ok(())
+    }
+
+    /// 本次事件是否要向 Claude Code 输出拒绝决定；此时 stdout 只能有决定 JSON
+    fn enforcing_dangerous(&self) -> bool {
+        self.config.enforce_dangerous && self.event_signals.dangerous
+    }
+
+    /// dry run 的输出，enforce_dangerous 生效时改写到 stderr，不干扰 stdout 上的决定
+    fn print_dry_run(&self, notification: &impl Serialize) -> Result<()> {
+        let pretty = serde_json::to_string_pretty(notification)?;
+        if self.enforcing_dangerous() {
+            eprintln!("{}", pretty);
+        } else {
+            println!("{}", pretty);
+        }
+        Ok(())
+    }
+
+    fn send_via_socket(&self, notification: &Notification) -> Result<()> {
+        if self.dry_run {
+            return self.print_dry_run(notification);
+        }
 
+        let json = serde_json::to_string(notification)?;
+        // 只有带选项（actions）的确认通知需要等待用户选择，其余照常发送
+        if notification.notification_type == NotificationType::Confirmation && notification.metadata.contains_key("actions") {
+            return self.send_and_await_choice(&json);
+        }
+        let mut attempt = 0;
+        let ack = loop {
+            match self.socket.send(&json, self.config.socket_ack) {
+                Ok(ack) => break ack,
+                Err(e) if attempt < SOCKET_RETRY_ATTEMPTS && is_transient_socket_error(&e) => {
+                    let delay = SOCKET_RETRY_BASE_DELAY * 3u32.pow(attempt);
+                    debug!("Socket unavailable ({}), retrying in {:?}", e, delay);
+                    std::thread::sleep(delay);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        if let Some(reason) = ack.rejection() {
+            return Err(transport::Rejected(reason).into());
+        }
         Ok(())
     }
 
 
+    /// socket 发送失败后的 HTTP 降级（NOTCH_HTTP_FALLBACK=1 或设置了 NOTCH_HTTP_URL 时启用）。
+    /// 两者都失败时返回合并的错误，只打印一次
+    fn send_via_http_fallback(&self, notification: &Notification, socket_error: anyhow::Error) -> Result<()> {
+        let Some(url) = http_fallback_url() else {
+            return Err(socket_error.context("socket"));
+        };
+
+        debug!("Socket unavailable ({}), falling back to {}", socket_error, url);
+        match Transport::Webhook(url).send(&serde_json::to_string(notification)?, AckStyle::Ignore, false) {
+            Ok(ack) => {
+                debug!("HTTP fallback ack: {}", ack.summary());
+                Ok(())
+            }
+            Err(http_error) => anyhow::bail!("socket: {:#}; HTTP fallback: {}", socket_error, http_error),
+        }
+    }
+
     fn is_dangerous_operation(&self, tool_name: &str, tool_input: &Option<Value>) -> Result<bool> {
         match tool_name {
             "Bash" => {
@@ -1000,7 +3941,7 @@ impl NotchHook {
 
                         for keyword in &dangerous_keywords {
                             if command.contains(keyword) {
-                                eprintln!("[SECURITY] Detected dangerous command: {}", keyword);
+                                warn!("Detected dangerous command: {}", keyword);
                                 return Ok(true);
                             }
                         }
@@ -1023,7 +3964,7 @@ impl NotchHook {
                         let path_str = file_path.to_string_lossy();
                         for pattern in &sensitive_patterns {
                             if path_str.contains(pattern) {
-                                eprintln!("[SECURITY] Detected sensitive file modification: {}", pattern);
+                                warn!("Detected sensitive file modification: {}", pattern);
                                 return Ok(true);
                             }
                         }
@@ -1042,21 +3983,21 @@ impl NotchHook {
             "Bash" => {
                 if let Some(input) = tool_input {
                     if let Some(command) = input.get("command").and_then(|v| v.as_str()) {
-                        return format!("执行命令: {}", command.chars().take(100).collect::<String>());
+                        return tf("op_run_command", &[&truncate_display(command, 100)]);
                     }
                 }
-                "执行 Bash 命令".to_string()
+                t("op_bash").to_string()
             }
             "Write" | "Edit" => {
                 if let Some(input) = tool_input {
                     if let Ok(Some(file_path)) = self.extract_file_path(tool_name, input) {
                         let relative_path = self.get_relative_path(&file_path);
-                        return format!("修改敏感文件: {}", relative_path);
+                        return tf("op_sensitive_file", &[&relative_path]);
                     }
                 }
-                "修改文件".to_string()
+                t("op_edit_file").to_string()
             }
-            _ => format!("执行操作: {}", tool_name),
+            _ => tf("op_other", &[&tool_name]),
         }
     }
     
@@ -1064,47 +4005,59 @@ impl NotchHook {
         &self,
         title: String,
         message: String,
-        notification_type: &str,
+        notification_type: NotificationType,
         priority: u8,
-        diff_path: Option<PathBuf>,
-        file_path: Option<PathBuf>,
+        diff: DiffAttachment,
         tool_name: &str,
     ) -> Result<()> {
+        // 通用的 metadata（会话、git 状态等）由 build_notification 填充，这里只放 diff 相关的字段
         let mut metadata = HashMap::new();
-        metadata.insert("source".to_string(), "claude-code".to_string());
-        metadata.insert("project".to_string(), self.project_name.clone());
-        metadata.insert("project_path".to_string(), self.project_path.to_string_lossy().to_string());
         metadata.insert("tool_name".to_string(), tool_name.to_string());  // 统一使用 tool_name
-        metadata.insert("event_type".to_string(), "PreToolUse".to_string());  // 统一使用 event_type
-        
-        if let Some(path) = file_path {
-            metadata.insert("file_path".to_string(), path.to_string_lossy().to_string());
+        let event_type = if diff.is_preview { "PreToolUse" } else { "PostToolUse" };
+        metadata.insert("event_type".to_string(), event_type.to_string());  // 统一使用 event_type
+
+        metadata.insert("file_path".to_string(), diff.file_path.to_string_lossy().to_string());
+        metadata.insert("diff_path".to_string(), diff.diff_path.to_string_lossy().to_string());
+        metadata.insert("is_preview".to_string(), diff.is_preview.to_string());
+        metadata.insert("lines_changed".to_string(), diff.lines_changed.to_string());
+        if diffstore::is_compressed(&diff.diff_path) {
+            metadata.insert("compressed".to_string(), "true".to_string());
         }
-        
-        if let Some(path) = diff_path {
-            metadata.insert("diff_path".to_string(), path.to_string_lossy().to_string());
-            metadata.insert("is_preview".to_string(), "true".to_string());
-            eprintln!("[DEBUG] Adding diff_path to metadata: {}", path.display());
+        if diff.revert {
+            metadata.insert("revert".to_string(), "true".to_string());
         }
-        
-        let notification = Notification {
-            title,
-            message,
-            notification_type: notification_type.to_string(),
-            priority,
-            metadata,
+        if let Some(line) = diff.first_change_line {
+            metadata.insert("first_change_line".to_string(), line.to_string());
+        }
+        if let Some(description) = diff.change_description {
+            metadata.insert("change_description".to_string(), description);
+        }
+        if let Some(snippet) = diff.snippet.filter(|_| matches!(tool_name, "Edit" | "Write")) {
+            metadata.insert("snippet".to_string(), snippet);
+        }
+        if let Some(old_path) = diff.renamed_from {
+            metadata.insert("rename".to_string(), "true".to_string());
+            metadata.insert("old_path".to_string(), old_path);
+        }
+        if let Some(full_diff_path) = &diff.full_diff_path {
+            metadata.insert("truncated".to_string(), "true".to_string());
+            metadata.insert("full_diff_path".to_string(), full_diff_path.to_string_lossy().to_string());
+        }
+        debug!("Adding diff_path to metadata: {}", diff.diff_path.display());
+
+        let message = if diff.revert {
+            tf("reverted", &[&message])
+        } else {
+            message
         };
 
+        let notification = self.build_notification(title, message, notification_type, priority.min(PRIORITY_MAX), metadata);
+
         // 打印要发送的完整JSON以便调试
-        eprintln!("[DEBUG] Sending JSON to NotchNoti:");
-        eprintln!("{}", serde_json::to_string_pretty(&notification)?);
-        
-        // 只使用 Unix Socket
-        if let Err(e) = self.send_via_socket(&notification) {
-            eprintln!("[ERROR] Failed to send notification with diff: {}", e);
-        }
-        
-        Ok(())
+        debug!("Sending JSON to NotchNoti:");
+        debug!("{}", serde_json::to_string_pretty(&notification)?);
+
+        self.deliver(notification)
     }
 
     fn handle_diff_command(
@@ -1122,11 +4075,25 @@ impl NotchHook {
                     &path,
                     old_text.as_deref(),
                     new_text.as_deref(),
+                    false,
                 )?;
                 
-                eprintln!("[diff-handler] Generated preview diff: +{} -{} lines", stats.added, stats.removed);
+                debug!("Generated preview diff: +{} -{} lines", stats.added, stats.removed);
+                // stdout 只有这一行 diff 路径，供调用方直接解析；诊断信息一律走 stderr
                 println!("{}", diff_path.display());
             }
+            "show" => {
+                // file_path 为已保存的 diff 文件，.gz 透明解压；着色时从 +++ 头中取原文件路径
+                let diff = diffstore::read(&path)?;
+                let source = diff
+                    .lines()
+                    .find_map(|line| line.strip_prefix("+++ "))
+                    .map(|header| PathBuf::from(header.strip_prefix("b/").unwrap_or(header)));
+                match source.filter(|_| self.color_diffs).and_then(|source| highlight::colorize(&diff, &source)) {
+                    Some(colored) => print!("{}", colored),
+                    None => print!("{}", diff),
+                }
+            }
             _ => {
                 eprintln!("Unknown diff action: {}", action);
             }
@@ -1134,21 +4101,1331 @@ impl NotchHook {
         
         Ok(())
     }
+
+    fn handle_commands_command(&self, session: Option<String>, limit: Option<usize>, absolute: bool) -> Result<()> {
+        let session_path = match session {
+            Some(session_id) => self.diff_dir.join(state::session_file_name(&session_id)),
+            None => match state::latest_session_file(&self.diff_dir) {
+                Some(path) => path,
+                None => {
+                    eprintln!("No session found for project: {}", self.project_name);
+                    return Ok(());
+                }
+            },
+        };
+
+        let session: SessionState = state::load(&session_path);
+        let limit = limit.unwrap_or(self.config.command_history_size);
+        let skip = session.recent_commands.len().saturating_sub(limit);
+
+        let now = state::now_millis();
+
+        for record in session.recent_commands.iter().skip(skip) {
+            println!("[{}] {}", timefmt::format_entry_time(record.at, now, absolute), record.command);
+        }
+
+        Ok(())
+    }
 }
 
 fn main() -> Result<()> {
+    // 诊断日志只写 stderr，stdout 留给 diff 路径和 hook 决定；级别由 NOTCH_LOG 控制（error/warn/info/debug，默认 warn）
+    env_logger::Builder::from_env(env_logger::Env::new().filter_or("NOTCH_LOG", "warn"))
+        .format_timestamp(None)
+        .format_target(false)
+        .init();
+
     let cli = Cli::parse();
-    let hook = NotchHook::new()?;
+    let mut hook = NotchHook::new(cli.socket_path, cli.dry_run)?;
+    hook.validate_only = cli.validate_only;
+    hook.record_path = cli.record;
+    hook.socket.framed |= cli.framed;
+    if cli.quiet {
+        hook.min_priority = PRIORITY_MAX;
+    } else if let Some(min_priority) = cli.min_priority {
+        hook.min_priority = min_priority;
+    }
+
+    if let Some(cassette) = cli.replay_cassette {
+        return hook.replay_cassette(&cassette, cli.check);
+    }
     
     match cli.command {
-        Some(Commands::Diff { action, file_path, old_text, new_text }) => {
+        Some(Commands::Diff { action, file_path, old_text, new_text, color, context }) => {
+            hook.color_diffs |= color;
+            if let Some(context) = context {
+                hook.diff_context = clamp_diff_context(context);
+            }
             hook.handle_diff_command(&action, &file_path, old_text, new_text)?;
         }
+        Some(Commands::Demo { delay_ms }) => {
+            hook.run_demo(std::time::Duration::from_millis(delay_ms))?;
+        }
+        Some(Commands::Doctor) => {
+            hook.run_doctor()?;
+        }
+        Some(Commands::Test { delay_ms }) => {
+            hook.run_self_test(std::time::Duration::from_millis(delay_ms))?;
+        }
+        Some(Commands::CommandLog { session, limit, absolute }) => {
+            hook.handle_commands_command(session, limit, absolute)?;
+        }
+        Some(Commands::Flush { since, keep_old }) => {
+            hook.handle_flush_command(since, keep_old)?;
+        }
+        Some(Commands::Stats { since, json }) => {
+            hook.handle_stats_command(since, json)?;
+        }
+        Some(Commands::History { limit, notification_type, json }) => {
+            hook.handle_history_command(limit, notification_type, json)?;
+        }
+        Some(Commands::Cleanup { older_than }) => {
+            hook.handle_cleanup_command(older_than, hook.dry_run)?;
+        }
         _ => {
             // 默认处理hook事件
-            hook.process_hook_event()?;
+            hook.process_hook_event(cli.event_json_pointer.as_deref())?;
         }
     }
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, Write};
+    use std::time::Instant;
+
+    /// 只构建、不发送通知的 hook（validate_only），项目和状态都在临时目录中
+    fn test_hook(dir: &Path, config: Config) -> NotchHook {
+        let project_path = dir.join("project");
+        // 与实际布局一致（.../diffs/<project>），历史等按 diff_dir 推导的路径也落在临时目录中
+        let diff_dir = dir.join("diffs").join("project");
+        fs::create_dir_all(&project_path).unwrap();
+        fs::create_dir_all(&diff_dir).unwrap();
+
+        let mut hook = NotchHook::with_state(project_path, "project".to_string(), diff_dir, config, dir.join("notch.sock"), false);
+        hook.validate_only = true;
+        hook
+    }
+
+    fn event(json: &str) -> HookEvent {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn passthrough_copies_configured_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+        config.passthrough_fields.insert("Grep".to_string(), vec!["-i".to_string(), "pattern".to_string()]);
+        let hook = test_hook(dir.path(), config);
+
+        let metadata = hook.passthrough_metadata(&event(
+            r#"{"hook_event_name":"PreToolUse","tool_name":"Grep","tool_input":{"pattern":"fn main","-i":true,"path":"src"}}"#,
+        ));
+
+        assert_eq!(metadata.get("input_pattern").map(String::as_str), Some("fn main"));
+        assert_eq!(metadata.get("input_-i").map(String::as_str), Some("true"));
+        assert_eq!(metadata.len(), 2);
+
+        let other_tool = hook.passthrough_metadata(&event(
+            r#"{"hook_event_name":"PreToolUse","tool_name":"Glob","tool_input":{"pattern":"*.rs"}}"#,
+        ));
+        assert!(other_tool.is_empty());
+    }
+
+    #[test]
+    fn token_estimate_for_known_sizes() {
+        assert_eq!(estimate_tokens("abcdefgh"), 2);
+        assert_eq!(estimate_tokens("abcde"), 2);
+        assert_eq!(format_token_estimate(1200), "~1.2k tokens");
+        assert_eq!(format_token_estimate(999), "~999 tokens");
+
+        let dir = tempfile::tempdir().unwrap();
+        let hook = test_hook(dir.path(), Config { show_token_estimate: true, ..Config::default() });
+        let file = hook.project_path.join("big.txt");
+        fs::write(&file, "x".repeat(4800)).unwrap();
+
+        let read = event(&format!(
+            r#"{{"hook_event_name":"PreToolUse","tool_name":"Read","tool_input":{{"file_path":"{}"}}}}"#,
+            file.display()
+        ));
+        assert_eq!(hook.estimate_event_tokens(&read), Some(1200));
+        assert_eq!(hook.token_suffix(&read), " [~1.2k tokens]");
+
+        let edit = event(
+            r#"{"hook_event_name":"PreToolUse","tool_name":"Edit","tool_input":{"file_path":"a.rs","old_string":"12345678","new_string":"1234"}}"#,
+        );
+        assert_eq!(hook.estimate_event_tokens(&edit), Some(3));
+        // 小操作只写 metadata，不在消息中显示
+        assert_eq!(hook.token_suffix(&edit), "");
+
+        let disabled = test_hook(dir.path(), Config::default());
+        assert_eq!(disabled.estimate_event_tokens(&read), None);
+    }
+
+    #[test]
+    fn git_style_diff_applies_to_the_original_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let hook = test_hook(dir.path(), Config { diff_style: DiffStyle::Git, ..Config::default() });
+        let file = hook.project_path.join("src").join("lib.rs");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        let original = "fn a() {}\nfn b() {}\nfn c() {}\n";
+        fs::write(&file, original).unwrap();
+
+        let (diff_path, stats) = hook.write_diff(&file, original, "fn a() {}\nfn b() { todo!() }\nfn c() {}\n", true, None).unwrap();
+        let patch = fs::read_to_string(&diff_path).unwrap();
+
+        assert!(patch.starts_with("diff --git a/src/lib.rs b/src/lib.rs\n"), "{}", patch);
+        assert!(patch.contains("--- a/src/lib.rs\n+++ b/src/lib.rs\n"), "{}", patch);
+        assert_eq!((stats.added, stats.removed), (1, 1));
+        let check = Command::new("git").args(["apply", "--check"]).arg(&diff_path).current_dir(&hook.project_path).output().unwrap();
+        assert!(check.status.success(), "{}", String::from_utf8_lossy(&check.stderr));
+    }
+
+    #[test]
+    fn keeps_the_last_commands_redacted_and_skips_ignored_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config { command_history_size: 3, ..Config::default() });
+        let commands = ["cargo build", "ls -la", "cargo test", "git status", "API_TOKEN=abc123 make deploy"];
+        for command in commands {
+            let json = serde_json::json!({
+                "hook_event_name": "PreToolUse",
+                "session_id": "s1",
+                "tool_name": "Bash",
+                "tool_input": { "command": command },
+            });
+            hook.handle_event(&serde_json::from_value(json).unwrap()).unwrap();
+        }
+
+        let recent: Vec<String> = hook.load_session_state().recent_commands.into_iter().map(|record| record.command).collect();
+        assert_eq!(recent, ["cargo test", "git status", "API_TOKEN=*** make deploy"]);
+    }
+
+    #[test]
+    fn empty_bash_output_confirms_only_when_configured() {
+        let quiet = r#"{"hook_event_name":"PostToolUse","session_id":"s1","tool_name":"Bash","tool_input":{"command":"cargo fmt"},"tool_output":{"stdout":""}}"#;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config::default());
+        hook.handle_event(&event(quiet)).unwrap();
+        assert!(hook.captured.borrow().is_empty());
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config { always_confirm_completion: true, ..Config::default() });
+        hook.handle_event(&event(quiet)).unwrap();
+        let captured = hook.captured.borrow();
+        assert_eq!(captured.len(), 1);
+        assert_eq!(captured[0]["type"], "success");
+    }
+
+    #[test]
+    fn model_name_goes_into_metadata_and_session_title() {
+        let dir = tempfile::tempdir().unwrap();
+        std::env::set_var("CLAUDE_MODEL", "claude-opus-4-1-20250805");
+        let mut hook = test_hook(dir.path(), Config::default());
+        std::env::remove_var("CLAUDE_MODEL");
+
+        hook.handle_event(&event(r#"{"hook_event_name":"SessionStart","session_id":"s1"}"#)).unwrap();
+        {
+            let captured = hook.captured.borrow();
+            assert_eq!(captured[0]["metadata"]["model"], "claude-opus-4-1-20250805");
+            assert!(captured[0]["title"].as_str().unwrap().ends_with("(Opus)"), "{}", captured[0]["title"]);
+        }
+
+        // 事件中的 model 字段优先于环境变量
+        hook.handle_event(&event(
+            r#"{"hook_event_name":"PreToolUse","session_id":"s1","model":{"id":"claude-sonnet-4-5","display_name":"Sonnet 4.5"},"tool_name":"Bash","tool_input":{"command":"cargo build"}}"#,
+        ))
+        .unwrap();
+        assert_eq!(hook.captured.borrow().last().unwrap()["metadata"]["model"], "claude-sonnet-4-5");
+        assert_eq!(model_display_name("claude-3-5-haiku"), "Haiku");
+    }
+
+    #[test]
+    fn notch_events_allowlist_ignores_unlisted_events() {
+        // 只有 process_hook_event 读取 NOTCH_EVENTS，不影响其他测试
+        std::env::set_var("NOTCH_EVENTS", "PreToolUse, stop");
+        let results = [
+            event_enabled("pre_tool_use"),
+            event_enabled("Stop"),
+            event_enabled("PostToolUse"),
+            event_enabled("Notification"),
+        ];
+        std::env::remove_var("NOTCH_EVENTS");
+
+        assert_eq!(results, [true, true, false, false]);
+        assert!(event_enabled("PostToolUse"));
+    }
+
+    #[test]
+    fn recognizes_test_files_by_name_and_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let hook = test_hook(dir.path(), Config::default());
+        let project = hook.project_path.clone();
+
+        for test_file in ["src/parser_test.rs", "app/test_models.py", "web/button.spec.ts", "tests/cli.rs", "pkg/__tests__/a.js"] {
+            assert!(hook.is_test_file(&project.join(test_file)), "{}", test_file);
+        }
+        for source_file in ["src/parser.rs", "app/models.py", "src/testing.rs", "tests.rs"] {
+            assert!(!hook.is_test_file(&project.join(source_file)), "{}", source_file);
+        }
+    }
+
+    #[test]
+    fn test_file_edit_uses_tests_category() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config::default());
+        fs::create_dir_all(hook.project_path.join("tests")).unwrap();
+        fs::write(hook.project_path.join("tests").join("cli.rs"), "let a = 1;\n").unwrap();
+
+        hook.handle_event(&event(
+            r#"{"hook_event_name":"PreToolUse","session_id":"s1","tool_name":"Edit","tool_input":{"file_path":"tests/cli.rs","old_string":"a","new_string":"b"}}"#,
+        ))
+        .unwrap();
+
+        let captured = hook.captured.borrow();
+        assert_eq!(captured[0]["metadata"]["category"], "tests");
+        assert!(captured[0]["title"].as_str().unwrap().contains("🧪"), "{}", captured[0]["title"]);
+    }
+
+    #[test]
+    fn crafted_events_build_schema_conformant_notifications() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config::default());
+        let events = [
+            r#"{"hook_event_name":"SessionStart","session_id":"s1"}"#,
+            r#"{"hook_event_name":"PreToolUse","session_id":"s1","tool_name":"Bash","tool_input":{"command":"rm -rf build"}}"#,
+            r#"{"hook_event_name":"PostToolUse","session_id":"s1","tool_name":"Bash","tool_input":{"command":"cargo test"},"error":"exit 101"}"#,
+            r#"{"hook_event_name":"Notification","session_id":"s1"}"#,
+            r#"{"hook_event_name":"Stop","session_id":"s1"}"#,
+        ];
+        for json in events {
+            hook.handle_event(&event(json)).unwrap();
+        }
+
+        let results = hook.validation_results.borrow();
+        assert!(results.len() >= events.len());
+        assert!(results.iter().all(|errors| errors.is_empty()), "{:?}", results);
+        drop(results);
+        assert!(hook.report_validation().is_ok());
+    }
+
+    #[test]
+    fn editing_a_file_back_is_flagged_as_revert() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config::default());
+        let file = hook.project_path.join("lib.rs");
+        fs::write(&file, "let x = 1;\n").unwrap();
+        let edit = |phase: &str, old: &str, new: &str| {
+            event(&format!(
+                r#"{{"hook_event_name":"{}","session_id":"s1","tool_name":"Edit","tool_input":{{"file_path":"lib.rs","old_string":"{}","new_string":"{}"}}}}"#,
+                phase, old, new
+            ))
+        };
+        let last_revert = |hook: &NotchHook| hook.captured.borrow().last().unwrap()["metadata"].get("revert").cloned();
+
+        hook.handle_event(&edit("PreToolUse", "x = 1", "x = 2")).unwrap();
+        assert_eq!(last_revert(&hook), None);
+        fs::write(&file, "let x = 2;\n").unwrap();
+        hook.handle_event(&edit("PostToolUse", "x = 1", "x = 2")).unwrap();
+
+        hook.handle_event(&edit("PreToolUse", "x = 2", "x = 1")).unwrap();
+        assert_eq!(last_revert(&hook), Some(Value::from("true")));
+    }
+
+    #[test]
+    fn env_setup_commands_are_downgraded_or_suppressed() {
+        let config = Config::default();
+        for command in ["source .env", "export PATH=$HOME/bin:$PATH", "nvm use 18", "conda activate ml", ". ./venv/bin/activate"] {
+            let classification = classify_bash_command(command, &config);
+            assert!(classification.should_notify, "{}", command);
+            assert_eq!(classification.priority, 0, "{}", command);
+        }
+        assert_eq!(classify_bash_command("cargo build", &config).priority, 1);
+
+        let suppress = Config { suppress_env_setup: true, ..Config::default() };
+        assert!(!classify_bash_command("nvm use 18", &suppress).should_notify);
+
+        // 不通知的环境命令也不进入命令历史
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), suppress);
+        hook.handle_event(&event(r#"{"hook_event_name":"PreToolUse","session_id":"s1","tool_name":"Bash","tool_input":{"command":"source .env"}}"#))
+            .unwrap();
+        assert!(hook.load_session_state().recent_commands.is_empty());
+        assert!(hook.captured.borrow().is_empty());
+    }
+
+    #[test]
+    fn mass_delete_is_critical_and_normal_priorities_are_clamped() {
+        assert!(is_mass_delete("rm -rf build"));
+        assert!(is_mass_delete("sudo rm -R /var/cache/app"));
+        assert!(is_mass_delete("cd out && find . -name '*.o' -delete"));
+        assert!(!is_mass_delete("rm build.log"));
+        assert!(!is_mass_delete("grep -r rm src"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config::default());
+        hook.handle_event(&event(r#"{"hook_event_name":"PreToolUse","session_id":"s1","tool_name":"Bash","tool_input":{"command":"rm -rf build"}}"#))
+            .unwrap();
+        assert!(hook.captured.borrow().iter().any(|notification| notification["priority"] == PRIORITY_CRITICAL));
+
+        // 普通通知无法达到保留的级别
+        hook.send_notification("loud".to_string(), String::new(), NotificationType::Info, 9).unwrap();
+        assert_eq!(hook.captured.borrow().last().unwrap()["priority"], PRIORITY_MAX);
+    }
+
+    #[test]
+    fn streaming_updates_share_one_notification_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config::default());
+        let update = |partial: bool, output: &str| {
+            event(&format!(
+                r#"{{"hook_event_name":"PostToolUse","session_id":"s1","tool_name":"Bash","tool_use_id":"toolu_1","is_partial":{},"tool_input":{{"command":"cargo build"}},"tool_output":{{"stdout":"{}"}}}}"#,
+                partial, output
+            ))
+        };
+
+        hook.handle_event(&update(true, "Compiling serde")).unwrap();
+        hook.handle_event(&update(true, "Compiling serde\\nCompiling app")).unwrap();
+        hook.handle_event(&update(false, "Finished dev profile")).unwrap();
+
+        let captured = hook.captured.borrow();
+        let summary: Vec<(&str, &str, &str)> = captured
+            .iter()
+            .map(|n| (n["metadata"]["notification_id"].as_str().unwrap(), n["metadata"]["partial"].as_str().unwrap(), n["type"].as_str().unwrap()))
+            .collect();
+        assert_eq!(summary, [("toolu_1", "true", "progress"), ("toolu_1", "true", "progress"), ("toolu_1", "false", "success")]);
+        assert_eq!(captured[1]["message"], "Compiling app");
+        assert!(captured[2]["message"].as_str().unwrap().ends_with("Finished dev profile"));
+    }
+
+    #[test]
+    fn error_notifications_also_go_to_the_error_transport() {
+        let dir = tempfile::tempdir().unwrap();
+        let received = collect_titles(&dir.path().join("notch.sock"));
+        let alerts = std::os::unix::net::UnixListener::bind(dir.path().join("alerts.sock")).unwrap();
+        let alert = std::thread::spawn(move || {
+            // 读完一条就关闭连接，Ignore 回执不会一直等下去
+            let (stream, _) = alerts.accept().unwrap();
+            let mut line = String::new();
+            io::BufReader::new(stream).read_line(&mut line).unwrap();
+            serde_json::from_str::<Value>(&line).unwrap()["title"].as_str().unwrap().to_string()
+        });
+        let wait = Duration::from_secs(2);
+
+        let mut hook = socket_hook(dir.path());
+        hook.config.error_transport = Some(Transport::Socket(dir.path().join("alerts.sock")));
+        hook.send_notification("build failed".to_string(), "exit 101".to_string(), NotificationType::Error, 2).unwrap();
+        assert_eq!(received.recv_timeout(wait).unwrap(), "build failed");
+        assert_eq!(alert.join().unwrap(), "build failed");
+
+        // 普通通知只走主通道；error_transport 不可用时主通道照常送达
+        hook.config.error_transport = Some(Transport::Socket(dir.path().join("missing.sock")));
+        hook.send_notification("edited".to_string(), "main.rs".to_string(), NotificationType::ToolUse, 2).unwrap();
+        hook.send_notification("tests failed".to_string(), "2 failed".to_string(), NotificationType::Error, 2).unwrap();
+        assert_eq!(received.recv_timeout(wait).unwrap(), "edited");
+        assert_eq!(received.recv_timeout(wait).unwrap(), "tests failed");
+    }
+
+    #[test]
+    fn estimated_stats_match_the_full_diff() {
+        let dir = tempfile::tempdir().unwrap();
+        let hook = test_hook(dir.path(), Config::default());
+        let file = dir.path().join("project").join("lib.rs");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, "fn a() {}\nfn b() {\n    old();\n}\nfn c() {}\n").unwrap();
+
+        for (old, new) in [
+            ("    old();", "    new();"),
+            ("fn b() {\n    old();\n}", "fn b() {\n    first();\n    second();\n}"),
+            ("    old();\n", ""),
+        ] {
+            let (_, stats) = hook.generate_preview_diff(&file, Some(old), Some(new), false).unwrap();
+            assert_eq!(estimate_diff_stats(old, new), (stats.added, stats.removed), "{:?} -> {:?}", old, new);
+        }
+    }
+
+    #[test]
+    fn edits_outside_the_project_are_flagged_and_bumped() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config::default());
+        let inside_file = dir.path().join("project").join("notes.txt");
+        let outside_file = outside.path().join("notes.txt");
+        fs::create_dir_all(inside_file.parent().unwrap()).unwrap();
+        for file in [&inside_file, &outside_file] {
+            fs::write(file, "one\n").unwrap();
+            let json = serde_json::json!({
+                "hook_event_name": "PreToolUse",
+                "session_id": "s1",
+                "tool_name": "Edit",
+                "tool_input": {"file_path": file, "old_string": "one", "new_string": "two"},
+            });
+            hook.handle_event(&serde_json::from_value(json).unwrap()).unwrap();
+        }
+
+        let captured = hook.captured.borrow();
+        assert_eq!(captured.len(), 2);
+        let (inside, outside) = (&captured[0], &captured[1]);
+        assert!(inside["metadata"].get("outside_project").is_none());
+        assert_eq!(outside["metadata"]["outside_project"], "true");
+        assert_eq!(outside["priority"].as_u64().unwrap(), inside["priority"].as_u64().unwrap() + 1);
+        // 项目外的文件显示绝对路径，并带有醒目的前缀
+        let message = outside["message"].as_str().unwrap();
+        assert!(message.starts_with(&i18n::tf("outside_project", &[&""])), "{}", message);
+        assert!(message.contains(outside_file.to_str().unwrap()), "{}", message);
+    }
+
+    #[test]
+    fn stats_style_controls_the_stats_in_edit_messages() {
+        for (style, expected) in [(StatsStyle::Short, "+2 -1".to_string()), (StatsStyle::Verbose, i18n::tf("verbose_stats", &[&2, &1]))] {
+            let dir = tempfile::tempdir().unwrap();
+            let mut hook = test_hook(dir.path(), Config { stats_style: style, ..Config::default() });
+            let file = dir.path().join("project").join("lib.rs");
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(&file, "fn a() {}\nold();\n").unwrap();
+
+            assert_eq!(hook.format_line_stats(2, 1), expected);
+            let json = serde_json::json!({
+                "hook_event_name": "PreToolUse",
+                "session_id": "s1",
+                "tool_name": "Edit",
+                "tool_input": {"file_path": file, "old_string": "old();", "new_string": "first();\nsecond();"},
+            });
+            hook.handle_event(&serde_json::from_value(json).unwrap()).unwrap();
+            let message = hook.captured.borrow()[0]["message"].as_str().unwrap().to_string();
+            assert!(message.contains(&expected), "{:?}: {}", style, message);
+        }
+    }
+
+    #[test]
+    fn large_binary_write_shows_its_size_without_a_diff() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config::default());
+        let file = dir.path().join("project").join("model.bin");
+        // 带 NUL 的伪二进制内容，大小超过默认的 1MB 阈值
+        let content: String = (0..(2 * 1024 * 1024 + 300 * 1024)).map(|i| char::from((i % 128) as u8)).collect();
+
+        for phase in ["PreToolUse", "PostToolUse"] {
+            let json = serde_json::json!({
+                "hook_event_name": phase,
+                "session_id": "s1",
+                "tool_name": "Write",
+                "tool_input": {"file_path": file, "content": content},
+            });
+            hook.handle_event(&serde_json::from_value(json).unwrap()).unwrap();
+        }
+
+        let captured = hook.captured.borrow();
+        let warning = &captured[0];
+        assert_eq!(warning["title"], format!("[project] 📦 {}", i18n::tf("large_write", &[&"2.3MB"])));
+        assert_eq!(warning["metadata"]["size_bytes"], content.len().to_string());
+        assert!(captured.iter().all(|notification| notification["metadata"].get("diff_path").is_none()));
+        let diff_files: Vec<_> = fs::read_dir(&hook.diff_dir)
+            .unwrap()
+            .flatten()
+            .filter(|entry| entry.path().to_string_lossy().contains(".diff"))
+            .collect();
+        assert!(diff_files.is_empty(), "{:?}", diff_files);
+    }
+
+    #[test]
+    fn build_targets_are_shown_and_release_targets_elevated() {
+        let config = Config::default();
+        let deploy = classify_bash_command("make deploy ENV=prod", &config);
+        assert_eq!(deploy.label.as_deref(), Some("make deploy"));
+        assert_eq!(deploy.priority, 2);
+
+        let build = classify_bash_command("cargo build --release", &config);
+        assert_eq!(build.label.as_deref(), Some("cargo build --release"));
+        assert_eq!(build.priority, 1);
+
+        // 带参数的选项被跳过，清理类目标降低优先级
+        let clean = classify_bash_command("make -C crates/app clean", &config);
+        assert_eq!(clean.label.as_deref(), Some("make clean"));
+        assert_eq!(clean.priority, 0);
+
+        assert!(parse_build_target("ls -la").is_none());
+        assert!(parse_build_target("make").is_none());
+    }
+
+    #[test]
+    fn permission_changes_escalate_when_world_writable_or_recursive() {
+        let config = Config::default();
+        let executable = classify_bash_command("chmod +x script.sh", &config);
+        assert_eq!(executable.icon, "🔑");
+        assert_eq!(executable.priority, 1);
+        assert_eq!(executable.label, Some(i18n::tf("permission_change", &[&"script.sh", &"+x", &""])));
+
+        let open = classify_bash_command("chmod -R 777 .", &config);
+        assert_eq!(open.priority, 3);
+        assert_eq!(open.label, Some(i18n::tf("permission_change", &[&".", &"777", &" -R"])));
+
+        // chmod -x 是权限而不是选项；o+w 与 777 一样视为所有人可写
+        let change = parse_permission_change("chmod -x bin/run").unwrap();
+        assert_eq!((change.mode.as_str(), change.targets.as_slice()), ("-x", &["bin/run".to_string()][..]));
+        assert!(parse_permission_change("sudo chmod o+w shared").unwrap().world_writable());
+        assert!(!parse_permission_change("chmod 755 bin").unwrap().world_writable());
+        assert_eq!(classify_bash_command("chown -R www-data:www-data public", &config).priority, 3);
+    }
+
+    #[test]
+    fn first_change_line_points_into_the_original_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config::default());
+        let file = dir.path().join("project").join("lib.rs");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, "line 1\nline 2\nline 3\nline 4\nline 5\n").unwrap();
+
+        let (_, stats) = hook.generate_preview_diff(&file, Some("line 3\nline 4"), Some("line three\nline 4"), false).unwrap();
+        assert_eq!(stats.first_change_line, Some(3));
+        // 末尾追加时指向最后一行之后
+        let (_, stats) = hook.generate_preview_diff(&file, Some("line 5\n"), Some("line 5\nline 6\n"), false).unwrap();
+        assert_eq!(stats.first_change_line, Some(6));
+
+        let json = serde_json::json!({
+            "hook_event_name": "PreToolUse",
+            "session_id": "s1",
+            "tool_name": "Edit",
+            "tool_input": {"file_path": file, "old_string": "line 2", "new_string": "line two"},
+        });
+        hook.handle_event(&serde_json::from_value(json).unwrap()).unwrap();
+        assert_eq!(hook.captured.borrow()[0]["metadata"]["first_change_line"], "2");
+    }
+
+    #[test]
+    fn path_prefix_strips_the_subproject_from_displayed_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config { path_prefix: Some("crates/compiler/".to_string()), ..Config::default() };
+        assert_eq!(subproject_name(&config), Some("compiler"));
+        let keep_name = Config { path_prefix: config.path_prefix.clone(), path_prefix_project_name: false, ..Config::default() };
+        assert_eq!(subproject_name(&keep_name), None);
+
+        let hook = test_hook(dir.path(), config);
+        let project = dir.path().join("project");
+        assert_eq!(hook.get_relative_path(&project.join("crates/compiler/parser/lexer.rs")), "parser/lexer.rs");
+        // 子项目外的文件仍显示仓库相对路径
+        assert_eq!(hook.get_relative_path(&project.join("crates/runtime/src/lib.rs")), "crates/runtime/src/lib.rs");
+    }
+
+    #[test]
+    fn excluded_extensions_get_plain_notifications_without_diffs() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config { diff_extensions: vec!["rs".to_string(), "md".to_string()], ..Config::default() };
+        let mut hook = test_hook(dir.path(), config);
+        let project = dir.path().join("project");
+
+        for name in ["Cargo.lock", "main.rs"] {
+            fs::write(project.join(name), "one\n").unwrap();
+            let json = serde_json::json!({
+                "hook_event_name": "PreToolUse",
+                "session_id": "s1",
+                "tool_name": "Edit",
+                "tool_input": {"file_path": project.join(name), "old_string": "one", "new_string": "two"},
+            });
+            hook.handle_event(&serde_json::from_value(json).unwrap()).unwrap();
+        }
+
+        let captured = hook.captured.borrow();
+        assert_eq!(captured.len(), 2);
+        assert!(captured[0]["metadata"].get("diff_path").is_none(), "{}", captured[0]);
+        assert!(captured[0]["message"].as_str().unwrap().contains("Cargo.lock"));
+        let diff_path = captured[1]["metadata"]["diff_path"].as_str().unwrap();
+        assert!(Path::new(diff_path).exists());
+        let diff_files: Vec<_> = fs::read_dir(&hook.diff_dir)
+            .unwrap()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.to_string_lossy().ends_with(".diff"))
+            .collect();
+        // 只有 main.rs 生成了 diff
+        assert_eq!(diff_files, vec![PathBuf::from(diff_path)]);
+    }
+
+    #[test]
+    fn event_json_pointer_unwraps_a_nested_envelope() {
+        let envelope = serde_json::json!({
+            "source": "proxy",
+            "payload": {"hook": {"hook_event_name": "PreToolUse", "session_id": "s1", "tool_name": "Read"}},
+        });
+
+        let event: HookEvent = serde_json::from_value(unwrap_event(envelope.clone(), Some("/payload/hook")).unwrap()).unwrap();
+        assert_eq!(event.hook_event_name, "PreToolUse");
+        assert_eq!(event.tool_name.as_deref(), Some("Read"));
+        // 没有 pointer 时原样使用整个输入
+        assert_eq!(unwrap_event(envelope.clone(), None).unwrap(), envelope);
+
+        let missing = unwrap_event(envelope.clone(), Some("/payload/event")).unwrap_err();
+        assert!(missing.to_string().contains("not found"), "{}", missing);
+        let not_object = unwrap_event(envelope, Some("/source")).unwrap_err();
+        assert!(not_object.to_string().contains("does not point to an object"), "{}", not_object);
+    }
+
+    #[test]
+    fn force_push_forms_are_distinguished() {
+        let force = parse_force_push("git push --force origin main").unwrap();
+        assert!(!force.with_lease);
+        assert_eq!(force.target(), "origin main");
+        let short = parse_force_push("cargo test && git push -f").unwrap();
+        assert!(!short.with_lease);
+        assert_eq!(short.target(), "");
+        let lease = parse_force_push("git push --force-with-lease origin feature").unwrap();
+        assert!(lease.with_lease);
+        assert!(parse_force_push("git push origin main").is_none());
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config::default());
+        for command in ["git push --force origin main", "git push -f", "git push --force-with-lease origin feature"] {
+            let json = serde_json::json!({
+                "hook_event_name": "PreToolUse",
+                "session_id": "s1",
+                "tool_name": "Bash",
+                "tool_input": {"command": command},
+            });
+            hook.handle_event(&serde_json::from_value(json).unwrap()).unwrap();
+        }
+
+        let captured = hook.captured.borrow();
+        let critical: Vec<&Value> = captured.iter().filter(|n| n["title"] == format!("[project] ⚠️ {}", t("force_push"))).collect();
+        assert_eq!(critical.len(), 2, "{:?}", captured);
+        assert!(critical.iter().all(|n| n["priority"] == PRIORITY_CRITICAL && n["type"] == "security"));
+        assert!(critical[0]["message"].as_str().unwrap().starts_with("origin main → "));
+        // --force-with-lease 只是一条普通的 git 通知
+        let lease_title = format!("[project] 🔀 {}", tf("force_push_lease", &[&"origin feature"]).trim_end());
+        let lease = captured.iter().find(|n| n["title"] == lease_title).unwrap();
+        assert_eq!(lease["priority"], 2);
+    }
+
+    #[test]
+    fn delivered_errors_are_counted_in_the_prometheus_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let received = collect_titles(&dir.path().join("notch.sock"));
+        let hook = socket_hook(dir.path());
+
+        for title in ["build failed", "tests failed"] {
+            hook.send_notification(title.to_string(), "exit 1".to_string(), NotificationType::Error, 2).unwrap();
+            received.recv_timeout(Duration::from_secs(2)).unwrap();
+        }
+        // 重复的通知被去重，计入 suppressed 而不是 notifications
+        hook.send_notification("tests failed".to_string(), "exit 1".to_string(), NotificationType::Error, 2).unwrap();
+
+        let prom = fs::read_to_string(hook.diff_dir.join("metrics.prom")).unwrap();
+        let lines: Vec<&str> = prom.lines().collect();
+        assert!(lines.contains(&"notch_notifications_total{type=\"error\"} 2"), "{}", prom);
+        assert!(lines.contains(&"notch_suppressed_total{reason=\"dedup\"} 1"), "{}", prom);
+        assert!(lines.contains(&"notch_socket_failures_total 0"), "{}", prom);
+    }
+
+    #[test]
+    fn replace_all_previews_every_occurrence() {
+        let content = "let a = old_name(1);\nlet b = 2;\nlet c = old_name(3);\nlet d = old_name(4);\n";
+        for (replace_all, expected) in [(true, (3, 3)), (false, (1, 1))] {
+            let dir = tempfile::tempdir().unwrap();
+            let mut hook = test_hook(dir.path(), Config::default());
+            let file = dir.path().join("project").join("lib.rs");
+            fs::write(&file, content).unwrap();
+
+            let (_, stats) = hook.generate_preview_diff(&file, Some("old_name"), Some("new_name"), replace_all).unwrap();
+            assert_eq!((stats.added, stats.removed), expected, "replace_all = {}", replace_all);
+
+            let json = serde_json::json!({
+                "hook_event_name": "PreToolUse",
+                "session_id": "s1",
+                "tool_name": "Edit",
+                "tool_input": {"file_path": file, "old_string": "old_name", "new_string": "new_name", "replace_all": replace_all},
+            });
+            hook.handle_event(&serde_json::from_value(json).unwrap()).unwrap();
+            let message = hook.captured.borrow()[0]["message"].as_str().unwrap().to_string();
+            assert_eq!(message.contains(&tf("replace_all_scope", &[&3])), replace_all, "{}", message);
+        }
+    }
+
+    #[test]
+    fn edit_milestone_fires_once_per_interval() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config::default());
+        hook.edit_milestone = Some(3);
+        let file = dir.path().join("project").join("lib.rs");
+
+        for i in 0..7 {
+            let json = serde_json::json!({
+                "hook_event_name": "PostToolUse",
+                "session_id": "s1",
+                "tool_name": "Edit",
+                "tool_input": {"file_path": file, "old_string": format!("v{}", i), "new_string": format!("v{}\nw{}", i + 1, i)},
+                "tool_output": {"success": true},
+            });
+            hook.handle_event(&serde_json::from_value(json).unwrap()).unwrap();
+        }
+
+        let captured = hook.captured.borrow();
+        let milestones: Vec<&Value> = captured.iter().filter(|n| n["metadata"].get("edit_count").is_some()).collect();
+        assert_eq!(milestones.len(), 2, "{:?}", captured);
+        assert_eq!(milestones[0]["title"], format!("[project] 📊 {}", tf("edit_milestone", &[&3])));
+        assert_eq!(milestones[1]["metadata"]["edit_count"], "6");
+        // 附带会话累计的增删行数（每次 +2 -1）
+        assert_eq!(milestones[1]["metadata"]["lines_added"], "12");
+        assert_eq!(milestones[1]["metadata"]["lines_removed"], "6");
+    }
+
+    #[test]
+    fn recorded_session_replays_to_identical_notifications() {
+        let events = [
+            r#"{"hook_event_name":"SessionStart","session_id":"s1"}"#,
+            r#"{"hook_event_name":"PreToolUse","session_id":"s1","tool_name":"Bash","tool_input":{"command":"cargo build"}}"#,
+            r#"{"hook_event_name":"PostToolUse","session_id":"s1","tool_name":"Bash","tool_input":{"command":"cargo test"},"tool_output":{"stdout":"test result: FAILED","exit_code":101}}"#,
+            r#"{"hook_event_name":"Stop","session_id":"s1"}"#,
+        ];
+        let record_dir = tempfile::tempdir().unwrap();
+        let cassette_path = record_dir.path().join("session.jsonl");
+        let mut hook = test_hook(record_dir.path(), Config::default());
+        hook.record_path = Some(cassette_path.clone());
+        // 与 process_hook_event 相同：先记下事件，再记下它产生的通知
+        for json in events {
+            cassette::append(&cassette_path, cassette::Record::Event(serde_json::from_str(json).unwrap())).unwrap();
+            hook.handle_event(&event(json)).unwrap();
+        }
+        let recorded = hook.captured.borrow().len();
+        assert!(recorded >= 3, "{:?}", hook.captured.borrow());
+        let entries = cassette::load(&cassette_path).unwrap();
+        assert_eq!(entries.len(), events.len() + recorded);
+
+        // 在全新的状态目录中重放，产生的通知与录制时一致
+        let replay_dir = tempfile::tempdir().unwrap();
+        test_hook(replay_dir.path(), Config::default()).replay_cassette(&cassette_path, true).unwrap();
+
+        // 改动录下的通知后，比对失败
+        let tampered = fs::read_to_string(&cassette_path).unwrap().replacen("[project]", "[other]", 1);
+        fs::write(&cassette_path, tampered).unwrap();
+        let replay_dir = tempfile::tempdir().unwrap();
+        assert!(test_hook(replay_dir.path(), Config::default()).replay_cassette(&cassette_path, true).is_err());
+    }
+
+    #[test]
+    fn migrations_are_elevated_and_rollbacks_critical() {
+        assert_eq!(parse_migration("alembic upgrade head", &[]), Some(Some(MigrationDirection::Up)));
+        assert_eq!(parse_migration("rails db:rollback STEP=1", &[]), Some(Some(MigrationDirection::Down)));
+        assert_eq!(parse_migration("ls migrations", &[]), None);
+        // 配置的额外迁移命令
+        let extra = vec!["./bin/migrate".to_string()];
+        assert_eq!(parse_migration("./bin/migrate down 3", &extra), Some(Some(MigrationDirection::Down)));
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config::default());
+        for command in ["alembic upgrade head", "alembic downgrade -1"] {
+            let json = serde_json::json!({
+                "hook_event_name": "PreToolUse",
+                "session_id": "s1",
+                "tool_name": "Bash",
+                "tool_input": {"command": command},
+            });
+            hook.handle_event(&serde_json::from_value(json).unwrap()).unwrap();
+        }
+
+        let captured = hook.captured.borrow();
+        assert_eq!(captured.len(), 2, "{:?}", captured);
+        assert_eq!(captured[0]["title"], format!("[project] 🗄️ {}", t("migration_up")));
+        assert_eq!(captured[0]["priority"], 3);
+        assert_eq!(captured[1]["title"], format!("[project] 🗄️ {}", t("migration_down")));
+        assert_eq!(captured[1]["priority"], PRIORITY_CRITICAL);
+    }
+
+    #[test]
+    fn session_errors_are_summarized_at_stop() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config::default());
+        hook.handle_event(&event(
+            r#"{"hook_event_name":"PostToolUse","session_id":"s1","tool_name":"Bash","tool_input":{"command":"cargo test"},"error":"exit code 101\ntest tests::parse ... FAILED"}"#,
+        ))
+        .unwrap();
+        hook.handle_event(&event(
+            r#"{"hook_event_name":"PostToolUse","session_id":"s1","tool_name":"Read","tool_input":{"file_path":"missing.rs"},"error":"File does not exist"}"#,
+        ))
+        .unwrap();
+        hook.handle_event(&event(r#"{"hook_event_name":"Stop","session_id":"s1"}"#)).unwrap();
+
+        let stop = hook.captured.borrow().last().unwrap().clone();
+        assert_eq!(stop["title"], format!("[project] 🎉 {}", tf("session_end_errors", &[&2])));
+        assert_eq!(stop["metadata"]["error_count"], "2");
+        let message = stop["message"].as_str().unwrap();
+        assert!(message.contains("❌ Bash: exit code 101; Read: File does not exist"), "{}", message);
+        // metadata 中保留完整的错误信息
+        assert_eq!(stop["metadata"]["errors"], "Bash: exit code 101\ntest tests::parse ... FAILED\nRead: File does not exist");
+
+        // 汇总后清空，下一次 Stop 恢复普通的结束通知
+        hook.handle_event(&event(r#"{"hook_event_name":"Stop","session_id":"s1"}"#)).unwrap();
+        let stop = hook.captured.borrow().last().unwrap().clone();
+        assert_eq!(stop["title"], format!("[project] 🎉 {}", t("session_end")));
+        assert!(stop["metadata"].get("error_count").is_none());
+    }
+
+    #[test]
+    fn binary_files_get_a_size_only_diff() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config::default());
+        let file = dir.path().join("project").join("logo.png");
+        let mut bytes = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        bytes.extend_from_slice(b"IEND tag");
+        fs::write(&file, &bytes).unwrap();
+
+        let (diff_path, stats) = hook.generate_preview_diff(&file, Some("IEND tag"), Some("IEND"), false).unwrap();
+        assert!(stats.binary);
+        assert_eq!((stats.added, stats.removed), (0, 0));
+        let expected = format!("Binary file changed ({} bytes → {} bytes)", bytes.len(), bytes.len() - 4);
+        assert!(fs::read_to_string(&diff_path).unwrap().contains(&expected));
+
+        // 预览失败不再退回无 diff 的分支，通知照常带上 diff
+        let json = serde_json::json!({
+            "hook_event_name": "PreToolUse",
+            "session_id": "s1",
+            "tool_name": "Edit",
+            "tool_input": {"file_path": file, "old_string": "IEND tag", "new_string": "IEND"},
+        });
+        hook.handle_event(&serde_json::from_value(json).unwrap()).unwrap();
+        let notification = hook.captured.borrow()[0].clone();
+        assert!(notification["metadata"].get("diff_path").is_some(), "{}", notification);
+        assert!(notification["message"].as_str().unwrap().contains(t("binary_file")), "{}", notification);
+    }
+
+    #[test]
+    fn newline_delimited_events_are_processed_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config::default());
+        let input = concat!(
+            r#"{"hook_event_name":"PreToolUse","session_id":"s1","tool_name":"Bash","tool_use_id":"toolu_1","tool_input":{"command":"cargo build"}}"#,
+            "\n\n",
+            r#"{"hook_event_name":"PostToolUse","session_id":"s1","tool_name":"Bash","tool_use_id":"toolu_1","tool_input":{"command":"cargo build"},"tool_output":{"stdout":"ok"}}"#,
+            "\n  \n",
+        );
+
+        hook.process_input(input, None).unwrap();
+
+        let captured = hook.captured.borrow();
+        assert_eq!(captured.len(), 2, "{:?}", captured);
+        // 同一次调用中的 Pre 和 Post 能对上，Post 带有耗时
+        assert!(captured[0]["metadata"].get("duration_ms").is_none());
+        assert!(captured[1]["metadata"]["duration_ms"].as_str().unwrap().parse::<u64>().is_ok(), "{}", captured[1]);
+        drop(captured);
+
+        let error = hook.process_input("{\"hook_event_name\":\"Stop\"}\n{not json}\n", None).unwrap_err();
+        assert!(error.to_string().contains("#2"), "{}", error);
+    }
+
+    #[test]
+    fn session_accent_is_stable_per_session() {
+        let accent = session_accent("2f6c1d0e-session-a");
+        assert_eq!(session_accent("2f6c1d0e-session-a"), accent);
+        assert!(accent.0 < 360);
+        assert_ne!(session_accent("9b41e7aa-session-b"), accent);
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config::default());
+        for (index, session_id) in ["2f6c1d0e-session-a", "9b41e7aa-session-b", "2f6c1d0e-session-a"].iter().enumerate() {
+            let json = serde_json::json!({
+                "hook_event_name": "PreToolUse",
+                "session_id": session_id,
+                "tool_name": "Bash",
+                "tool_input": {"command": format!("cargo test --test case_{}", index)},
+            });
+            hook.handle_event(&serde_json::from_value(json).unwrap()).unwrap();
+        }
+
+        let captured = hook.captured.borrow();
+        let accents: Vec<(String, String)> = captured
+            .iter()
+            .map(|n| (n["metadata"]["session_accent_color"].as_str().unwrap().to_string(), n["metadata"]["session_accent_emoji"].as_str().unwrap().to_string()))
+            .collect();
+        assert_eq!(accents.len(), 3, "{:?}", captured);
+        assert_eq!(accents[0], (accent.0.to_string(), accent.1.to_string()));
+        assert_ne!(accents[0], accents[1]);
+        assert_eq!(accents[0], accents[2]);
+    }
+
+    #[test]
+    fn format_size_switches_units_at_the_boundaries() {
+        assert_eq!(format_size(0), "0B");
+        assert_eq!(format_size(1023), "1023B");
+        assert_eq!(format_size(1024), "1.0KB");
+        assert_eq!(format_size(1536), "1.5KB");
+        // 不会出现 1024.0KB
+        assert_eq!(format_size(1024 * 1024 - 1), "1.0MB");
+        assert_eq!(format_size(1024 * 1024), "1.0MB");
+        assert_eq!(format_size(2_411_725), "2.3MB");
+        assert_eq!(format_size(1024 * 1024 * 1024), "1.0GB");
+        // 最大单位是 GB
+        assert_eq!(format_size(2048 * 1024 * 1024 * 1024), "2048.0GB");
+    }
+
+    #[test]
+    fn truncate_display_keeps_grapheme_clusters_whole() {
+        let family = "👨\u{200d}👩\u{200d}👧\u{200d}👦";
+        let text = format!("{}{}ok", family, family);
+        assert_eq!(truncate_display(&text, 1), format!("{}…", family));
+        assert_eq!(truncate_display(&text, 2), format!("{}{}…", family, family));
+        assert_eq!(truncate_display(&text, 4), text);
+
+        // 国旗由两个区域指示符组成，不会被拆成半个
+        let flags = "🇨🇳🇯🇵🇺🇸";
+        assert_eq!(truncate_display(flags, 2), "🇨🇳🇯🇵…");
+        // 组合字符与前面的字母算一个字素
+        assert_eq!(truncate_display("e\u{301}te\u{301}", 1), "e\u{301}…");
+        assert_eq!(truncate_display("cargo build", 80), "cargo build");
+    }
+
+    #[test]
+    fn identical_notification_within_the_dedup_window_is_suppressed() {
+        let dir = tempfile::tempdir().unwrap();
+        let received = collect_titles(&dir.path().join("notch.sock"));
+        let hook = socket_hook(dir.path());
+        let read = |title: &str, notification_type| hook.send_notification(title.to_string(), "src/lib.rs".to_string(), notification_type, 1);
+
+        read("read", NotificationType::Info).unwrap();
+        read("read", NotificationType::Info).unwrap();
+        // 类型不同不算重复
+        read("read", NotificationType::ToolUse).unwrap();
+
+        let wait = Duration::from_secs(2);
+        assert_eq!(received.recv_timeout(wait).unwrap(), "read");
+        assert_eq!(received.recv_timeout(wait).unwrap(), "read");
+        assert!(received.recv_timeout(Duration::from_millis(100)).is_err());
+        let prom = fs::read_to_string(hook.diff_dir.join("metrics.prom")).unwrap();
+        assert!(prom.lines().any(|line| line == "notch_suppressed_total{reason=\"dedup\"} 1"), "{}", prom);
+    }
+
+    #[test]
+    fn stop_hook_active_suppresses_stop_notification() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config::default());
+
+        hook.handle_event(&event(r#"{"hook_event_name":"Stop","session_id":"s1","stop_hook_active":true}"#)).unwrap();
+        assert!(hook.captured.borrow().is_empty());
+
+        hook.handle_event(&event(r#"{"hook_event_name":"Stop","session_id":"s1","stop_hook_active":false}"#)).unwrap();
+        assert_eq!(hook.captured.borrow().len(), 1);
+    }
+
+    #[test]
+    fn stop_without_stop_hook_active_still_notifies() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config::default());
+
+        // 旧版 Claude Code 不发送该字段
+        let stop = event(r#"{"hook_event_name":"Stop","session_id":"s1"}"#);
+        assert_eq!(stop.stop_hook_active, None);
+        hook.handle_event(&stop).unwrap();
+        assert_eq!(hook.captured.borrow().len(), 1);
+
+        // 嵌套的 SubagentStop 同样跳过
+        hook.handle_event(&event(r#"{"hook_event_name":"SubagentStop","session_id":"s1","stop_hook_active":true}"#)).unwrap();
+        assert_eq!(hook.captured.borrow().len(), 1);
+        hook.handle_event(&event(r#"{"hook_event_name":"SubagentStop","session_id":"s1"}"#)).unwrap();
+        assert_eq!(hook.captured.borrow().len(), 2);
+    }
+
+    #[test]
+    fn stop_lists_files_created_in_the_session() {
+        let write = r#"{"hook_event_name":"PreToolUse","session_id":"s1","tool_name":"Write","tool_input":{"file_path":"src/new.rs","content":"fn main() {}"}}"#;
+        let stop = r#"{"hook_event_name":"Stop","session_id":"s1"}"#;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config { summarize_created_files: true, ..Config::default() });
+        hook.handle_event(&event(write)).unwrap();
+        hook.handle_event(&event(stop)).unwrap();
+
+        let captured = hook.captured.borrow();
+        let summary = &captured.last().unwrap()["metadata"];
+        assert_eq!(summary["created_files_count"], "1");
+        assert!(summary["created_files"].as_str().unwrap().ends_with("new.rs"));
+        drop(captured);
+
+        // 默认关闭时 Stop 通知不带新建文件列表
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config::default());
+        hook.handle_event(&event(write)).unwrap();
+        hook.handle_event(&event(stop)).unwrap();
+        assert!(hook.captured.borrow().last().unwrap()["metadata"].get("created_files").is_none());
+    }
+
+    #[test]
+    fn debugging_suppresses_low_priority_notifications_when_enabled() {
+        let start_debugging = r#"{"hook_event_name":"PreToolUse","session_id":"s1","tool_name":"mcp__jetbrains__debug_configuration","tool_input":{}}"#;
+        let titles = |hook: &NotchHook| -> Vec<String> {
+            hook.captured.borrow().iter().map(|notification| notification["title"].as_str().unwrap().to_string()).collect()
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config { suppress_while_debugging: true, ..Config::default() });
+        hook.handle_event(&event(start_debugging)).unwrap();
+        hook.send_notification("minor".to_string(), String::new(), NotificationType::Info, 1).unwrap();
+        hook.send_notification("important".to_string(), String::new(), NotificationType::Info, 3).unwrap();
+        let captured = titles(&hook);
+        assert!(!captured.contains(&"minor".to_string()), "{:?}", captured);
+        assert!(captured.contains(&"important".to_string()), "{:?}", captured);
+
+        // 默认关闭：调试期间照常通知
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config::default());
+        hook.handle_event(&event(start_debugging)).unwrap();
+        hook.send_notification("minor".to_string(), String::new(), NotificationType::Info, 1).unwrap();
+        assert!(titles(&hook).contains(&"minor".to_string()));
+    }
+
+    #[test]
+    fn idle_gap_is_metadata_unless_idle_notify_is_set() {
+        let waiting = r#"{"hook_event_name":"Notification","session_id":"s1"}"#;
+        let resumed = r#"{"hook_event_name":"PreToolUse","session_id":"s1","tool_name":"Bash","tool_input":{"command":"cargo build"}}"#;
+        // 模拟用户离开了 10 分钟
+        let wait_ten_minutes = |hook: &mut NotchHook| {
+            hook.handle_event(&event(waiting)).unwrap();
+            let since = state::now_millis() - 10 * 60 * 1000;
+            hook.update_session_state(|session| session.waiting_since = Some(since));
+            hook.captured.borrow_mut().clear();
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config::default());
+        wait_ten_minutes(&mut hook);
+        hook.handle_event(&event(resumed)).unwrap();
+        {
+            let captured = hook.captured.borrow();
+            assert_eq!(captured.len(), 1, "{:?}", captured);
+            assert!(captured[0]["metadata"]["idle_ms"].as_str().unwrap().parse::<u64>().unwrap() >= 10 * 60 * 1000);
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config { idle_notify_secs: 5 * 60, ..Config::default() });
+        wait_ten_minutes(&mut hook);
+        hook.handle_event(&event(resumed)).unwrap();
+        assert_eq!(hook.captured.borrow().len(), 2);
+    }
+
+    #[test]
+    fn success_after_failure_notifies_recovery() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config::default());
+        let recovered = |hook: &NotchHook| {
+            hook.captured
+                .borrow()
+                .iter()
+                .filter(|notification| notification["metadata"]["event_type"] == "tool_recovered")
+                .count()
+        };
+
+        // 之前没有失败过的成功只是普通完成
+        hook.handle_event(&event(
+            r#"{"hook_event_name":"PostToolUse","session_id":"s1","tool_name":"Bash","tool_input":{"command":"cargo test"}}"#,
+        ))
+        .unwrap();
+        assert_eq!(recovered(&hook), 0);
+
+        hook.handle_event(&event(
+            r#"{"hook_event_name":"PostToolUse","session_id":"s1","tool_name":"Bash","tool_input":{"command":"cargo test"},"error":"exit 101"}"#,
+        ))
+        .unwrap();
+        hook.handle_event(&event(
+            r#"{"hook_event_name":"PostToolUse","session_id":"s1","tool_name":"Bash","tool_input":{"command":"cargo test"}}"#,
+        ))
+        .unwrap();
+        assert_eq!(recovered(&hook), 1);
+
+        // 恢复后失败记录已清除，再次成功不会重复提示
+        hook.handle_event(&event(
+            r#"{"hook_event_name":"PostToolUse","session_id":"s1","tool_name":"Bash","tool_input":{"command":"cargo test"}}"#,
+        ))
+        .unwrap();
+        assert_eq!(recovered(&hook), 1);
+    }
+
+    fn socket_hook(dir: &Path) -> NotchHook {
+        let mut hook = test_hook(dir, Config::default());
+        hook.validate_only = false;
+        hook
+    }
+
+    #[test]
+    fn send_via_socket_retries_until_the_app_starts_listening() {
+        let dir = tempfile::tempdir().unwrap();
+        let hook = socket_hook(dir.path());
+        let socket_path = dir.path().join("notch.sock");
+
+        // 第一次连接时 socket 还不存在，在第二次尝试（SOCKET_RETRY_BASE_DELAY 之后）前才开始监听
+        let app = std::thread::spawn(move || {
+            std::thread::sleep(SOCKET_RETRY_BASE_DELAY / 2);
+            let listener = std::os::unix::net::UnixListener::bind(&socket_path).unwrap();
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut line = String::new();
+            io::BufReader::new(&stream).read_line(&mut line).unwrap();
+            stream.write_all(b"{\"ok\":true}\n").ok();
+            line
+        });
+
+        let started = Instant::now();
+        let notification = hook.build_notification("title".to_string(), "message".to_string(), NotificationType::Info, 2, HashMap::new());
+        hook.send_via_socket(&notification).unwrap();
+
+        assert!(started.elapsed() >= SOCKET_RETRY_BASE_DELAY);
+        let received: Value = serde_json::from_str(&app.join().unwrap()).unwrap();
+        assert_eq!(received["title"], "title");
+    }
+
+    #[test]
+    fn send_via_socket_gives_up_after_the_retry_attempts() {
+        let dir = tempfile::tempdir().unwrap();
+        let hook = socket_hook(dir.path());
+
+        let started = Instant::now();
+        let notification = hook.build_notification("title".to_string(), "message".to_string(), NotificationType::Info, 2, HashMap::new());
+        let error = hook.send_via_socket(&notification).unwrap_err();
+
+        assert!(is_transient_socket_error(&error));
+        // 每次重试的等待时间 ×3：50ms + 150ms + 450ms
+        let backoff: Duration = (0..SOCKET_RETRY_ATTEMPTS).map(|attempt| SOCKET_RETRY_BASE_DELAY * 3u32.pow(attempt)).sum();
+        assert!(started.elapsed() >= backoff);
+    }
+
+    /// 模拟 App：接受一个连接，读一条通知后回复 reply，并保持连接直到对方断开
+    fn reply_once(socket_path: PathBuf, reply: &'static str) -> std::thread::JoinHandle<Value> {
+        let listener = std::os::unix::net::UnixListener::bind(socket_path).unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = io::BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            stream.write_all(reply.as_bytes()).ok();
+            reader.read_line(&mut String::new()).ok();
+            serde_json::from_str(&line).unwrap()
+        })
+    }
+
+    #[test]
+    fn only_confirmations_with_actions_await_a_choice() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut metadata = HashMap::new();
+        metadata.insert("actions".to_string(), "allow,deny".to_string());
+
+        let hook = socket_hook(dir.path());
+        let app = reply_once(dir.path().join("notch.sock"), "{\"choice\":\"deny\"}\n");
+        let notification = hook.build_notification("title".to_string(), "message".to_string(), NotificationType::Confirmation, 3, metadata);
+        // 带 actions 时等待并记录用户的选择
+        hook.send_via_socket(&notification).unwrap();
+        assert_eq!(hook.reply_decision.get(), Some(confirm::Decision::Deny));
+        drop(hook);
+        app.join().unwrap();
+
+        let hook = socket_hook(dir.path());
+        fs::remove_file(dir.path().join("notch.sock")).unwrap();
+        let app = reply_once(dir.path().join("notch.sock"), "{\"ok\":true}\n");
+        let notification = hook.build_notification("title".to_string(), "message".to_string(), NotificationType::Confirmation, 3, HashMap::new());
+        hook.send_via_socket(&notification).unwrap();
+        assert_eq!(hook.reply_decision.get(), None);
+        drop(hook);
+        app.join().unwrap();
+    }
+
+    /// 模拟 App：接受任意多个连接，把收到的每条通知的标题转发到 channel
+    fn collect_titles(socket_path: &Path) -> std::sync::mpsc::Receiver<String> {
+        let listener = std::os::unix::net::UnixListener::bind(socket_path).unwrap();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let sender = sender.clone();
+                std::thread::spawn(move || {
+                    for line in io::BufReader::new(stream).lines().map_while(Result::ok) {
+                        let notification: Value = serde_json::from_str(&line).unwrap();
+                        sender.send(notification["title"].as_str().unwrap_or_default().to_string()).ok();
+                    }
+                });
+            }
+        });
+        receiver
+    }
+
+    #[test]
+    fn deferred_notifications_are_delivered_and_recorded_at_stop() {
+        let dir = tempfile::tempdir().unwrap();
+        let received = collect_titles(&dir.path().join("notch.sock"));
+        let mut hook = socket_hook(dir.path());
+        hook.config.defer_low_priority = true;
+        let wait = Duration::from_secs(2);
+
+        hook.send_notification("low".to_string(), "later".to_string(), NotificationType::Info, 1).unwrap();
+        hook.send_notification("urgent".to_string(), "now".to_string(), NotificationType::Info, 3).unwrap();
+        // 低优先级的先攒着，高优先级的立即送达
+        assert_eq!(received.recv_timeout(wait).unwrap(), "urgent");
+        assert!(received.recv_timeout(Duration::from_millis(100)).is_err());
+
+        hook.handle_event(&event(r#"{"hook_event_name":"Stop","session_id":"s1"}"#)).unwrap();
+        assert_eq!(received.recv_timeout(wait).unwrap(), "low");
+
+        let history = fs::read_to_string(hook.history_path()).unwrap();
+        let titles: Vec<String> = history
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap()["title"].as_str().unwrap().to_string())
+            .collect();
+        assert!(titles.contains(&"low".to_string()), "{:?}", titles);
+        assert!(titles.contains(&"urgent".to_string()), "{:?}", titles);
+    }
+
+    #[test]
+    fn notify_phase_selects_dual_phase_notifications() {
+        let pre = r#"{"hook_event_name":"PreToolUse","session_id":"s1","tool_name":"Bash","tool_input":{"command":"cargo build"}}"#;
+        let post = r#"{"hook_event_name":"PostToolUse","session_id":"s1","tool_name":"Bash","tool_input":{"command":"cargo build"},"tool_output":{"stdout":"ok"}}"#;
+
+        for (phase, expected) in [(NotifyPhase::Pre, (1, 0)), (NotifyPhase::Post, (0, 1)), (NotifyPhase::Both, (1, 1))] {
+            let dir = tempfile::tempdir().unwrap();
+            let config = Config { notify_phase: phase, ..Config::default() };
+            let mut hook = test_hook(dir.path(), config);
+
+            hook.handle_event(&event(pre)).unwrap();
+            let pre_count = hook.captured.borrow().len();
+            hook.handle_event(&event(post)).unwrap();
+            let post_count = hook.captured.borrow().len() - pre_count;
+
+            assert_eq!((pre_count, post_count), expected, "notify_phase = {:?}", phase);
+        }
+    }
+
+    #[test]
+    fn enforced_dangerous_operation_sends_only_the_blocked_notification() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config { enforce_dangerous: true, ..Config::default() };
+        let mut hook = test_hook(dir.path(), config);
+
+        hook.handle_event(&event(
+            r#"{"hook_event_name":"PreToolUse","session_id":"s1","tool_name":"Bash","tool_input":{"command":"rm -rf build"}}"#,
+        ))
+        .unwrap();
+
+        let captured = hook.captured.borrow();
+        assert_eq!(captured.len(), 1, "{:?}", captured);
+        assert_eq!(captured[0]["metadata"]["blocked"], "true");
+    }
+
+    fn dangerous_warnings(hook: &NotchHook) -> usize {
+        hook.captured
+            .borrow()
+            .iter()
+            .filter(|notification| notification["type"] == "warning" && notification["metadata"]["dangerous"] == "true")
+            .count()
+    }
+
+    #[test]
+    fn dangerous_operation_warns_once_regardless_of_notify_phase() {
+        let dangerous = r#"{"hook_event_name":"PreToolUse","session_id":"s1","tool_name":"Bash","tool_input":{"command":"rm -rf build"}}"#;
+
+        for phase in [NotifyPhase::Pre, NotifyPhase::Post, NotifyPhase::Both] {
+            let dir = tempfile::tempdir().unwrap();
+            let mut hook = test_hook(dir.path(), Config { notify_phase: phase, ..Config::default() });
+            hook.handle_event(&event(dangerous)).unwrap();
+            assert_eq!(dangerous_warnings(&hook), 1, "notify_phase = {:?}", phase);
+        }
+
+        // 被 enforce_dangerous 阻止时只有已阻止的提示，没有危险提醒
+        let dir = tempfile::tempdir().unwrap();
+        let mut hook = test_hook(dir.path(), Config { enforce_dangerous: true, ..Config::default() });
+        hook.handle_event(&event(dangerous)).unwrap();
+        assert_eq!(dangerous_warnings(&hook), 0);
+    }
+}
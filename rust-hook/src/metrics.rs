@@ -0,0 +1,125 @@
+use anyhow::Result;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs::{self, OpenOptions};
+use std::io::Write as _;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::state;
+
+// 计数器累积在 metrics.json（加锁更新），每次更新后重新生成 metrics.prom 供 node_exporter textfile collector 抓取。
+// NOTCH_METRICS=1 时另外把每个事件的耗时逐行追加到 metrics.jsonl，用于分析 hook 开销
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Counters {
+    /// 通知类型 -> 成功发送次数
+    notifications: BTreeMap<String, u64>,
+    /// 抑制原因 -> 次数
+    suppressed: BTreeMap<String, u64>,
+    socket_failures: u64,
+}
+
+pub enum Event<'a> {
+    /// 已发送的通知（类型）
+    Notification(&'a str),
+    /// 被抑制的通知（原因，如 dedup / debugging）
+    Suppressed(&'a str),
+    SocketFailure,
+}
+
+/// 记录一次事件，失败只打印警告，不影响通知本身
+pub fn record(dir: &Path, event: Event) {
+    let result = state::update(&dir.join("metrics.json"), |counters: &mut Counters| {
+        match event {
+            Event::Notification(notification_type) => *counters.notifications.entry(notification_type.to_string()).or_default() += 1,
+            Event::Suppressed(reason) => *counters.suppressed.entry(reason.to_string()).or_default() += 1,
+            Event::SocketFailure => counters.socket_failures += 1,
+        }
+        // 仍在锁内，保证 metrics.prom 与 metrics.json 一致
+        write_prom(&dir.join("metrics.prom"), counters)
+    });
+
+    if let Err(e) = result.and_then(|written| written) {
+        warn!("Failed to update metrics: {}", e);
+    }
+}
+
+fn write_prom(path: &Path, counters: &Counters) -> Result<()> {
+    let mut out = String::new();
+
+    writeln!(out, "# HELP notch_notifications_total Notifications delivered to NotchNoti.")?;
+    writeln!(out, "# TYPE notch_notifications_total counter")?;
+    for (notification_type, count) in &counters.notifications {
+        writeln!(out, "notch_notifications_total{{type=\"{}\"}} {}", escape_label(notification_type), count)?;
+    }
+
+    writeln!(out, "# HELP notch_suppressed_total Notifications suppressed before delivery.")?;
+    writeln!(out, "# TYPE notch_suppressed_total counter")?;
+    for (reason, count) in &counters.suppressed {
+        writeln!(out, "notch_suppressed_total{{reason=\"{}\"}} {}", escape_label(reason), count)?;
+    }
+
+    writeln!(out, "# HELP notch_socket_failures_total Failed attempts to reach the NotchNoti socket.")?;
+    writeln!(out, "# TYPE notch_socket_failures_total counter")?;
+    writeln!(out, "notch_socket_failures_total {}", counters.socket_failures)?;
+
+    // textfile collector 可能随时读取，先写临时文件再 rename
+    let tmp_path = path.with_extension(format!("prom.{}", std::process::id()));
+    fs::write(&tmp_path, out)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// metrics.jsonl 中的一条耗时记录
+#[derive(Debug, Serialize)]
+pub struct Timing<'a> {
+    pub pid: u32,
+    /// CLOCK_MONOTONIC（纳秒），同一次开机内可跨进程比较先后和间隔
+    pub monotonic_ns: u64,
+    /// 墙钟时间（毫秒）
+    pub at: u64,
+    pub event: &'a str,
+    pub tool: Option<&'a str>,
+    /// 处理该事件的总耗时
+    pub total_ms: f64,
+    /// 连接 socket 并写入通知的耗时（不含等待回执）
+    pub socket_ms: f64,
+    pub socket_sends: u32,
+}
+
+pub fn timing_enabled() -> bool {
+    matches!(std::env::var("NOTCH_METRICS").as_deref(), Ok("1" | "true"))
+}
+
+pub fn monotonic_ns() -> u64 {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    // SAFETY: ts 是有效的可写 timespec，CLOCK_MONOTONIC 在 macOS 和 Linux 上都可用
+    if unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) } != 0 {
+        return 0;
+    }
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// 追加一条耗时记录，失败只打印警告
+pub fn append_timing(dir: &Path, timing: &Timing) {
+    let result = serde_json::to_string(timing).map_err(anyhow::Error::from).and_then(|line| {
+        let mut file = OpenOptions::new().create(true).append(true).open(dir.join("metrics.jsonl"))?;
+        writeln!(file, "{}", line)?;
+        Ok(())
+    });
+    if let Err(e) = result {
+        warn!("Failed to append timing metrics: {}", e);
+    }
+}
@@ -0,0 +1,110 @@
+use anyhow::Result;
+use log::{debug, warn};
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::{i18n, DiffAttachment, HookEvent, NotchHook, NotificationType};
+
+// NotebookEdit 的单元格级 diff：只比较被修改的单元格的 source，而不是整个 .ipynb JSON
+
+/// 单元格的 source 可以是字符串，也可以是按行拆开的字符串数组
+fn cell_source(cell: &Value) -> String {
+    match cell.get("source") {
+        Some(Value::String(source)) => source.clone(),
+        Some(Value::Array(lines)) => lines.iter().filter_map(|line| line.as_str()).collect(),
+        _ => String::new(),
+    }
+}
+
+/// 按 cell_id 找到单元格，返回 (下标, 当前 source)；没有 id 的 notebook 用 "cell-3" 或数字下标指代单元格
+fn find_cell(notebook: &Value, cell_id: &str) -> Option<(usize, String)> {
+    let cells = notebook.get("cells")?.as_array()?;
+    let index = cells
+        .iter()
+        .position(|cell| cell.get("id").and_then(|id| id.as_str()) == Some(cell_id))
+        .or_else(|| {
+            let index: usize = cell_id.strip_prefix("cell-").unwrap_or(cell_id).parse().ok()?;
+            (index < cells.len()).then_some(index)
+        })?;
+    Some((index, cell_source(&cells[index])))
+}
+
+/// 修改后单元格的位置（从 1 开始）和修改前后的 source
+fn cell_change(notebook: &Value, tool_input: &Value) -> Option<(usize, String, String)> {
+    let new_source = tool_input.get("new_source").and_then(|v| v.as_str()).unwrap_or_default().to_string();
+    let cell_id = tool_input.get("cell_id").and_then(|v| v.as_str());
+
+    match tool_input.get("edit_mode").and_then(|v| v.as_str()) {
+        // 插入到 cell_id 之后，没有 cell_id 时插入到开头
+        Some("insert") => {
+            let position = match cell_id {
+                Some(cell_id) => find_cell(notebook, cell_id)?.0 + 2,
+                None => 1,
+            };
+            Some((position, String::new(), new_source))
+        }
+        Some("delete") => {
+            let (index, source) = find_cell(notebook, cell_id?)?;
+            Some((index + 1, source, String::new()))
+        }
+        _ => {
+            let (index, source) = find_cell(notebook, cell_id?)?;
+            Some((index + 1, source, new_source))
+        }
+    }
+}
+
+impl NotchHook {
+    /// NotebookEdit 的 PreToolUse：附带目标单元格的 diff，notebook 无法解析或找不到单元格时只发普通通知
+    pub(crate) fn handle_notebook_edit(&self, tool_name: &str, event: &HookEvent) -> Result<()> {
+        let Some(tool_input) = &event.tool_input else {
+            return Ok(());
+        };
+        let Some(notebook_path) = self.extract_file_path(tool_name, tool_input)? else {
+            return Ok(());
+        };
+        let relative_path = self.get_relative_path(&notebook_path);
+
+        let change = fs::read_to_string(&notebook_path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+            .and_then(|notebook| cell_change(&notebook, tool_input));
+        let Some((position, old_source, new_source)) = change else {
+            debug!("Could not locate the edited cell in {}", notebook_path.display());
+            return self.send_notification(
+                format!("[{}] 📓 {}", self.project_name, i18n::t("notebook_edit")),
+                relative_path,
+                NotificationType::ToolUse,
+                2,
+            );
+        };
+
+        let title_key = match tool_input.get("edit_mode").and_then(|v| v.as_str()) {
+            Some("insert") => "notebook_insert_cell",
+            Some("delete") => "notebook_delete_cell",
+            _ => "notebook_edit_cell",
+        };
+        let title = format!("[{}] 📓 {}", self.project_name, i18n::tf(title_key, &[&position]));
+
+        if self.config.preview_diffs && self.diff_enabled_for(&notebook_path) {
+            // diff 以 "notebook#cell-N" 为文件标识，不同单元格的预览和来回修改记录互不干扰
+            let cell_path = PathBuf::from(format!("{}#cell-{}", notebook_path.display(), position));
+            match self.write_diff(&cell_path, &old_source, &new_source, true, None) {
+                Ok((diff_path, stats)) => {
+                    return self.send_notification_with_diff(
+                        title,
+                        i18n::tf("estimate", &[&relative_path, &self.format_stats(&stats)]),
+                        NotificationType::ToolUse,
+                        2,
+                        DiffAttachment::new(diff_path, notebook_path, &stats),
+                        tool_name,
+                    );
+                }
+                Err(e) => warn!("Failed to write notebook cell diff: {}", e),
+            }
+        }
+
+        self.send_notification(title, relative_path, NotificationType::ToolUse, 2)
+    }
+}
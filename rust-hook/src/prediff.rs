@@ -0,0 +1,69 @@
+use log::debug;
+use serde::Deserialize;
+use std::borrow::Cow;
+
+/// 生成 diff 前对文件内容做的规范化处理，减少格式变化带来的噪音
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PrediffFormat {
+    /// 以稳定的键顺序重新格式化 JSON
+    Json,
+}
+
+impl PrediffFormat {
+    fn transform(self, content: &str) -> Option<String> {
+        match self {
+            PrediffFormat::Json => {
+                let value: serde_json::Value = serde_json::from_str(content).ok()?;
+                let mut pretty = serde_json::to_string_pretty(&value).ok()?;
+                pretty.push('\n');
+                Some(pretty)
+            }
+        }
+    }
+}
+
+/// 对修改前后的内容应用同一转换；任一侧无法解析时两侧都保留原始内容
+pub fn apply<'a>(format: PrediffFormat, original: &'a str, modified: &'a str) -> (Cow<'a, str>, Cow<'a, str>) {
+    let transform = |content: &str| {
+        if content.trim().is_empty() {
+            Some(String::new())
+        } else {
+            format.transform(content)
+        }
+    };
+
+    match (transform(original), transform(modified)) {
+        (Some(original), Some(modified)) => (Cow::Owned(original), Cow::Owned(modified)),
+        _ => {
+            debug!("Pre-diff transform {:?} failed, diffing raw content", format);
+            (Cow::Borrowed(original), Cow::Borrowed(modified))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use similar::{ChangeTag, TextDiff};
+
+    #[test]
+    fn minified_json_change_diffs_as_one_line() {
+        let original = r#"{"name":"app","version":"1.0.0","dependencies":{"serde":"1","log":"0.4"}}"#;
+        let modified = r#"{"name":"app","version":"1.1.0","dependencies":{"serde":"1","log":"0.4"}}"#;
+
+        let (original, modified) = apply(PrediffFormat::Json, original, modified);
+        let diff = TextDiff::from_lines(original.as_ref(), modified.as_ref());
+        let changed: Vec<_> = diff.iter_all_changes().filter(|change| change.tag() != ChangeTag::Equal).map(|change| change.value().trim().to_string()).collect();
+
+        // 键按字母序排列，version 在最后一行
+        assert_eq!(changed, [r#""version": "1.0.0""#, r#""version": "1.1.0""#]);
+    }
+
+    #[test]
+    fn unparseable_side_keeps_raw_content() {
+        let (original, modified) = apply(PrediffFormat::Json, r#"{"a":1}"#, "{not json");
+        assert_eq!(original, r#"{"a":1}"#);
+        assert_eq!(modified, "{not json");
+    }
+}
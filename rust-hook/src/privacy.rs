@@ -0,0 +1,103 @@
+use serde::Deserialize;
+use url::Url;
+
+/// 通知中展示 URL 的详细程度，避免把查询参数里的 token 显示出来
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UrlPrivacy {
+    #[default]
+    Full,
+    /// 只保留协议和域名
+    Domain,
+    /// 保留协议、域名和路径，去掉查询参数和片段
+    HostPath,
+}
+
+/// 无法解析的 URL 退回到截断后的原始字符串
+pub fn display_url(raw: &str, privacy: UrlPrivacy) -> String {
+    if privacy == UrlPrivacy::Full {
+        return raw.to_string();
+    }
+
+    let Ok(url) = Url::parse(raw) else {
+        return crate::truncate_display(raw, 100);
+    };
+    let Some(host) = url.host_str() else {
+        return crate::truncate_display(raw, 100);
+    };
+
+    let mut display = format!("{}://{}", url.scheme(), host);
+    if let Some(port) = url.port() {
+        display.push_str(&format!(":{}", port));
+    }
+    if privacy == UrlPrivacy::HostPath {
+        display.push_str(url.path());
+    }
+    display
+}
+
+/// 对命令中出现的 http(s) URL 逐个应用 display_url
+pub fn redact_command_urls(command: &str, privacy: UrlPrivacy) -> String {
+    if privacy == UrlPrivacy::Full {
+        return command.to_string();
+    }
+
+    command
+        .split(' ')
+        .map(|token| {
+            let unquoted = token.trim_matches(|c| c == '"' || c == '\'');
+            if unquoted.starts_with("http://") || unquoted.starts_with("https://") {
+                display_url(unquoted, privacy)
+            } else {
+                token.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// 名称中包含这些关键字的赋值/参数，其值会被遮盖
+const SECRET_KEYWORDS: &[&str] = &["token", "secret", "password", "passwd", "api_key", "apikey"];
+
+/// 遮盖命令中形如 `API_TOKEN=xxx` 或 `--password=xxx` 的敏感值
+pub fn redact_secrets(command: &str) -> String {
+    command
+        .split(' ')
+        .map(|token| match token.split_once('=') {
+            Some((name, value)) if !value.is_empty() && is_secret_name(name) => format!("{}=***", name),
+            _ => token.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn is_secret_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    SECRET_KEYWORDS.iter().any(|keyword| name.contains(keyword))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn domain_mode_drops_path_and_query() {
+        let url = "https://api.example.com:8443/v1/items?token=abc123#top";
+        assert_eq!(display_url(url, UrlPrivacy::Domain), "https://api.example.com:8443");
+        assert_eq!(display_url(url, UrlPrivacy::HostPath), "https://api.example.com:8443/v1/items");
+        assert_eq!(display_url(url, UrlPrivacy::Full), url);
+    }
+
+    #[test]
+    fn malformed_url_falls_back_to_raw_string() {
+        assert_eq!(display_url("not a url?token=abc", UrlPrivacy::Domain), "not a url?token=abc");
+    }
+
+    #[test]
+    fn redacts_urls_inside_commands() {
+        assert_eq!(
+            redact_command_urls("curl -s 'https://example.com/download?key=secret' -o out", UrlPrivacy::Domain),
+            "curl -s https://example.com -o out"
+        );
+    }
+}
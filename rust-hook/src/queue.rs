@@ -0,0 +1,74 @@
+use anyhow::{bail, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::state;
+
+/// 队列最多保留的通知数，App 长时间不在线时丢弃最旧的
+const MAX_QUEUED: usize = 200;
+
+/// App 不在线时发送失败的通知，等下次连接成功时补发
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PendingQueue {
+    pub entries: Vec<QueuedNotification>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedNotification {
+    /// 入队时间（毫秒）
+    pub queued_at: u64,
+    pub notification: Value,
+}
+
+pub fn push(path: &Path, notification: Value) -> Result<()> {
+    let queued_at = state::now_millis();
+    state::update(path, |queue: &mut PendingQueue| {
+        queue.entries.push(QueuedNotification { queued_at, notification });
+        let overflow = queue.entries.len().saturating_sub(MAX_QUEUED);
+        queue.entries.drain(..overflow);
+    })
+}
+
+/// 取出队列：返回 cutoff 之后入队的通知，更早的在 keep_old 时留在队列中，否则丢弃
+pub fn take(path: &Path, cutoff: Option<u64>, keep_old: bool) -> Result<Vec<QueuedNotification>> {
+    state::update(path, |queue: &mut PendingQueue| {
+        let (recent, old): (Vec<_>, Vec<_>) = std::mem::take(&mut queue.entries)
+            .into_iter()
+            .partition(|entry| cutoff.is_none_or(|cutoff| entry.queued_at >= cutoff));
+        if keep_old {
+            queue.entries = old;
+        } else if !old.is_empty() {
+            info!("Discarded {} stale queued notification(s)", old.len());
+        }
+        recent
+    })
+}
+
+/// 把没能补发的通知放回队首
+pub fn requeue(path: &Path, entries: Vec<QueuedNotification>) -> Result<()> {
+    state::update(path, |queue: &mut PendingQueue| {
+        let newer = std::mem::replace(&mut queue.entries, entries);
+        queue.entries.extend(newer);
+    })
+}
+
+/// 解析 "90s" / "30m" / "2h" / "1d"，纯数字按秒
+pub fn parse_duration(text: &str) -> Result<Duration> {
+    let text = text.trim();
+    let (number, unit) = text.split_at(text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len()));
+    let Ok(number) = number.parse::<u64>() else {
+        bail!("invalid duration: {}", text);
+    };
+    let seconds = match unit {
+        "" | "s" => number,
+        "m" => number * 60,
+        "h" => number * 3600,
+        "d" => number * 86400,
+        _ => bail!("invalid duration unit in {} (use s, m, h or d)", text),
+    };
+    Ok(Duration::from_secs(seconds))
+}
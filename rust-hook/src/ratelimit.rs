@@ -0,0 +1,78 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::state;
+
+// 令牌桶：桶满时最多连发 RATE_LIMIT_CAPACITY 条，之后每 RATE_LIMIT_WINDOW_SECS 秒补满一桶
+
+/// 桶容量
+pub const RATE_LIMIT_CAPACITY: f64 = 10.0;
+/// 补满一桶需要的时间
+pub const RATE_LIMIT_WINDOW_SECS: f64 = 10.0;
+/// 不低于该优先级的通知总是放行，且不消耗令牌
+pub const RATE_LIMIT_BYPASS_PRIORITY: u8 = 2;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Bucket {
+    tokens: f64,
+    /// 上次更新时间（毫秒）
+    updated_at: u64,
+}
+
+impl Default for Bucket {
+    fn default() -> Self {
+        Self { tokens: RATE_LIMIT_CAPACITY, updated_at: state::now_millis() }
+    }
+}
+
+/// 取一个令牌，返回 false 表示桶已空、这条通知应被丢弃
+pub fn acquire(state_path: &Path, priority: u8) -> bool {
+    let now = state::now_millis();
+
+    state::update(state_path, |bucket: &mut Bucket| {
+        let elapsed_secs = now.saturating_sub(bucket.updated_at) as f64 / 1000.0;
+        bucket.tokens = (bucket.tokens + elapsed_secs * RATE_LIMIT_CAPACITY / RATE_LIMIT_WINDOW_SECS).min(RATE_LIMIT_CAPACITY);
+        bucket.updated_at = now;
+
+        if priority >= RATE_LIMIT_BYPASS_PRIORITY {
+            return true;
+        }
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    })
+    .unwrap_or_else(|e| {
+        warn!("Failed to update rate limit state: {}", e);
+        true
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn low_priority_is_limited_after_a_burst() {
+        let dir = tempfile::tempdir().unwrap();
+        let state_path = dir.path().join("ratelimit.json");
+
+        let allowed = (0..RATE_LIMIT_CAPACITY as usize + 5).filter(|_| acquire(&state_path, 1)).count();
+        assert_eq!(allowed, RATE_LIMIT_CAPACITY as usize);
+    }
+
+    #[test]
+    fn bypassed_sends_do_not_consume_tokens() {
+        let dir = tempfile::tempdir().unwrap();
+        let state_path = dir.path().join("ratelimit.json");
+
+        for _ in 0..RATE_LIMIT_CAPACITY as usize * 2 {
+            assert!(acquire(&state_path, RATE_LIMIT_BYPASS_PRIORITY));
+        }
+        let allowed = (0..RATE_LIMIT_CAPACITY as usize + 5).filter(|_| acquire(&state_path, 1)).count();
+        assert_eq!(allowed, RATE_LIMIT_CAPACITY as usize);
+    }
+}
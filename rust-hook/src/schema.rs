@@ -0,0 +1,138 @@
+use serde_json::Value;
+
+/// NotchNoti App 接受的通知格式（JSON Schema 子集）
+pub const NOTIFICATION_SCHEMA: &str = r#"{
+  "type": "object",
+  "required": ["title", "message", "type", "priority", "metadata"],
+  "properties": {
+    "title": { "type": "string", "minLength": 1 },
+    "message": { "type": "string" },
+    "type": {
+      "type": "string",
+      "enum": [
+        "info", "success", "warning", "error", "hook", "tool_use", "progress",
+        "celebration", "reminder", "download", "upload", "security", "ai", "sync", "confirmation"
+      ]
+    },
+    "priority": { "type": "integer", "minimum": 0, "maximum": 4 },
+    "metadata": {
+      "type": "object",
+      "additionalProperties": { "type": "string" }
+    }
+  },
+  "additionalProperties": false
+}"#;
+
+/// 按 NOTIFICATION_SCHEMA 校验，返回所有违规描述
+pub fn validate_notification(value: &Value) -> Vec<String> {
+    let schema: Value = serde_json::from_str(NOTIFICATION_SCHEMA).expect("embedded schema is valid JSON");
+    let mut errors = Vec::new();
+    validate(value, &schema, "$", &mut errors);
+    errors
+}
+
+/// 支持 type / required / properties / additionalProperties / enum / minimum / maximum / minLength
+fn validate(value: &Value, schema: &Value, path: &str, errors: &mut Vec<String>) {
+    if let Some(expected) = schema.get("type").and_then(|v| v.as_str()) {
+        let matches = match expected {
+            "object" => value.is_object(),
+            "string" => value.is_string(),
+            "integer" => value.is_u64() || value.is_i64(),
+            "boolean" => value.is_boolean(),
+            _ => true,
+        };
+        if !matches {
+            errors.push(format!("{}: expected {}, got {}", path, expected, value));
+            return;
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(|v| v.as_array()) {
+        if !allowed.contains(value) {
+            errors.push(format!("{}: {} is not one of the allowed values", path, value));
+        }
+    }
+
+    if let Some(min_length) = schema.get("minLength").and_then(|v| v.as_u64()) {
+        if value.as_str().is_some_and(|s| (s.chars().count() as u64) < min_length) {
+            errors.push(format!("{}: shorter than {} characters", path, min_length));
+        }
+    }
+
+    if let Some(number) = value.as_i64() {
+        if let Some(minimum) = schema.get("minimum").and_then(|v| v.as_i64()) {
+            if number < minimum {
+                errors.push(format!("{}: {} is less than {}", path, number, minimum));
+            }
+        }
+        if let Some(maximum) = schema.get("maximum").and_then(|v| v.as_i64()) {
+            if number > maximum {
+                errors.push(format!("{}: {} is greater than {}", path, number, maximum));
+            }
+        }
+    }
+
+    let Some(object) = value.as_object() else {
+        return;
+    };
+
+    if let Some(required) = schema.get("required").and_then(|v| v.as_array()) {
+        for key in required.iter().filter_map(|k| k.as_str()) {
+            if !object.contains_key(key) {
+                errors.push(format!("{}: missing required field '{}'", path, key));
+            }
+        }
+    }
+
+    let properties = schema.get("properties").and_then(|v| v.as_object());
+    for (key, child) in object {
+        let child_path = format!("{}.{}", path, key);
+        match (properties.and_then(|p| p.get(key)), schema.get("additionalProperties")) {
+            (Some(child_schema), _) => validate(child, child_schema, &child_path, errors),
+            (None, Some(Value::Bool(false))) => errors.push(format!("{}: unexpected field", child_path)),
+            (None, Some(additional)) if additional.is_object() => validate(child, additional, &child_path, errors),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn accepts_a_well_formed_notification() {
+        let notification = json!({
+            "title": "[app] ✏️ Edit",
+            "message": "src/main.rs",
+            "type": "tool_use",
+            "priority": 2,
+            "metadata": { "tool_name": "Edit" },
+        });
+        assert!(validate_notification(&notification).is_empty());
+    }
+
+    #[test]
+    fn reports_each_violation() {
+        let notification = json!({
+            "title": "",
+            "type": "shout",
+            "priority": 7,
+            "metadata": { "lines": 3 },
+            "extra": true,
+        });
+
+        let errors = validate_notification(&notification);
+        for expected in [
+            "missing required field 'message'",
+            "$.title: shorter than 1 characters",
+            "$.type:",
+            "$.priority: 7 is greater than 4",
+            "$.metadata.lines: expected string",
+            "$.extra: unexpected field",
+        ] {
+            assert!(errors.iter().any(|error| error.contains(expected)), "{} not in {:?}", expected, errors);
+        }
+    }
+}
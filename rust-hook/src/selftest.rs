@@ -0,0 +1,102 @@
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::time::Duration;
+
+use crate::{NotchHook, NotificationType};
+
+const SAMPLE_OLD: &str = "fn main() {\n    println!(\"hello\");\n}\n";
+const SAMPLE_NEW: &str = "fn main() {\n    println!(\"hello, notch\");\n}\n";
+
+fn sample_icon(notification_type: NotificationType) -> &'static str {
+    match notification_type {
+        NotificationType::Info => "ℹ️",
+        NotificationType::Success => "✅",
+        NotificationType::Warning => "⚠️",
+        NotificationType::Error => "❌",
+        NotificationType::ToolUse => "🔧",
+        NotificationType::Progress => "⏳",
+        NotificationType::Ai => "🤖",
+        NotificationType::Sync => "🔄",
+        NotificationType::Celebration => "🎉",
+        NotificationType::Confirmation => "🛡️",
+        NotificationType::Reminder => "⏰",
+        NotificationType::Download => "🌐",
+        NotificationType::Security => "🚨",
+    }
+}
+
+fn sample_priority(notification_type: NotificationType) -> u8 {
+    match notification_type {
+        NotificationType::Error | NotificationType::Security | NotificationType::Confirmation => 3,
+        NotificationType::Warning => 2,
+        _ => 1,
+    }
+}
+
+impl NotchHook {
+    /// 每种通知类型各发一条样例，再发一条带 diff 的通知，按 socket 是否发送成功输出 PASS/FAIL
+    pub(crate) fn run_self_test(&self, delay: Duration) -> Result<()> {
+        let mut results = Vec::new();
+
+        for notification_type in NotificationType::ALL {
+            let notification = self.build_notification(
+                format!("[test] {} {}", sample_icon(notification_type), notification_type.as_str()),
+                format!("Sample {} notification from notch-hook test", notification_type.as_str()),
+                notification_type,
+                sample_priority(notification_type),
+                HashMap::new(),
+            );
+            results.push((notification_type.as_str().to_string(), self.send_via_socket(&notification)));
+            std::thread::sleep(delay);
+        }
+
+        results.push(("diff".to_string(), self.send_sample_diff()));
+
+        let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+        for (name, result) in &results {
+            match result {
+                Ok(()) => println!("PASS  {}", name),
+                Err(e) => println!("FAIL  {} ({:#})", name, e),
+            }
+        }
+        println!("{}/{} passed", results.len() - failed, results.len());
+
+        if failed > 0 {
+            bail!("{} of {} test notification(s) failed", failed, results.len());
+        }
+        Ok(())
+    }
+
+    /// 对临时文件生成一份预览 diff，附在通知上发送
+    fn send_sample_diff(&self) -> Result<()> {
+        let sample_dir = std::env::temp_dir().join(format!("notch-hook-test-{}", std::process::id()));
+        fs::create_dir_all(&sample_dir)?;
+        let file_path = sample_dir.join("main.rs");
+        fs::write(&file_path, SAMPLE_OLD)?;
+
+        let result = self
+            .generate_preview_diff(&file_path, Some(SAMPLE_OLD), Some(SAMPLE_NEW), false)
+            .and_then(|(diff_path, stats)| {
+                let mut metadata = HashMap::new();
+                metadata.insert("tool_name".to_string(), "Edit".to_string());
+                metadata.insert("event_type".to_string(), "PreToolUse".to_string());
+                metadata.insert("file_path".to_string(), file_path.to_string_lossy().to_string());
+                metadata.insert("diff_path".to_string(), diff_path.to_string_lossy().to_string());
+                metadata.insert("is_preview".to_string(), "true".to_string());
+                metadata.insert("lines_changed".to_string(), (stats.added + stats.removed).to_string());
+
+                let notification = self.build_notification(
+                    "[test] ✏️ diff".to_string(),
+                    format!("main.rs {}", self.format_stats(&stats)),
+                    NotificationType::ToolUse,
+                    1,
+                    metadata,
+                );
+                self.send_via_socket(&notification)
+            });
+
+        fs::remove_dir_all(&sample_dir).ok();
+        result
+    }
+}
@@ -0,0 +1,178 @@
+use anyhow::{bail, Result};
+use fs2::FileExt;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// 每次 hook 调用都是独立的短生命周期进程，跨调用的状态以 JSON 文件形式保存在 diff 目录下
+
+/// 读取状态文件，不存在或损坏时返回默认值
+pub fn load<T: DeserializeOwned + Default>(path: &Path) -> T {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// 先写临时文件再 rename，避免进程中断留下半个文件
+pub fn save<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let tmp_path = path.with_extension(format!("tmp.{}", std::process::id()));
+    fs::write(&tmp_path, serde_json::to_string(value)?)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// 并发的 hook 进程等待锁的最长时间，超时则放弃本次更新而不是阻塞
+const LOCK_TIMEOUT: Duration = Duration::from_millis(500);
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// 读取-修改-写回，整个过程持有 `<path>.lock` 上的 flock，避免并发调用互相覆盖
+pub fn update<T, R>(path: &Path, f: impl FnOnce(&mut T) -> R) -> Result<R>
+where
+    T: DeserializeOwned + Serialize + Default,
+{
+    let lock_file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path.with_extension("lock"))?;
+
+    let deadline = Instant::now() + LOCK_TIMEOUT;
+    while lock_file.try_lock_exclusive().is_err() {
+        if Instant::now() >= deadline {
+            bail!("timed out waiting for lock on {}", path.display());
+        }
+        std::thread::sleep(LOCK_RETRY_INTERVAL);
+    }
+
+    let mut value: T = load(path);
+    let result = f(&mut value);
+    let saved = save(path, &value);
+    lock_file.unlock().ok();
+    saved?;
+    Ok(result)
+}
+
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// 单个 Claude 会话内跨 hook 调用累积的状态
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionState {
+    /// 失败命令签名 -> 失败时间（毫秒），用于检测之后的恢复
+    pub failed_commands: HashMap<String, u64>,
+    /// 本次会话新建的文件（相对路径，按创建顺序）
+    pub created_files: Vec<String>,
+    /// 最近执行的 Bash 命令（已脱敏）
+    pub recent_commands: Vec<CommandRecord>,
+    /// JetBrains 调试开始时间（毫秒），调试期间提高通知门槛
+    pub debugging_since: Option<u64>,
+    /// 文件相对路径 -> 本会话中出现过的内容状态
+    pub file_states: HashMap<String, FileHistory>,
+    /// 收到过流式中间更新、还未结束的工具调用（notification_id）
+    pub streaming_invocations: Vec<String>,
+    /// 最近一次 Notification（Claude 等待用户响应）的时间（毫秒），下一个事件到来时计算等待时长
+    pub waiting_since: Option<u64>,
+    /// SessionStart 的时间（毫秒），SessionEnd 时计算会话总时长
+    pub started_at: Option<u64>,
+    /// 本会话完成的编辑次数及累计（估算）增删行数，用于编辑里程碑和会话总结
+    pub edit_count: u64,
+    pub edit_lines_added: u64,
+    pub edit_lines_removed: u64,
+    /// 本会话执行的 Bash 命令数和工具错误数（不随 Stop 清空），用于会话总结
+    pub command_count: u64,
+    pub error_count: u64,
+    /// 通知类型 -> 本会话成功发送次数
+    pub notification_counts: HashMap<String, u64>,
+    /// 上次 Stop 以来的工具错误，Stop 时汇总后清空
+    pub errors: Vec<ErrorRecord>,
+    /// 工具调用（tool_use_id，没有时为工具名）-> PreToolUse 的时间（毫秒），用于计算执行耗时
+    pub tool_started_at: HashMap<String, u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorRecord {
+    pub tool: String,
+    pub message: String,
+    /// 发生时间（毫秒）
+    pub at: u64,
+}
+
+/// 用于检测"改回去"的文件内容历史
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileHistory {
+    /// 内容哈希，最近出现的在最后
+    pub states: Vec<String>,
+    /// 最近一次变换是否为撤销
+    pub last_revert: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandRecord {
+    pub command: String,
+    /// 记录时间（毫秒）
+    pub at: u64,
+}
+
+/// 会话 id 用作文件名，只保留安全字符
+pub fn session_file_name(session_id: &str) -> String {
+    let safe: String = session_id
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect();
+    format!("session-{}.json", if safe.is_empty() { "default" } else { &safe })
+}
+
+/// 目录下最近更新的会话状态文件
+pub fn latest_session_file(dir: &Path) -> Option<PathBuf> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with("session-") && name.ends_with(".json")
+        })
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn concurrent_updates_are_not_lost() {
+        const N: u64 = 200;
+        let dir = tempfile::tempdir().unwrap();
+        let path = Arc::new(dir.path().join("counter.json"));
+
+        let workers: Vec<_> = (0..2)
+            .map(|_| {
+                let path = Arc::clone(&path);
+                std::thread::spawn(move || {
+                    for _ in 0..N {
+                        // 锁超时会放弃本次更新（设计如此），这里重试直到成功，只验证成功的更新不会互相覆盖
+                        while update(&path, |count: &mut u64| *count += 1).is_err() {}
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        assert_eq!(load::<u64>(&path), 2 * N);
+    }
+}
@@ -0,0 +1,80 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::time::UNIX_EPOCH;
+
+use crate::state::{self, SessionState};
+use crate::{queue, NotchHook};
+
+/// 项目下所有会话的汇总
+#[derive(Debug, Default, Serialize)]
+struct ProjectStats {
+    project: String,
+    sessions: usize,
+    /// 通知类型 -> 成功发送次数
+    notifications: BTreeMap<String, u64>,
+    edits: u64,
+    lines_added: u64,
+    lines_removed: u64,
+    commands: u64,
+    errors: u64,
+}
+
+impl NotchHook {
+    /// 汇总项目 diff 目录下所有会话状态文件；since 按会话最后活动时间（文件修改时间）过滤
+    pub(crate) fn handle_stats_command(&self, since: Option<String>, json: bool) -> Result<()> {
+        let cutoff = match since {
+            Some(since) => Some(state::now_millis().saturating_sub(queue::parse_duration(&since)?.as_millis() as u64)),
+            None => None,
+        };
+
+        let mut stats = ProjectStats { project: self.project_name.clone(), ..Default::default() };
+        let entries = fs::read_dir(&self.diff_dir).into_iter().flatten().filter_map(|entry| entry.ok());
+        for entry in entries {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if !name.starts_with("session-") || !name.ends_with(".json") {
+                continue;
+            }
+            if let Some(cutoff) = cutoff {
+                let modified = entry
+                    .metadata()
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+                    .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                    .map(|modified| modified.as_millis() as u64)
+                    .unwrap_or(0);
+                if modified < cutoff {
+                    continue;
+                }
+            }
+
+            let session: SessionState = state::load(&entry.path());
+            stats.sessions += 1;
+            for (notification_type, count) in session.notification_counts {
+                *stats.notifications.entry(notification_type).or_default() += count;
+            }
+            stats.edits += session.edit_count;
+            stats.lines_added += session.edit_lines_added;
+            stats.lines_removed += session.edit_lines_removed;
+            stats.commands += session.command_count;
+            stats.errors += session.error_count;
+        }
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&stats)?);
+            return Ok(());
+        }
+
+        println!("Project: {} ({} sessions)", stats.project, stats.sessions);
+        println!("Notifications: {}", stats.notifications.values().sum::<u64>());
+        for (notification_type, count) in &stats.notifications {
+            println!("  {:<14}{}", notification_type, count);
+        }
+        println!("Edits: {} (+{} -{})", stats.edits, stats.lines_added, stats.lines_removed);
+        println!("Commands: {}", stats.commands);
+        println!("Errors: {}", stats.errors);
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+use chrono::{Local, TimeZone};
+
+use crate::i18n;
+
+/// 本地时间 "2025-01-01 12:00:00"
+pub fn format_timestamp(millis: u64) -> String {
+    match Local.timestamp_millis_opt(millis as i64).single() {
+        Some(time) => time.format("%Y-%m-%d %H:%M:%S").to_string(),
+        None => "-".to_string(),
+    }
+}
+
+/// 相对时间: 刚刚 / 2分钟前 / 1小时前 / 3天前
+pub fn format_relative(elapsed_millis: u64) -> String {
+    let seconds = elapsed_millis / 1000;
+    match seconds {
+        0..=59 => i18n::t("just_now").to_string(),
+        60..=3599 => i18n::tf("minutes_ago", &[&(seconds / 60)]),
+        3600..=86399 => i18n::tf("hours_ago", &[&(seconds / 3600)]),
+        _ => i18n::tf("days_ago", &[&(seconds / 86400)]),
+    }
+}
+
+/// 时长: 45秒 / 4分钟 / 1小时20分钟
+pub fn format_duration(millis: u64) -> String {
+    let seconds = millis / 1000;
+    match seconds {
+        0..=59 => i18n::tf("seconds", &[&seconds]),
+        60..=3599 => i18n::tf("minutes", &[&(seconds / 60)]),
+        _ if seconds % 3600 < 60 => i18n::tf("hours", &[&(seconds / 3600)]),
+        _ => i18n::tf("hours_minutes", &[&(seconds / 3600), &(seconds % 3600 / 60)]),
+    }
+}
+
+/// 工具耗时: 850ms / 2.3s / 4分钟
+pub fn format_elapsed(millis: u64) -> String {
+    match millis {
+        0..=999 => format!("{}ms", millis),
+        1000..=59_999 => format!("{:.1}s", millis as f64 / 1000.0),
+        _ => format_duration(millis),
+    }
+}
+
+/// 列表输出用：默认 "绝对时间 (相对时间)"，absolute 时只显示绝对时间
+pub fn format_entry_time(millis: u64, now_millis: u64, absolute: bool) -> String {
+    if absolute {
+        format_timestamp(millis)
+    } else {
+        format!("{} ({})", format_timestamp(millis), format_relative(now_millis.saturating_sub(millis)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_time_at_several_deltas() {
+        assert_eq!(format_relative(0), i18n::t("just_now"));
+        assert_eq!(format_relative(59_999), i18n::t("just_now"));
+        assert_eq!(format_relative(2 * 60_000 + 30_000), i18n::tf("minutes_ago", &[&2]));
+        assert_eq!(format_relative(3_600_000), i18n::tf("hours_ago", &[&1]));
+        assert_eq!(format_relative(23 * 3_600_000 + 3_599_000), i18n::tf("hours_ago", &[&23]));
+        assert_eq!(format_relative(3 * 86_400_000), i18n::tf("days_ago", &[&3]));
+    }
+
+    #[test]
+    fn entry_time_shows_relative_unless_absolute() {
+        let at = 1_700_000_000_000;
+        let now = at + 5 * 60_000;
+        assert_eq!(format_entry_time(at, now, true), format_timestamp(at));
+        assert_eq!(format_entry_time(at, now, false), format!("{} ({})", format_timestamp(at), i18n::tf("minutes_ago", &[&5])));
+    }
+}
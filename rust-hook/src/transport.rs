@@ -0,0 +1,542 @@
+use anyhow::{bail, Context, Result};
+use log::debug;
+use serde::Deserialize;
+use serde_json::Value;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// 发往 NotchNoti 的消息格式：
+// - 默认：JSON 后跟一个换行 `\n`
+// - framed（--framed 或 NOTCH_FRAMED=1）：4 字节大端无符号长度头，后跟该长度的 JSON（UTF-8），不加换行
+//
+//     +----------------+----------------------+
+//     | len: u32 (BE)  | JSON body (len 字节) |
+//     +----------------+----------------------+
+//
+// 两种格式下 App 的回复都是一行 JSON
+
+/// 等待确认回执的最长时间
+const ACK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 通知的投递通道：NotchNoti 的 Unix Socket，或额外的 socket / webhook
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// 例: `error_transport = { socket = "/tmp/alerts.sock" }`
+    Socket(PathBuf),
+    /// 例: `error_transport = { webhook = "https://example.com/hook" }`，以 JSON POST
+    Webhook(String),
+}
+
+/// 不同版本的 App 回执方式不同：回复 JSON、直接关闭连接，或什么都不回
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AckStyle {
+    /// 不关心结果（旧版行为）：NotchNoti 主通道写入后不等待回复，其他 socket 尽力读取
+    #[default]
+    Ignore,
+    /// 对方必须回复 JSON
+    ExpectJson,
+    /// 对方收到后关闭连接即视为确认
+    ExpectClose,
+}
+
+/// 投递结果
+#[derive(Debug)]
+pub enum Ack {
+    /// 未要求回执
+    None,
+    /// 对方已关闭连接
+    Closed,
+    Json(Value),
+    HttpStatus(u16),
+}
+
+/// App 的 JSON 回复，如 `{"ok": false, "error": "rate limited"}`
+#[derive(Debug, Deserialize)]
+pub struct SocketResponse {
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+/// App 收到了通知但明确拒绝（限流、通知不合法等），重试、降级或排队都没有意义
+#[derive(Debug)]
+pub struct Rejected(pub String);
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NotchNoti rejected the notification: {}", self.0)
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// 等待回复超时，或对方没有回复就关闭了连接
+#[derive(Debug)]
+pub struct NoReply(pub Duration);
+
+impl fmt::Display for NoReply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "No reply (waited up to {:?})", self.0)
+    }
+}
+
+impl std::error::Error for NoReply {}
+
+impl Ack {
+    /// JSON 回执中明确表示未接受时返回原因（ok: false，或旧版的 success: false / status: error）
+    pub fn rejection(&self) -> Option<String> {
+        let Ack::Json(value) = self else {
+            return None;
+        };
+        if let Ok(response) = serde_json::from_value::<SocketResponse>(value.clone()) {
+            return (!response.ok).then(|| response.error.unwrap_or_else(|| value.to_string()));
+        }
+        let rejected = value.get("success").and_then(|v| v.as_bool()) == Some(false)
+            || value.get("status").and_then(|v| v.as_str()).is_some_and(|status| status == "error");
+        rejected.then(|| value.to_string())
+    }
+
+    pub fn summary(&self) -> String {
+        match self {
+            Ack::None => "none".to_string(),
+            Ack::Closed => "closed".to_string(),
+            Ack::Json(value) => value.to_string(),
+            Ack::HttpStatus(status) => format!("HTTP {}", status),
+        }
+    }
+}
+
+/// 按 AckStyle 读取 socket 回执，最多等待 ACK_TIMEOUT
+pub fn read_ack(stream: &mut UnixStream, style: AckStyle) -> Result<Ack> {
+    match style {
+        AckStyle::Ignore => {
+            let mut response = String::new();
+            stream.read_to_string(&mut response).ok();
+            // 旧版 App 不回复或回复非 JSON，都视为成功；回复了 JSON 时交给调用方检查是否被拒绝
+            Ok(serde_json::from_str(response.trim()).map(Ack::Json).unwrap_or(Ack::None))
+        }
+        AckStyle::ExpectClose => {
+            let response = read_bounded(stream, ACK_TIMEOUT).context("Connection was not closed")?;
+            if !response.trim().is_empty() {
+                debug!("Ignoring unexpected reply before close: {}", response.trim());
+            }
+            Ok(Ack::Closed)
+        }
+        AckStyle::ExpectJson => {
+            let response = read_bounded(stream, ACK_TIMEOUT)?;
+            let value = serde_json::from_str(response.trim()).with_context(|| format!("Invalid JSON ack: {:?}", response))?;
+            Ok(Ack::Json(value))
+        }
+    }
+}
+
+/// 在 timeout 内读取回复；对方回复后没有关闭连接时，超时前已收到的内容也算回复
+fn read_bounded(stream: &mut UnixStream, timeout: Duration) -> Result<String> {
+    stream.set_read_timeout(Some(timeout))?;
+
+    let mut response = String::new();
+    match stream.read_to_string(&mut response) {
+        Ok(_) => Ok(response),
+        Err(_) if !response.is_empty() => Ok(response),
+        Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => bail!("No reply within {:?}", timeout),
+        Err(e) => Err(e).context("Failed to read reply"),
+    }
+}
+
+/// 在 timeout 内读取一行回复，App 回复后可以不关闭连接；不换行直接关闭时取全部内容
+fn read_reply_line(stream: &mut UnixStream, timeout: Duration) -> Result<String> {
+    stream.set_read_timeout(Some(timeout))?;
+
+    let mut line = String::new();
+    match BufReader::new(stream).read_line(&mut line) {
+        Ok(_) if line.trim().is_empty() => Err(NoReply(timeout).into()),
+        Ok(_) => Ok(line),
+        Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Err(NoReply(timeout).into()),
+        Err(e) => Err(e).context("Failed to read reply"),
+    }
+}
+
+/// 一次 hook 调用内可复用的 NotchNoti socket 连接。每条通知以换行结尾；
+/// expect-json 时 App 回复一行后仍保持连接，后续通知沿用同一连接，回复后关闭连接（当前 App 的做法）时每条通知重新连接
+pub struct NotchSocket {
+    path: PathBuf,
+    stream: RefCell<Option<UnixStream>>,
+    /// 使用 4 字节长度头分帧，而不是换行分隔
+    pub framed: bool,
+    /// 累计的连接 + 写入耗时和发送次数，NOTCH_METRICS 记录耗时用
+    write_time: Cell<(Duration, u32)>,
+}
+
+/// 非阻塞地探测对方是否已关闭连接；读到残留数据也视为不可复用
+fn is_open(stream: &UnixStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let open = matches!((&*stream).read(&mut [0u8]), Err(e) if e.kind() == ErrorKind::WouldBlock);
+    stream.set_nonblocking(false).is_ok() && open
+}
+
+impl NotchSocket {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            stream: RefCell::new(None),
+            framed: matches!(std::env::var("NOTCH_FRAMED").as_deref(), Ok("1" | "true")),
+            write_time: Cell::new((Duration::ZERO, 0)),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 取出并清零累计的连接 + 写入耗时和发送次数
+    pub fn take_write_time(&self) -> (Duration, u32) {
+        self.write_time.take()
+    }
+
+    /// 新建连接发送后在 timeout 内等待对方回复一行（用于需要用户决定的通知），不复用连接
+    pub fn request(&self, payload: &str, timeout: Duration) -> Result<String> {
+        let mut stream = self.connect_and_write(payload)?;
+        read_reply_line(&mut stream, timeout)
+    }
+
+    /// 新建连接发送后立即返回，由调用方分段等待回复；连接失败时直接返回错误
+    pub fn start_request(&self, payload: &str) -> Result<PendingReply> {
+        let stream = self.connect_and_write(payload)?;
+        Ok(PendingReply { stream: Some(stream), received: Vec::new() })
+    }
+
+    pub fn send(&self, payload: &str, style: AckStyle) -> Result<Ack> {
+        // expect-close 以关闭连接作为回执，无法复用
+        if style == AckStyle::ExpectClose {
+            let mut stream = self.connect_and_write(payload)?;
+            return read_ack(&mut stream, style);
+        }
+
+        // ignore 不等待回复，写入即视为成功；不读回复就无法判断对方是否会关闭连接，所以也不复用
+        let started = Instant::now();
+        if style == AckStyle::Ignore {
+            self.connect_and_write(payload)?;
+            self.add_write_time(started.elapsed());
+            return Ok(Ack::None);
+        }
+
+        let reused = self.stream.borrow_mut().take().filter(is_open);
+        let stream = match reused {
+            Some(mut stream) => match self.write_message(&mut stream, payload) {
+                Ok(()) => stream,
+                Err(e) => {
+                    debug!("Reused socket connection failed ({}), reconnecting", e);
+                    self.connect_and_write(payload)?
+                }
+            },
+            None => self.connect_and_write(payload)?,
+        };
+        self.add_write_time(started.elapsed());
+
+        stream.set_read_timeout(Some(ACK_TIMEOUT))?;
+        let mut line = String::new();
+        let read = BufReader::new(&stream).read_line(&mut line);
+
+        let ack = match read {
+            Ok(_) if !line.trim().is_empty() => match serde_json::from_str(line.trim()) {
+                Ok(value) => Ack::Json(value),
+                Err(_) => bail!("Invalid JSON ack: {:?}", line),
+            },
+            Err(e) if !matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Err(e).context("Failed to read ack");
+            }
+            _ => bail!("No JSON ack within {:?}", ACK_TIMEOUT),
+        };
+
+        // 只有回复了完整一行且连接仍然打开时才复用
+        if line.ends_with('\n') && is_open(&stream) {
+            *self.stream.borrow_mut() = Some(stream);
+        }
+        Ok(ack)
+    }
+
+    fn add_write_time(&self, elapsed: Duration) {
+        let (total, sends) = self.write_time.get();
+        self.write_time.set((total + elapsed, sends + 1));
+    }
+
+    fn connect_and_write(&self, payload: &str) -> Result<UnixStream> {
+        let mut stream = UnixStream::connect(&self.path).context("Failed to connect to Unix socket")?;
+        self.write_message(&mut stream, payload)?;
+        Ok(stream)
+    }
+
+    fn write_message(&self, stream: &mut UnixStream, payload: &str) -> Result<()> {
+        write_message(stream, payload, self.framed)
+    }
+}
+
+/// 已发出、正在等待回复的请求。按时间片轮询，调用方可以在两次轮询之间检查其他回复渠道
+pub struct PendingReply {
+    /// 对方关闭连接或读取出错后为 None
+    stream: Option<UnixStream>,
+    received: Vec<u8>,
+}
+
+impl PendingReply {
+    /// 最多等待 slice，收到完整一行时返回它；对方不换行直接关闭时取已收到的全部内容。
+    /// 连接已关闭时只是等待 slice，不会再有回复
+    pub fn poll(&mut self, slice: Duration) -> Result<Option<String>> {
+        let slice = slice.max(Duration::from_millis(1));
+        let Some(stream) = &mut self.stream else {
+            std::thread::sleep(slice);
+            return Ok(None);
+        };
+
+        stream.set_read_timeout(Some(slice))?;
+        let mut chunk = [0u8; 1024];
+        match stream.read(&mut chunk) {
+            Ok(0) => {
+                self.stream = None;
+                let reply = String::from_utf8_lossy(&std::mem::take(&mut self.received)).to_string();
+                Ok(Some(reply).filter(|reply| !reply.trim().is_empty()))
+            }
+            Ok(read) => {
+                self.received.extend_from_slice(&chunk[..read]);
+                let Some(end) = self.received.iter().position(|byte| *byte == b'\n') else {
+                    return Ok(None);
+                };
+                let line: Vec<u8> = self.received.drain(..=end).collect();
+                Ok(Some(String::from_utf8_lossy(&line).to_string()))
+            }
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(None),
+            Err(e) => {
+                self.stream = None;
+                Err(e).context("Failed to read reply")
+            }
+        }
+    }
+}
+
+/// 按模块注释中的格式写入一条消息：framed 时加长度头，否则以换行结尾
+fn write_message(stream: &mut UnixStream, payload: &str, framed: bool) -> Result<()> {
+    let mut message = Vec::with_capacity(payload.len() + 4);
+    if framed {
+        let len = u32::try_from(payload.len()).context("Notification too large for a frame")?;
+        message.extend_from_slice(&len.to_be_bytes());
+        message.extend_from_slice(payload.as_bytes());
+    } else {
+        message.extend_from_slice(payload.as_bytes());
+        message.push(b'\n');
+    }
+    stream.write_all(&message).context("Failed to write to socket")
+}
+
+impl Transport {
+    /// framed 只影响 socket，与主通道使用相同的消息格式
+    pub fn send(&self, payload: &str, ack: AckStyle, framed: bool) -> Result<Ack> {
+        match self {
+            Transport::Socket(path) => {
+                let mut stream = UnixStream::connect(path).context("Failed to connect to Unix socket")?;
+                write_message(&mut stream, payload, framed)?;
+                read_ack(&mut stream, ack)
+            }
+            Transport::Webhook(url) => {
+                let response = reqwest::blocking::Client::builder()
+                    .timeout(Duration::from_secs(5))
+                    .build()?
+                    .post(url)
+                    .header("Content-Type", "application/json")
+                    .body(payload.to_string())
+                    .send()
+                    .context("Failed to post to webhook")?
+                    .error_for_status()?;
+                Ok(Ack::HttpStatus(response.status().as_u16()))
+            }
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Transport::Socket(path) => format!("socket {}", path.display()),
+            Transport::Webhook(url) => format!("webhook {}", url),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+
+    #[test]
+    fn framed_message_round_trips() {
+        let (mut writer, mut reader) = UnixStream::pair().unwrap();
+        let payload = r#"{"title":"测试","message":"framed"}"#;
+        write_message(&mut writer, payload, true).unwrap();
+        drop(writer);
+
+        let mut header = [0u8; 4];
+        reader.read_exact(&mut header).unwrap();
+        let len = u32::from_be_bytes(header) as usize;
+        assert_eq!(len, payload.len());
+
+        let mut body = Vec::new();
+        reader.read_to_end(&mut body).unwrap();
+        assert_eq!(body, payload.as_bytes());
+    }
+
+    #[test]
+    fn default_message_ends_with_newline() {
+        let (mut writer, mut reader) = UnixStream::pair().unwrap();
+        write_message(&mut writer, r#"{"title":"t"}"#, false).unwrap();
+        drop(writer);
+
+        let mut received = String::new();
+        reader.read_to_string(&mut received).unwrap();
+        assert_eq!(received, "{\"title\":\"t\"}\n");
+    }
+
+    #[test]
+    fn framed_send_is_decoded_by_listener() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notch.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut header = [0u8; 4];
+            stream.read_exact(&mut header).unwrap();
+            let mut body = vec![0u8; u32::from_be_bytes(header) as usize];
+            stream.read_exact(&mut body).unwrap();
+            stream.write_all(b"{\"ok\":true}\n").unwrap();
+            String::from_utf8(body).unwrap()
+        });
+
+        let mut socket = NotchSocket::new(path);
+        socket.framed = true;
+        let ack = socket.send(r#"{"title":"framed"}"#, AckStyle::ExpectJson).unwrap();
+
+        assert_eq!(server.join().unwrap(), r#"{"title":"framed"}"#);
+        assert!(ack.rejection().is_none());
+    }
+
+    /// 读一行消息并回复 ok
+    fn reply_ok(stream: &mut UnixStream) -> String {
+        let mut line = String::new();
+        BufReader::new(&*stream).read_line(&mut line).unwrap();
+        stream.write_all(b"{\"ok\":true}\n").unwrap();
+        line
+    }
+
+    #[test]
+    fn reuses_connection_while_peer_keeps_it_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notch.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let messages = vec![reply_ok(&mut stream), reply_ok(&mut stream)];
+            listener.set_nonblocking(true).unwrap();
+            let second_connection = listener.accept().is_ok();
+            (messages, second_connection)
+        });
+
+        let mut socket = NotchSocket::new(path);
+        socket.framed = false;
+        socket.send(r#"{"n":1}"#, AckStyle::ExpectJson).unwrap();
+        socket.send(r#"{"n":2}"#, AckStyle::ExpectJson).unwrap();
+
+        let (messages, second_connection) = server.join().unwrap();
+        assert_eq!(messages, ["{\"n\":1}\n", "{\"n\":2}\n"]);
+        assert!(!second_connection);
+    }
+
+    #[test]
+    fn ignore_does_not_wait_for_a_reply() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notch.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        // 对方读完消息后既不回复也不关闭连接
+        let server = std::thread::spawn(move || {
+            let streams: Vec<_> = (0..2).map(|_| listener.accept().unwrap().0).collect();
+            streams
+                .iter()
+                .map(|stream| {
+                    let mut line = String::new();
+                    BufReader::new(stream).read_line(&mut line).unwrap();
+                    line
+                })
+                .collect::<Vec<_>>()
+        });
+
+        let mut socket = NotchSocket::new(path);
+        socket.framed = false;
+        let started = Instant::now();
+        assert!(matches!(socket.send(r#"{"n":1}"#, AckStyle::Ignore).unwrap(), Ack::None));
+        assert!(matches!(socket.send(r#"{"n":2}"#, AckStyle::Ignore).unwrap(), Ack::None));
+
+        assert!(started.elapsed() < ACK_TIMEOUT);
+        assert_eq!(server.join().unwrap(), ["{\"n\":1}\n", "{\"n\":2}\n"]);
+    }
+
+    #[test]
+    fn reconnects_when_peer_closes_after_reply() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notch.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = std::thread::spawn(move || {
+            (0..2)
+                .map(|_| {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    reply_ok(&mut stream)
+                })
+                .collect::<Vec<_>>()
+        });
+
+        let mut socket = NotchSocket::new(path);
+        socket.framed = false;
+        socket.send(r#"{"n":1}"#, AckStyle::ExpectJson).unwrap();
+        // 给对方留出关闭连接的时间，模拟当前 App 回复后即关闭的行为
+        std::thread::sleep(Duration::from_millis(50));
+        socket.send(r#"{"n":2}"#, AckStyle::ExpectJson).unwrap();
+
+        assert_eq!(server.join().unwrap(), ["{\"n\":1}\n", "{\"n\":2}\n"]);
+    }
+
+    /// 对方写入 reply（可以为空）后关闭连接，返回本端按 style 读到的回执
+    fn ack_after_reply(reply: &str, style: AckStyle) -> Result<Ack> {
+        let (mut client, mut peer) = UnixStream::pair().unwrap();
+        peer.write_all(reply.as_bytes()).unwrap();
+        drop(peer);
+        read_ack(&mut client, style)
+    }
+
+    #[test]
+    fn ignore_accepts_anything_but_reports_json_rejections() {
+        assert!(matches!(ack_after_reply("", AckStyle::Ignore).unwrap(), Ack::None));
+        assert!(matches!(ack_after_reply("OK\n", AckStyle::Ignore).unwrap(), Ack::None));
+        let ack = ack_after_reply("{\"ok\":false,\"error\":\"rate limited\"}\n", AckStyle::Ignore).unwrap();
+        assert_eq!(ack.rejection().as_deref(), Some("rate limited"));
+    }
+
+    #[test]
+    fn expect_close_only_needs_the_connection_closed() {
+        assert!(matches!(ack_after_reply("", AckStyle::ExpectClose).unwrap(), Ack::Closed));
+        assert!(matches!(ack_after_reply("bye\n", AckStyle::ExpectClose).unwrap(), Ack::Closed));
+    }
+
+    #[test]
+    fn expect_json_requires_a_json_reply() {
+        let ack = ack_after_reply("{\"ok\":true}\n", AckStyle::ExpectJson).unwrap();
+        assert!(matches!(ack, Ack::Json(_)));
+        assert_eq!(ack.rejection(), None);
+        // 旧版 App 的 success: false 也算拒绝
+        let ack = ack_after_reply("{\"success\":false}", AckStyle::ExpectJson).unwrap();
+        assert!(ack.rejection().is_some());
+
+        assert!(ack_after_reply("OK\n", AckStyle::ExpectJson).is_err());
+        assert!(ack_after_reply("", AckStyle::ExpectJson).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use similar::{ChangeTag, DiffOp, DiffTag, TextDiff};
+use std::fmt::Write;
+
+// 词级 diff：hunk 结构与普通 unified diff 相同，成对修改的行输出为一行 "~"，
+// 行内删除的词标为 [-旧-]，新增的词标为 {+新+}
+
+pub fn render<'a>(diff: &TextDiff<'a, 'a, '_, str>, path: &str, context_radius: usize) -> String {
+    let mut out = String::new();
+    let groups = diff.grouped_ops(context_radius);
+    if groups.is_empty() {
+        return out;
+    }
+
+    let _ = writeln!(out, "--- {}\n+++ {}", path, path);
+    for group in groups {
+        write_hunk(&mut out, diff, &group);
+    }
+    out
+}
+
+fn write_hunk<'a>(out: &mut String, diff: &TextDiff<'a, 'a, '_, str>, ops: &[DiffOp]) {
+    let (Some(first), Some(last)) = (ops.first(), ops.last()) else {
+        return;
+    };
+    let old_start = first.old_range().start;
+    let new_start = first.new_range().start;
+    let _ = writeln!(
+        out,
+        "@@ -{},{} +{},{} @@",
+        old_start + 1,
+        last.old_range().end - old_start,
+        new_start + 1,
+        last.new_range().end - new_start
+    );
+
+    let old_lines = diff.old_slices();
+    let new_lines = diff.new_slices();
+    for op in ops {
+        let old = &old_lines[op.old_range()];
+        let new = &new_lines[op.new_range()];
+        match op.tag() {
+            DiffTag::Equal => old.iter().for_each(|line| push_line(out, ' ', line)),
+            DiffTag::Delete => old.iter().for_each(|line| push_line(out, '-', line)),
+            DiffTag::Insert => new.iter().for_each(|line| push_line(out, '+', line)),
+            DiffTag::Replace => {
+                // 逐行配对做词级比较，多出来的行按普通增删输出
+                let paired = old.len().min(new.len());
+                for (old_line, new_line) in old.iter().zip(new.iter()) {
+                    push_line(out, '~', &inline_words(old_line.trim_end_matches('\n'), new_line.trim_end_matches('\n')));
+                }
+                old[paired..].iter().for_each(|line| push_line(out, '-', line));
+                new[paired..].iter().for_each(|line| push_line(out, '+', line));
+            }
+        }
+    }
+}
+
+fn push_line(out: &mut String, prefix: char, line: &str) {
+    out.push(prefix);
+    out.push_str(line.trim_end_matches('\n'));
+    out.push('\n');
+}
+
+/// 相邻的同类变化合并在一个标记里，避免 [-a-][- -][-b-] 这样的碎片
+fn inline_words(old: &str, new: &str) -> String {
+    let diff = TextDiff::from_words(old, new);
+    let mut out = String::new();
+    let mut run: Option<(ChangeTag, String)> = None;
+
+    for change in diff.iter_all_changes() {
+        match &mut run {
+            Some((tag, text)) if *tag == change.tag() => text.push_str(change.value()),
+            _ => {
+                if let Some((tag, text)) = run.take() {
+                    push_run(&mut out, tag, &text);
+                }
+                run = Some((change.tag(), change.value().to_string()));
+            }
+        }
+    }
+    if let Some((tag, text)) = run {
+        push_run(&mut out, tag, &text);
+    }
+    out
+}
+
+fn push_run(out: &mut String, tag: ChangeTag, text: &str) {
+    match tag {
+        ChangeTag::Equal => out.push_str(text),
+        ChangeTag::Delete => {
+            let _ = write!(out, "[-{}-]", text);
+        }
+        ChangeTag::Insert => {
+            let _ = write!(out, "{{+{}+}}", text);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+// stdout 只能输出给机器解析的内容（diff 路径、hook 决定 JSON），诊断信息必须走 stderr
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+struct Sandbox {
+    _dir: tempfile::TempDir,
+    home: PathBuf,
+    project: PathBuf,
+    socket: PathBuf,
+}
+
+impl Sandbox {
+    fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let home = dir.path().join("home");
+        let project = dir.path().join("project");
+        std::fs::create_dir_all(&home).unwrap();
+        std::fs::create_dir_all(&project).unwrap();
+        let socket = dir.path().join("notch.sock");
+        Self { _dir: dir, home, project, socket }
+    }
+
+    /// 模拟 App：每个连接读一条通知后回复 ok 并关闭
+    fn serve(&self) {
+        let listener = UnixListener::bind(&self.socket).unwrap();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut line = String::new();
+                BufReader::new(&stream).read_line(&mut line).ok();
+                stream.write_all(b"{\"ok\":true}\n").ok();
+            }
+        });
+    }
+
+    fn run(&self, args: &[&str], stdin: &str) -> Output {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_notch-hook"))
+            .args(args)
+            .arg("--socket-path")
+            .arg(&self.socket)
+            .env("HOME", &self.home)
+            .env("CLAUDE_PROJECT_DIR", &self.project)
+            // 打开最详细的日志，确认它们都不会出现在 stdout
+            .env("NOTCH_LOG", "debug")
+            .env_remove("NOTCH_DRY_RUN")
+            .env_remove("NOTCH_FRAMED")
+            .env_remove("NOTCH_HTTP_URL")
+            .env_remove("NOTCH_HTTP_FALLBACK")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+        output
+    }
+}
+
+fn assert_stdout_is_json_or_empty(output: &Output) {
+    let stdout = String::from_utf8(output.stdout.clone()).unwrap();
+    for line in stdout.lines() {
+        assert!(serde_json::from_str::<serde_json::Value>(line).is_ok(), "non-JSON stdout line: {:?}", line);
+    }
+}
+
+#[test]
+fn diff_preview_prints_only_the_path() {
+    let sandbox = Sandbox::new();
+    let file = sandbox.project.join("main.rs");
+    std::fs::write(&file, "fn main() {}\n").unwrap();
+
+    let output = sandbox.run(
+        &["diff", "--action", "preview", "--file-path", file.to_str().unwrap(), "--old-text", "{}", "--new-text", "{ run(); }"],
+        "",
+    );
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 1, "stdout: {:?}", stdout);
+    assert!(lines[0].ends_with(".preview.diff"));
+    assert!(Path::new(lines[0]).exists());
+    // 诊断信息仍然输出，只是在 stderr
+    assert!(!output.stderr.is_empty());
+}
+
+#[test]
+fn notification_paths_keep_stdout_clean() {
+    let sandbox = Sandbox::new();
+    sandbox.serve();
+    std::fs::write(sandbox.project.join("a.txt"), "one\n").unwrap();
+
+    let events = [
+        r#"{"hook_event_name":"SessionStart","session_id":"stdout-test"}"#,
+        r#"{"hook_event_name":"PreToolUse","session_id":"stdout-test","tool_name":"Bash","tool_input":{"command":"cargo build"}}"#,
+        r#"{"hook_event_name":"PreToolUse","session_id":"stdout-test","tool_name":"Edit","tool_input":{"file_path":"a.txt","old_string":"one","new_string":"two"}}"#,
+        r#"{"hook_event_name":"PostToolUse","session_id":"stdout-test","tool_name":"Bash","tool_input":{"command":"cargo build"},"tool_response":{"stdout":"ok"}}"#,
+        r#"{"hook_event_name":"Stop","session_id":"stdout-test"}"#,
+    ];
+    for event in events {
+        let output = sandbox.run(&[], event);
+        assert!(output.stdout.is_empty(), "stdout for {}: {:?}", event, String::from_utf8_lossy(&output.stdout));
+    }
+}
+
+#[test]
+fn deny_decision_is_the_only_stdout() {
+    let sandbox = Sandbox::new();
+    sandbox.serve();
+    let config_dir = sandbox.home.join(".config").join("notch-hook");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(config_dir.join("config.toml"), "enforce_dangerous = true\n").unwrap();
+
+    let output = sandbox.run(
+        &[],
+        r#"{"hook_event_name":"PreToolUse","session_id":"stdout-test","tool_name":"Bash","tool_input":{"command":"rm -rf build"}}"#,
+    );
+
+    assert_stdout_is_json_or_empty(&output);
+    let decision: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(decision["hookSpecificOutput"]["permissionDecision"], "deny");
+}